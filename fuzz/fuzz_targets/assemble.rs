@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Diagnostics are expected for most inputs, only panics are bugs
+    let _ = spasm::fuzz_assemble(data);
+});
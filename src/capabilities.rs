@@ -0,0 +1,88 @@
+use crate::{
+    checksum::ChecksumAlgorithm, diagnostic::Warning, format::OutputFormat, isa::Mnemonic, json,
+};
+
+/**
+ * Version of the library API and of the capability data, bumped when either changes incompatibly
+ */
+pub const API_VERSION: u32 = 3;
+
+/**
+ * Every directive the assembler understands, without the leading `.`
+ */
+pub(crate) const DIRECTIVES: &[&str] = &[
+    "text", "data", "bss", "region", "endian", "entry", "global", "extern", "org", "section",
+    "size", "ascii", "asciz", "byte", "word", "space", "res", "incbin", "inctable", "checksum",
+    "include", "if", "ifdef", "ifndef", "else", "endif", "assert", "print", "info", "macro",
+    "endm", "rept", "irp", "endr",
+];
+
+/**
+ * What this build of the assembler supports, so build systems and editors can adapt to it at runtime
+ */
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub version: &'static str,
+    pub api_version: u32,
+    pub formats: Vec<&'static str>,
+    pub targets: Vec<&'static str>,
+    pub directives: Vec<&'static str>,
+    pub instructions: Vec<&'static str>,
+    pub checksums: Vec<&'static str>,
+    // Warnings that can be turned on or off with -W
+    pub warnings: Vec<&'static str>,
+    // Cargo features the library was built with
+    pub features: Vec<&'static str>,
+}
+
+pub fn capabilities() -> Capabilities {
+    let mut features = Vec::new();
+
+    if cfg!(feature = "fuzz") {
+        features.push("fuzz");
+    }
+
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        api_version: API_VERSION,
+        formats: OutputFormat::NAMES.to_vec(),
+        targets: vec!["sis16"],
+        directives: DIRECTIVES.to_vec(),
+        instructions: Mnemonic::all()
+            .iter()
+            .map(|mnemonic| mnemonic.name)
+            .collect(),
+        checksums: ChecksumAlgorithm::ALL
+            .iter()
+            .map(|algorithm| algorithm.name())
+            .collect(),
+        warnings: Warning::ALL.iter().map(|warning| warning.name()).collect(),
+        features,
+    }
+}
+
+impl Capabilities {
+    /**
+     * Render the capabilities printed by `--capabilities --json`
+     */
+    pub fn to_json(&self) -> String {
+        let list = |values: &[&str]| {
+            let values: Vec<_> = values.iter().map(|value| json::string(value)).collect();
+
+            format!("[{}]", values.join(", "))
+        };
+
+        format!(
+            "{{\n  \"version\": {},\n  \"api_version\": {},\n  \"formats\": {},\n  \"targets\": {},\n  \"directives\": {},\n  \"instructions\": {},\n  \"checksums\": {},\n  \"warnings\": {},\n  \"features\": {}\n}}\n",
+            json::string(self.version),
+            self.api_version,
+            list(&self.formats),
+            list(&self.targets),
+            list(&self.directives),
+            list(&self.instructions),
+            list(&self.checksums),
+            list(&self.warnings),
+            list(&self.features),
+        )
+    }
+}
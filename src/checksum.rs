@@ -0,0 +1,194 @@
+use crate::compile::Endian;
+
+/**
+ * Checksum written into the image for boot ROM verifiers, selected with `--checksum` or `.checksum`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    // 8-bit two's complement, the sum of every byte in the image including the checksum is 0
+    Sum,
+    // CRC-16/CCITT-FALSE (polynomial $1021, initial value $FFFF)
+    Crc16,
+    // CRC-32 as used by zip and ethernet
+    Crc32,
+}
+
+impl ChecksumAlgorithm {
+    pub const ALL: &'static [ChecksumAlgorithm] = &[
+        ChecksumAlgorithm::Sum,
+        ChecksumAlgorithm::Crc16,
+        ChecksumAlgorithm::Crc32,
+    ];
+
+    pub fn from_name(name: &str) -> Option<ChecksumAlgorithm> {
+        match name {
+            "sum" => Some(ChecksumAlgorithm::Sum),
+            "crc16" => Some(ChecksumAlgorithm::Crc16),
+            "crc32" => Some(ChecksumAlgorithm::Crc32),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sum => "sum",
+            ChecksumAlgorithm::Crc16 => "crc16",
+            ChecksumAlgorithm::Crc32 => "crc32",
+        }
+    }
+
+    /**
+     * Number of bytes the checksum takes up in the image
+     */
+    pub fn size(&self) -> u16 {
+        match self {
+            ChecksumAlgorithm::Sum => 1,
+            ChecksumAlgorithm::Crc16 => 2,
+            ChecksumAlgorithm::Crc32 => 4,
+        }
+    }
+
+    /**
+     * Checksum every byte in `data`, encoded in the byte order of the image
+     */
+    pub fn compute(&self, data: &[u8], endian: Endian) -> Vec<u8> {
+        match self {
+            ChecksumAlgorithm::Sum => {
+                vec![data
+                    .iter()
+                    .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+                    .wrapping_neg()]
+            }
+            ChecksumAlgorithm::Crc16 => endian.word_bytes(crc16(data)).to_vec(),
+            ChecksumAlgorithm::Crc32 => match endian {
+                Endian::Little => crc32(data).to_le_bytes().to_vec(),
+                Endian::Big => crc32(data).to_be_bytes().to_vec(),
+            },
+        }
+    }
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFFFFFF;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+const SHA256_ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/**
+ * SHA-256 digest of `data`
+ */
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    // Pad with a 1 bit, zeros and the length in bits up to a multiple of 64 bytes
+    let mut message = data.to_vec();
+    message.push(0x80);
+
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut schedule = [0u32; 64];
+
+        for (i, word) in block.chunks(4).enumerate() {
+            schedule[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+
+        for i in 16..64 {
+            let s0 = schedule[i - 15].rotate_right(7)
+                ^ schedule[i - 15].rotate_right(18)
+                ^ (schedule[i - 15] >> 3);
+            let s1 = schedule[i - 2].rotate_right(17)
+                ^ schedule[i - 2].rotate_right(19)
+                ^ (schedule[i - 2] >> 10);
+
+            schedule[i] = schedule[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(schedule[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(SHA256_ROUND_CONSTANTS[i])
+                .wrapping_add(schedule[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+
+        for (value, added) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+
+    digest
+}
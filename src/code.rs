@@ -0,0 +1,195 @@
+/**
+ * Longer descriptions of every diagnostic code with examples, shown by `spasm --explain <code>`
+ */
+const EXPLANATIONS: &str = include_str!("../docs/errors.md");
+
+/**
+ * A stable code for each kind of diagnostic, so a problem can be looked up and explained no matter how its
+ * message is worded
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Code {
+    InvalidToken,
+    UnterminatedLiteral,
+    InvalidNumber,
+    NumberTooLarge,
+    InvalidLabelName,
+    UnclosedComment,
+    UnexpectedToken,
+    InvalidExpression,
+    UnknownIdentifier,
+    UnknownDirective,
+    InvalidDirectiveArgument,
+    DuplicateDirective,
+    MisplacedDirective,
+    UnbalancedBlock,
+    UnknownInstruction,
+    WrongArgumentCount,
+    InvalidOperand,
+    OperandSizeMismatch,
+    ValueOutOfRange,
+    InvalidRegister,
+    UnknownLabel,
+    EmptyLabel,
+    DuplicateLabel,
+    SymbolConflict,
+    BranchOutOfRange,
+    ProgramTooLarge,
+    OverlappingCode,
+    InvalidEntryPoint,
+    UnreadableFile,
+    RecursiveInclude,
+    FileRange,
+    AssertionFailed,
+    ChecksumMismatch,
+    InvalidMacro,
+    InvalidMacroCall,
+    InvalidTable,
+    InvalidEncoding,
+    UnwritableFile,
+    TooManyErrors,
+    Redundant,
+    UnusedLabel,
+    Truncation,
+}
+
+impl Code {
+    pub const ALL: &'static [Code] = &[
+        Code::InvalidToken,
+        Code::UnterminatedLiteral,
+        Code::InvalidNumber,
+        Code::NumberTooLarge,
+        Code::InvalidLabelName,
+        Code::UnclosedComment,
+        Code::UnexpectedToken,
+        Code::InvalidExpression,
+        Code::UnknownIdentifier,
+        Code::UnknownDirective,
+        Code::InvalidDirectiveArgument,
+        Code::DuplicateDirective,
+        Code::MisplacedDirective,
+        Code::UnbalancedBlock,
+        Code::UnknownInstruction,
+        Code::WrongArgumentCount,
+        Code::InvalidOperand,
+        Code::OperandSizeMismatch,
+        Code::ValueOutOfRange,
+        Code::InvalidRegister,
+        Code::UnknownLabel,
+        Code::EmptyLabel,
+        Code::DuplicateLabel,
+        Code::SymbolConflict,
+        Code::BranchOutOfRange,
+        Code::ProgramTooLarge,
+        Code::OverlappingCode,
+        Code::InvalidEntryPoint,
+        Code::UnreadableFile,
+        Code::RecursiveInclude,
+        Code::FileRange,
+        Code::AssertionFailed,
+        Code::ChecksumMismatch,
+        Code::InvalidMacro,
+        Code::InvalidMacroCall,
+        Code::InvalidTable,
+        Code::InvalidEncoding,
+        Code::UnwritableFile,
+        Code::TooManyErrors,
+        Code::Redundant,
+        Code::UnusedLabel,
+        Code::Truncation,
+    ];
+
+    /**
+     * The code as it is printed, `E` for errors and `W` for warnings followed by four digits.
+     *
+     * Codes are never reused or renumbered, so new kinds of diagnostics get the next free number.
+     */
+    pub fn id(&self) -> &'static str {
+        match self {
+            Code::InvalidToken => "E0001",
+            Code::UnterminatedLiteral => "E0002",
+            Code::InvalidNumber => "E0003",
+            Code::NumberTooLarge => "E0004",
+            Code::InvalidLabelName => "E0005",
+            Code::UnclosedComment => "E0006",
+            Code::UnexpectedToken => "E0007",
+            Code::InvalidExpression => "E0008",
+            Code::UnknownIdentifier => "E0009",
+            Code::UnknownDirective => "E0010",
+            Code::InvalidDirectiveArgument => "E0011",
+            Code::DuplicateDirective => "E0012",
+            Code::MisplacedDirective => "E0013",
+            Code::UnbalancedBlock => "E0014",
+            Code::UnknownInstruction => "E0015",
+            Code::WrongArgumentCount => "E0016",
+            Code::InvalidOperand => "E0017",
+            Code::OperandSizeMismatch => "E0018",
+            Code::ValueOutOfRange => "E0019",
+            Code::InvalidRegister => "E0020",
+            Code::UnknownLabel => "E0021",
+            Code::EmptyLabel => "E0022",
+            Code::DuplicateLabel => "E0023",
+            Code::SymbolConflict => "E0024",
+            Code::BranchOutOfRange => "E0025",
+            Code::ProgramTooLarge => "E0026",
+            Code::OverlappingCode => "E0027",
+            Code::InvalidEntryPoint => "E0028",
+            Code::UnreadableFile => "E0029",
+            Code::RecursiveInclude => "E0030",
+            Code::FileRange => "E0031",
+            Code::AssertionFailed => "E0032",
+            Code::ChecksumMismatch => "E0033",
+            Code::InvalidMacro => "E0034",
+            Code::InvalidMacroCall => "E0035",
+            Code::InvalidTable => "E0036",
+            Code::InvalidEncoding => "E0037",
+            Code::UnwritableFile => "E0038",
+            Code::TooManyErrors => "E0039",
+            Code::Redundant => "W0001",
+            Code::UnusedLabel => "W0002",
+            Code::Truncation => "W0003",
+        }
+    }
+
+    /**
+     * Look up a code by its id, which can be written in any case (e.g. `E0015` or `e0015`)
+     */
+    pub fn from_id(id: &str) -> Option<Code> {
+        Code::ALL
+            .iter()
+            .copied()
+            .find(|code| code.id().eq_ignore_ascii_case(id))
+    }
+
+    /**
+     * A short description of the kind of problem, from the heading of its explanation
+     */
+    pub fn title(&self) -> &'static str {
+        self.section().0
+    }
+
+    /**
+     * What causes the problem and how to fix it, with examples
+     */
+    pub fn explanation(&self) -> &'static str {
+        self.section().1
+    }
+
+    /**
+     * The heading and body of the code's section in the explanations, written as `## <id>: <title>`
+     *
+     * The explanations are part of the binary, so a code without one is a bug in the assembler and panics.
+     */
+    fn section(&self) -> (&'static str, &'static str) {
+        EXPLANATIONS
+            .split("\n## ")
+            .skip(1)
+            .find_map(|section| {
+                let (heading, body) = section.split_once('\n').unwrap_or((section, ""));
+                let title = heading.strip_prefix(self.id())?.strip_prefix(": ")?;
+
+                Some((title.trim(), body.trim()))
+            })
+            .unwrap_or_else(|| panic!("no explanation for {} in docs/errors.md", self.id()))
+    }
+}
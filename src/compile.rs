@@ -0,0 +1,1016 @@
+use std::path::Path;
+
+use crate::{
+    checksum::ChecksumAlgorithm,
+    diagnostic::Diagnostic,
+    include::SourceMap,
+    isa::Mnemonic,
+    json,
+    parse::{
+        self, Branch, ConstantLabelType, IndexedAddress, Instruction, Program, Register, Spanned,
+        SubroutineItem,
+    },
+    resolve::{Layout, Section, Symbol},
+    token::Span,
+};
+
+/**
+ * Byte order of 16-bit words in the output, selected with `--endian` or `.endian`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endian {
+    pub fn from_name(name: &str) -> Option<Endian> {
+        match name {
+            "little" => Some(Endian::Little),
+            "big" => Some(Endian::Big),
+            _ => None,
+        }
+    }
+
+    pub fn word_bytes(&self, word: u16) -> [u8; 2] {
+        match self {
+            Endian::Little => word.to_le_bytes(),
+            Endian::Big => word.to_be_bytes(),
+        }
+    }
+}
+
+/**
+ * An instruction operand in the form it is encoded into the binary
+ */
+#[rustfmt::skip]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Immediate(u16),             // #$F354   ; Mode 0b0001
+    MemoryAddress(u16),         // $F354    ; Mode 0b0010
+    Register(Register),         // %eax     ; Mode 0b0011, or 0b1010 for an 8-bit register such as %ax
+    MemoryAddressIndirect(u16), // ($F354)  ; Mode 0b0100
+    RegisterIndirect(Register), // (%ebx)   ; Mode 0b0101
+    Indexed(IndexedAddress),    // $8000(%ecx) ; Mode 0b1001
+}
+
+impl Operand {
+    /**
+     * The addressing mode nibble for this operand
+     */
+    fn mode(&self) -> u8 {
+        match self {
+            Operand::Immediate(_) => 0b0001,
+            Operand::MemoryAddress(_) => 0b0010,
+            Operand::Register(register) if register.width() == 8 => 0b1010,
+            Operand::Register(_) => 0b0011,
+            Operand::MemoryAddressIndirect(_) => 0b0100,
+            Operand::RegisterIndirect(_) => 0b0101,
+            Operand::Indexed(_) => 0b1001,
+        }
+    }
+
+    /**
+     * The same kind of operand with a different data word
+     */
+    pub fn with_data(&self, value: u16) -> Operand {
+        match self {
+            Operand::Immediate(_) => Operand::Immediate(value),
+            Operand::MemoryAddress(_) => Operand::MemoryAddress(value),
+            Operand::MemoryAddressIndirect(_) => Operand::MemoryAddressIndirect(value),
+            Operand::Indexed(address) => Operand::Indexed(IndexedAddress {
+                base: value,
+                ..*address
+            }),
+            Operand::Register(_) | Operand::RegisterIndirect(_) => {
+                panic!("Register operands do not have a value to fill in")
+            }
+        }
+    }
+
+    /**
+     * The 16-bit data word for this operand
+     */
+    fn data(&self) -> u16 {
+        match self {
+            Operand::Immediate(value)
+            | Operand::MemoryAddress(value)
+            | Operand::MemoryAddressIndirect(value) => *value,
+            Operand::Register(register) | Operand::RegisterIndirect(register) => {
+                register.index() as u16
+            }
+            Operand::Indexed(address) => address.base,
+        }
+    }
+
+    /**
+     * Number of bytes this operand takes up after the addressing mode byte
+     */
+    fn size(&self) -> u16 {
+        match self {
+            Operand::Indexed(_) => 3,
+            _ => 2,
+        }
+    }
+
+    /**
+     * The bytes of this operand after the addressing mode byte
+     *
+     * Every operand has a 16-bit data word, an indexed address is followed by the index of its register.
+     */
+    fn encode(&self, endian: Endian) -> Vec<u8> {
+        let mut bytes = endian.word_bytes(self.data()).to_vec();
+
+        if let Operand::Indexed(address) = self {
+            bytes.push(address.index.index());
+        }
+
+        bytes
+    }
+}
+
+impl std::fmt::Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operand::Immediate(value) => write!(f, "#${value:04X}"),
+            Operand::MemoryAddress(address) => write!(f, "${address:04X}"),
+            Operand::Register(register) => write!(f, "%{}", register.name()),
+            Operand::MemoryAddressIndirect(address) => write!(f, "(${address:04X})"),
+            Operand::RegisterIndirect(register) => write!(f, "(%{})", register.name()),
+            Operand::Indexed(address) => {
+                write!(f, "${:04X}(%{})", address.base, address.index.name())
+            }
+        }
+    }
+}
+
+impl Register {
+    /**
+     * Index of the register as it is encoded in an operand
+     *
+     * 16-bit registers use a 4-bit index and 8-bit registers use the 5-bit index of their low byte. The two index
+     * spaces overlap, so 8-bit registers are told apart by the mode of their operand instead.
+     */
+    fn index(&self) -> u8 {
+        match self {
+            Register::EAX => 0b0000,
+            Register::EBX => 0b0001,
+            Register::ECX => 0b0010,
+            Register::EDX => 0b0011,
+            Register::EEX => 0b0100,
+            Register::PC => 0b1000,
+            Register::SP => 0b1001,
+            Register::FLAGS => 0b1011,
+            Register::AX => 0b00000,
+            Register::BX => 0b00010,
+            Register::CX => 0b00100,
+            Register::DX => 0b00110,
+            Register::EX => 0b01000,
+        }
+    }
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::nop => "nop",
+            Instruction::mov_RegisterToMemory(..)
+            | Instruction::mov_MemoryToRegister(..)
+            | Instruction::mov_ImmediateToRegister(..)
+            | Instruction::mov_RegisterToRegister(..)
+            | Instruction::mov_ImmediateToMemory16(..)
+            | Instruction::mov_RegisterToRegisterIndirect(..)
+            | Instruction::mov_RegisterIndirectToRegister(..)
+            | Instruction::mov_ImmediateToRegisterIndirect16(..)
+            | Instruction::mov_RegisterToIndexed(..)
+            | Instruction::mov_IndexedToRegister(..) => "mov",
+            Instruction::mov_ImmediateToMemory8(..)
+            | Instruction::mov_ImmediateToRegisterIndirect8(..) => "mov.b",
+            Instruction::add_RegisterToAccumulator(_)
+            | Instruction::add_ImmediateToAccumulator(_)
+            | Instruction::add_RegisterToRegister(..)
+            | Instruction::add_ImmediateToRegister(..) => "add",
+            Instruction::sub_RegisterFromAccumulator(_)
+            | Instruction::sub_ImmediateFromAccumulator(_)
+            | Instruction::sub_RegisterFromRegister(..)
+            | Instruction::sub_ImmediateFromRegister(..) => "sub",
+            Instruction::mul_Accumulator(_)
+            | Instruction::mul_AccumulatorImmediate(_)
+            | Instruction::mul_Register(..)
+            | Instruction::mul_RegisterImmediate(..) => "mul",
+            Instruction::div_Accumulator(_)
+            | Instruction::div_AccumulatorImmediate(_)
+            | Instruction::div_Register(..)
+            | Instruction::div_RegisterImmediate(..) => "div",
+            Instruction::and_Accumulator(_)
+            | Instruction::and_AccumulatorImmediate(_)
+            | Instruction::and_Register(..)
+            | Instruction::and_RegisterImmediate(..) => "and",
+            Instruction::or_Accumulator(_)
+            | Instruction::or_AccumulatorImmediate(_)
+            | Instruction::or_Register(..)
+            | Instruction::or_RegisterImmediate(..) => "or",
+            Instruction::xor_Accumulator(_)
+            | Instruction::xor_AccumulatorImmediate(_)
+            | Instruction::xor_Register(..)
+            | Instruction::xor_RegisterImmediate(..) => "xor",
+            Instruction::not_Accumulator | Instruction::not_Register(_) => "not",
+            Instruction::shl_Immediate(..) | Instruction::shl_Register(..) => "shl",
+            Instruction::shr_Immediate(..) | Instruction::shr_Register(..) => "shr",
+            Instruction::rol_Immediate(..) | Instruction::rol_Register(..) => "rol",
+            Instruction::ror_Immediate(..) | Instruction::ror_Register(..) => "ror",
+            Instruction::inc_Accumulator | Instruction::inc_Register(_) => "inc",
+            Instruction::dec_Accumulator | Instruction::dec_Register(_) => "dec",
+            Instruction::jmp_Immediate(_)
+            | Instruction::jmp_Register(_)
+            | Instruction::jmp_Memory(_)
+            | Instruction::jmp_Label(_) => "jmp",
+            Instruction::jsr_Immediate(_)
+            | Instruction::jsr_Register(_)
+            | Instruction::jsr_Memory(_)
+            | Instruction::jsr_MemoryIndirect(_)
+            | Instruction::jsr_Label(_) => "jsr",
+            Instruction::ret => "ret",
+            Instruction::cmp_Accumulator(_)
+            | Instruction::cmp_AccumulatorImmediate(_)
+            | Instruction::cmp_Register(..)
+            | Instruction::cmp_RegisterImmediate(..) => "cmp",
+            Instruction::beq(_) => "beq",
+            Instruction::bne(_) => "bne",
+            Instruction::blt(_) => "blt",
+            Instruction::bge(_) => "bge",
+            Instruction::bcs(_) => "bcs",
+            Instruction::bcc(_) => "bcc",
+            Instruction::syscall => "syscall",
+            Instruction::ssc(_) => "ssc",
+            Instruction::push_Immediate(_)
+            | Instruction::push_Memory(_)
+            | Instruction::push_Register(_) => "push",
+            Instruction::pop_Memory(_) | Instruction::pop_Register(_) => "pop",
+            Instruction::pusha => "pusha",
+            Instruction::popa => "popa",
+            Instruction::xchg_Registers(..)
+            | Instruction::xchg_RegisterMemory(..)
+            | Instruction::xchg_MemoryRegister(..) => "xchg",
+            Instruction::hlt => "hlt",
+            Instruction::brk => "brk",
+            Instruction::in_Immediate(..) | Instruction::in_Register(..) => "in",
+            Instruction::out_Immediate(..) | Instruction::out_Register(..) => "out",
+            Instruction::cli => "cli",
+            Instruction::sei => "sei",
+            Instruction::rti => "rti",
+        }
+    }
+
+    fn opcode(&self) -> u8 {
+        let mnemonic = self.mnemonic();
+
+        match Mnemonic::from_name(mnemonic) {
+            Some(mnemonic) => mnemonic.opcode,
+            None => panic!("No opcode assigned to `{mnemonic}` in the ISA spec"),
+        }
+    }
+
+    /**
+     * The operands of this instruction in encoding order (destination first)
+     */
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Instruction::nop
+            | Instruction::inc_Accumulator
+            | Instruction::dec_Accumulator
+            | Instruction::not_Accumulator
+            | Instruction::ret
+            | Instruction::syscall
+            | Instruction::pusha
+            | Instruction::popa
+            | Instruction::hlt
+            | Instruction::brk
+            | Instruction::cli
+            | Instruction::sei
+            | Instruction::rti => vec![],
+            Instruction::mov_RegisterToMemory(address, register)
+            | Instruction::xchg_MemoryRegister(address, register) => {
+                vec![
+                    Operand::MemoryAddress(*address),
+                    Operand::Register(*register),
+                ]
+            }
+            Instruction::mov_MemoryToRegister(register, address)
+            | Instruction::xchg_RegisterMemory(register, address) => {
+                vec![
+                    Operand::Register(*register),
+                    Operand::MemoryAddress(*address),
+                ]
+            }
+            Instruction::mov_ImmediateToRegister(register, immediate)
+            | Instruction::add_ImmediateToRegister(register, immediate)
+            | Instruction::sub_ImmediateFromRegister(register, immediate)
+            | Instruction::mul_RegisterImmediate(register, immediate)
+            | Instruction::div_RegisterImmediate(register, immediate)
+            | Instruction::and_RegisterImmediate(register, immediate)
+            | Instruction::or_RegisterImmediate(register, immediate)
+            | Instruction::xor_RegisterImmediate(register, immediate)
+            | Instruction::shl_Immediate(register, immediate)
+            | Instruction::shr_Immediate(register, immediate)
+            | Instruction::rol_Immediate(register, immediate)
+            | Instruction::ror_Immediate(register, immediate)
+            | Instruction::cmp_RegisterImmediate(register, immediate)
+            | Instruction::in_Immediate(register, immediate) => {
+                vec![Operand::Register(*register), Operand::Immediate(*immediate)]
+            }
+            Instruction::out_Immediate(immediate, register) => {
+                vec![Operand::Immediate(*immediate), Operand::Register(*register)]
+            }
+            Instruction::mov_RegisterToRegister(dest_register, src_register)
+            | Instruction::add_RegisterToRegister(dest_register, src_register)
+            | Instruction::sub_RegisterFromRegister(dest_register, src_register)
+            | Instruction::mul_Register(dest_register, src_register)
+            | Instruction::div_Register(dest_register, src_register)
+            | Instruction::and_Register(dest_register, src_register)
+            | Instruction::or_Register(dest_register, src_register)
+            | Instruction::xor_Register(dest_register, src_register)
+            | Instruction::shl_Register(dest_register, src_register)
+            | Instruction::shr_Register(dest_register, src_register)
+            | Instruction::rol_Register(dest_register, src_register)
+            | Instruction::ror_Register(dest_register, src_register)
+            | Instruction::cmp_Register(dest_register, src_register)
+            | Instruction::xchg_Registers(dest_register, src_register)
+            | Instruction::in_Register(dest_register, src_register)
+            | Instruction::out_Register(dest_register, src_register) => {
+                vec![
+                    Operand::Register(*dest_register),
+                    Operand::Register(*src_register),
+                ]
+            }
+            Instruction::mov_ImmediateToMemory8(address, immediate) => {
+                vec![
+                    Operand::MemoryAddress(*address),
+                    Operand::Immediate(*immediate as u16),
+                ]
+            }
+            Instruction::mov_ImmediateToMemory16(address, immediate) => {
+                vec![
+                    Operand::MemoryAddress(*address),
+                    Operand::Immediate(*immediate),
+                ]
+            }
+            Instruction::mov_RegisterToRegisterIndirect(pointer, register) => {
+                vec![
+                    Operand::RegisterIndirect(*pointer),
+                    Operand::Register(*register),
+                ]
+            }
+            Instruction::mov_RegisterIndirectToRegister(register, pointer) => {
+                vec![
+                    Operand::Register(*register),
+                    Operand::RegisterIndirect(*pointer),
+                ]
+            }
+            Instruction::mov_ImmediateToRegisterIndirect8(pointer, immediate) => {
+                vec![
+                    Operand::RegisterIndirect(*pointer),
+                    Operand::Immediate(*immediate as u16),
+                ]
+            }
+            Instruction::mov_ImmediateToRegisterIndirect16(pointer, immediate) => {
+                vec![
+                    Operand::RegisterIndirect(*pointer),
+                    Operand::Immediate(*immediate),
+                ]
+            }
+            Instruction::mov_RegisterToIndexed(address, register) => {
+                vec![Operand::Indexed(*address), Operand::Register(*register)]
+            }
+            Instruction::mov_IndexedToRegister(register, address) => {
+                vec![Operand::Register(*register), Operand::Indexed(*address)]
+            }
+            Instruction::add_RegisterToAccumulator(register)
+            | Instruction::sub_RegisterFromAccumulator(register)
+            | Instruction::mul_Accumulator(register)
+            | Instruction::div_Accumulator(register)
+            | Instruction::and_Accumulator(register)
+            | Instruction::cmp_Accumulator(register)
+            | Instruction::or_Accumulator(register)
+            | Instruction::xor_Accumulator(register)
+            | Instruction::inc_Register(register)
+            | Instruction::dec_Register(register)
+            | Instruction::not_Register(register)
+            | Instruction::jmp_Register(register)
+            | Instruction::jsr_Register(register)
+            | Instruction::push_Register(register)
+            | Instruction::pop_Register(register) => vec![Operand::Register(*register)],
+            Instruction::add_ImmediateToAccumulator(immediate)
+            | Instruction::sub_ImmediateFromAccumulator(immediate)
+            | Instruction::mul_AccumulatorImmediate(immediate)
+            | Instruction::div_AccumulatorImmediate(immediate)
+            | Instruction::and_AccumulatorImmediate(immediate)
+            | Instruction::cmp_AccumulatorImmediate(immediate)
+            | Instruction::or_AccumulatorImmediate(immediate)
+            | Instruction::xor_AccumulatorImmediate(immediate)
+            | Instruction::jmp_Immediate(immediate)
+            | Instruction::jsr_Immediate(immediate)
+            | Instruction::ssc(immediate)
+            | Instruction::push_Immediate(immediate) => vec![Operand::Immediate(*immediate)],
+            Instruction::jmp_Memory(address)
+            | Instruction::jsr_Memory(address)
+            | Instruction::push_Memory(address)
+            | Instruction::pop_Memory(address) => vec![Operand::MemoryAddress(*address)],
+            Instruction::jsr_MemoryIndirect(address) => {
+                vec![Operand::MemoryAddressIndirect(*address)]
+            }
+            // The address of the label is filled in once labels are placed
+            Instruction::jmp_Label(_)
+            | Instruction::jsr_Label(_)
+            | Instruction::beq(_)
+            | Instruction::bne(_)
+            | Instruction::blt(_)
+            | Instruction::bge(_)
+            | Instruction::bcs(_)
+            | Instruction::bcc(_) => vec![Operand::Immediate(0)],
+        }
+    }
+
+    /**
+     * Number of bytes an immediate operand of this instruction has to fit in.
+     *
+     * Byte stores and instructions on an 8-bit register only take 8-bit values.
+     */
+    pub fn immediate_size(&self) -> u16 {
+        match self {
+            Instruction::mov_ImmediateToMemory8(..)
+            | Instruction::mov_ImmediateToRegisterIndirect8(..) => 1,
+            Instruction::mov_ImmediateToRegister(register, _)
+            | Instruction::add_ImmediateToRegister(register, _)
+            | Instruction::sub_ImmediateFromRegister(register, _)
+            | Instruction::mul_RegisterImmediate(register, _)
+            | Instruction::div_RegisterImmediate(register, _)
+            | Instruction::and_RegisterImmediate(register, _)
+            | Instruction::or_RegisterImmediate(register, _)
+            | Instruction::xor_RegisterImmediate(register, _)
+            | Instruction::cmp_RegisterImmediate(register, _)
+                if register.width() == 8 =>
+            {
+                1
+            }
+            _ => 2,
+        }
+    }
+
+    /**
+     * The target of a conditional branch
+     */
+    pub fn branch(&self) -> Option<&Branch> {
+        match self {
+            Instruction::beq(branch)
+            | Instruction::bne(branch)
+            | Instruction::blt(branch)
+            | Instruction::bge(branch)
+            | Instruction::bcs(branch)
+            | Instruction::bcc(branch) => Some(branch),
+            _ => None,
+        }
+    }
+
+    pub fn branch_mut(&mut self) -> Option<&mut Branch> {
+        match self {
+            Instruction::beq(branch)
+            | Instruction::bne(branch)
+            | Instruction::blt(branch)
+            | Instruction::bge(branch)
+            | Instruction::bcs(branch)
+            | Instruction::bcc(branch) => Some(branch),
+            _ => None,
+        }
+    }
+
+    /**
+     * Number of bytes this instruction takes up once encoded
+     *
+     * Instructions without operands are a lone opcode, otherwise the opcode is followed
+     * by the addressing mode byte and the data bytes of every operand. A short branch is
+     * its opcode and an 8-bit displacement.
+     */
+    pub fn size(&self) -> u16 {
+        if self.branch().is_some_and(|branch| !branch.long) {
+            return 2;
+        }
+
+        let operands = self.operands();
+
+        match operands.len() {
+            0 => 1,
+            _ => 2 + operands.iter().map(Operand::size).sum::<u16>(),
+        }
+    }
+
+    /**
+     * Encode this instruction at `address` with its operands, whose values can be filled in once labels are placed
+     */
+    pub fn encode(&self, operands: &[Operand], address: u16, endian: Endian) -> Vec<u8> {
+        // Short branches have the high bit of the opcode set, and jump relative to the end of the branch
+        if self.branch().is_some_and(|branch| !branch.long) {
+            let displacement = operands[0].data().wrapping_sub(address.wrapping_add(2));
+
+            return vec![self.opcode() | 0x80, displacement as u8];
+        }
+
+        let mut bytes = vec![self.opcode()];
+
+        if operands.is_empty() {
+            return bytes;
+        }
+
+        // First operand goes in the high nibble, second operand in the low nibble
+        let mode = operands
+            .iter()
+            .take(2)
+            .enumerate()
+            .fold(0, |mode, (i, operand)| mode | operand.mode() << (4 - 4 * i));
+
+        bytes.push(mode);
+
+        for operand in operands {
+            bytes.extend(operand.encode(endian));
+        }
+
+        bytes
+    }
+}
+
+impl ConstantLabelType {
+    /**
+     * Number of bytes this constant takes up once encoded
+     */
+    pub fn size(&self) -> u16 {
+        match self {
+            ConstantLabelType::StringLiteral(string) => string.len() as u16,
+            ConstantLabelType::Word(_) | ConstantLabelType::DeferredWord(_) => 2,
+            ConstantLabelType::Byte(_) | ConstantLabelType::DeferredByte(_) => 1,
+            ConstantLabelType::Bytes(bytes) => bytes.len() as u16,
+            ConstantLabelType::Space { size, .. } => *size,
+            ConstantLabelType::Checksum(algorithm) => algorithm.size(),
+        }
+    }
+
+    /**
+     * Number of values in this constant, such as the characters of a string or the bytes reserved by `.space`
+     */
+    pub fn length(&self) -> u16 {
+        match self {
+            ConstantLabelType::Word(_)
+            | ConstantLabelType::DeferredWord(_)
+            | ConstantLabelType::Byte(_)
+            | ConstantLabelType::DeferredByte(_)
+            | ConstantLabelType::Checksum(_) => 1,
+            _ => self.size(),
+        }
+    }
+
+    /**
+     * The bytes of this constant, deferred values are taken in order from the values computed after layout
+     */
+    pub fn encode(&self, endian: Endian, values: &mut dyn Iterator<Item = u16>) -> Vec<u8> {
+        match self {
+            ConstantLabelType::StringLiteral(string) => string.as_bytes().to_vec(),
+            ConstantLabelType::Word(value) => endian.word_bytes(*value).to_vec(),
+            ConstantLabelType::DeferredWord(_) => {
+                endian.word_bytes(values.next().unwrap()).to_vec()
+            }
+            ConstantLabelType::Byte(value) => vec![*value],
+            ConstantLabelType::DeferredByte(_) => vec![values.next().unwrap() as u8],
+            ConstantLabelType::Bytes(bytes) => bytes.clone(),
+            ConstantLabelType::Space { size, value } => vec![*value; *size as usize],
+            // Computed later from the whole image
+            ConstantLabelType::Checksum(algorithm) => vec![0; algorithm.size() as usize],
+        }
+    }
+}
+
+impl SubroutineItem {
+    pub fn size(&self) -> u16 {
+        match self {
+            SubroutineItem::Instruction { instruction, .. } => instruction.size(),
+            SubroutineItem::Constant(constant) => constant.size(),
+        }
+    }
+}
+
+/**
+ * What a fragment of the output was assembled from
+ */
+#[derive(Debug, Clone)]
+pub enum FragmentKind {
+    Instruction {
+        mnemonic: &'static str,
+        operands: Vec<Operand>,
+    },
+    Data,
+}
+
+/**
+ * A run of output bytes that came from a single instruction or constant in the source
+ */
+#[derive(Debug, Clone)]
+pub struct Fragment {
+    pub span: Span,
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub kind: FragmentKind,
+    pub section: Section,
+}
+
+impl Fragment {
+    pub fn size(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+}
+
+/**
+ * The fully assembled program
+ */
+#[derive(Debug)]
+pub struct Assembly {
+    pub fragments: Vec<Fragment>,
+    pub symbols: Vec<Symbol>,
+    // Byte written to gaps between fragments and to padding
+    pub fill: u8,
+    // Size the image is padded to, if any
+    pub rom_size: Option<u32>,
+    pub checksum: Option<Checksum>,
+    // Address execution starts at, set with .entry
+    pub entry: Option<u16>,
+    // Labels declared with .extern, defined in another file
+    pub externs: Vec<String>,
+    // Files the program was assembled from, used to find the source of each fragment
+    pub sources: SourceMap,
+    // Text of every .print and .info directive, in source order
+    pub messages: Vec<Spanned<String>>,
+    // Warnings found while assembling, which did not stop it
+    pub warnings: Vec<Diagnostic>,
+}
+
+/**
+ * Where the checksum of the image goes and how it is calculated
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub address: u16,
+    pub endian: Endian,
+}
+
+impl Assembly {
+    /**
+     * Find what the source at a line and column of the file at `path` assembled to, used for editor hovers.
+     *
+     * The file can be the one that was assembled or one it included. Anywhere on a line that calls a macro finds
+     * the first fragment the macro assembled to.
+     */
+    pub fn fragment_at(&self, path: &Path, line_number: u32, column: u32) -> Option<&Fragment> {
+        let lines = self.sources.expanded_lines(path, line_number);
+
+        self.fragments.iter().find(|fragment| {
+            let span = fragment.span;
+
+            // Columns of expanded lines are in the macro body, not the line it was called from
+            lines.contains(&span.line_number)
+                && (self.sources.is_expanded(span.line_number)
+                    || (span.column_start <= column && column <= span.column_end))
+        })
+    }
+
+    /**
+     * All the fragments that were assembled from a single source line
+     */
+    pub fn fragments_on_line(&self, line_number: u32) -> impl Iterator<Item = &Fragment> {
+        self.fragments
+            .iter()
+            .filter(move |fragment| fragment.span.line_number == line_number)
+    }
+
+    /**
+     * The lowest address anything was assembled to
+     */
+    pub fn origin(&self) -> u16 {
+        self.fragments
+            .iter()
+            .map(|fragment| fragment.address)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /**
+     * Split the image into runs of contiguous bytes from the same section, in address order
+     */
+    pub fn section_runs(&self) -> Vec<(Section, u16, Vec<u8>)> {
+        let mut fragments: Vec<_> = self.fragments.iter().collect();
+        fragments.sort_by_key(|fragment| fragment.address);
+
+        let mut runs: Vec<(Section, u16, Vec<u8>)> = Vec::new();
+
+        for fragment in fragments {
+            match runs.last_mut() {
+                Some((run_section, address, bytes))
+                    if *run_section == fragment.section
+                        && *address as usize + bytes.len() == fragment.address as usize =>
+                {
+                    bytes.extend(&fragment.bytes)
+                }
+                _ => runs.push((
+                    fragment.section.clone(),
+                    fragment.address,
+                    fragment.bytes.clone(),
+                )),
+            }
+        }
+
+        runs
+    }
+
+    /**
+     * Render the symbol table loaded by debuggers and emulators.
+     *
+     * Every label gets one `ADDR S NAME` line where `S` is `T` for text and named section, `D` for data and `B` for bss labels,
+     * sorted by address and then by name so the output is stable between runs. Labels exported with
+     * `.global` have ` global` after the name, and `.extern` labels follow as `---- U NAME` lines.
+     */
+    pub fn symbol_file(&self) -> String {
+        let mut symbols: Vec<_> = self.symbols.iter().collect();
+
+        symbols.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+
+        symbols
+            .iter()
+            .map(|symbol| {
+                let section = match symbol.section {
+                    Section::Text | Section::Named(_) => 'T',
+                    Section::Data => 'D',
+                    Section::Bss => 'B',
+                };
+
+                let visibility = if symbol.global { " global" } else { "" };
+
+                format!(
+                    "{:04X} {section} {}{visibility}\n",
+                    symbol.address, symbol.name
+                )
+            })
+            .chain(self.externs.iter().map(|name| format!("---- U {name}\n")))
+            .collect()
+    }
+
+    /**
+     * Render the JSON source map written by `--debug`, which lets emulators step through the source.
+     *
+     * Every fragment maps the address range `[start, end)` to the 1-based line and columns it came from.
+     * Fragments assembled from a macro body point at the line in the macro, and name the macro and the line that called it.
+     */
+    pub fn source_map(&self, file_name: &str) -> String {
+        let mappings: Vec<_> = self
+            .fragments
+            .iter()
+            .map(|fragment| {
+                // Fragments from included files point at the file they are in
+                let (file_name, line_number) = match self.sources.locate(fragment.span.line_number) {
+                    Some((file, line_number)) => (file.path.to_string_lossy().into_owned(), line_number),
+                    None => (file_name.to_owned(), fragment.span.line_number),
+                };
+
+                // Fragments from a macro body also record the call they were expanded from
+                let expansion = match self.sources.macro_call(fragment.span.line_number) {
+                    Some((name, file, line_number)) => format!(
+                        ", \"macro\": {{ \"name\": {}, \"file\": {}, \"line\": {} }}",
+                        json::string(name),
+                        json::string(&file.path.to_string_lossy()),
+                        line_number + 1
+                    ),
+                    None => String::new(),
+                };
+
+                format!(
+                    "    {{ \"start\": {}, \"end\": {}, \"file\": {}, \"line\": {}, \"column\": {}, \"end_column\": {}{expansion} }}",
+                    fragment.address,
+                    fragment.address as u32 + fragment.size() as u32,
+                    json::string(&file_name),
+                    line_number + 1,
+                    fragment.span.column_start + 1,
+                    fragment.span.column_end + 1,
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\n  \"version\": 1,\n  \"mappings\": [\n{}\n  ]\n}}\n",
+            mappings.join(",\n")
+        )
+    }
+
+    /**
+     * Flatten all fragments into the binary image, starting at the origin
+     *
+     * Gaps are filled with the fill byte, and so is the rest of the ROM if a size was given.
+     */
+    pub fn image(&self) -> Vec<u8> {
+        let origin = self.origin() as usize;
+        let mut image = Vec::new();
+
+        for fragment in &self.fragments {
+            let start = fragment.address as usize - origin;
+            let end = start + fragment.bytes.len();
+
+            if image.len() < end {
+                image.resize(end, self.fill);
+            }
+
+            image[start..end].copy_from_slice(&fragment.bytes);
+        }
+
+        if let Some(rom_size) = self.rom_size {
+            image.resize(rom_size as usize, self.fill);
+        }
+
+        // The checksum covers every byte of the image except itself
+        if let Some(checksum) = &self.checksum {
+            let start = checksum.address as usize - origin;
+            let end = start + checksum.algorithm.size() as usize;
+
+            if image.len() < end {
+                image.resize(end, self.fill);
+            }
+
+            let data: Vec<_> = image[..start]
+                .iter()
+                .chain(&image[end..])
+                .copied()
+                .collect();
+
+            image[start..end].copy_from_slice(&checksum.algorithm.compute(&data, checksum.endian));
+        }
+
+        image
+    }
+
+    /**
+     * Copy the final checksum into the fragment that reserved space for it, so listings show its value
+     */
+    pub fn patch_checksum(&mut self) {
+        let Some(checksum) = self.checksum else {
+            return;
+        };
+
+        let image = self.image();
+        let start = (checksum.address - self.origin()) as usize;
+
+        if let Some(fragment) = self
+            .fragments
+            .iter_mut()
+            .find(|fragment| fragment.address == checksum.address)
+        {
+            let end = start + fragment.bytes.len();
+
+            fragment.bytes.copy_from_slice(&image[start..end]);
+        }
+    }
+}
+
+/**
+ * Whether every register operand is encoded differently, so a write to an 8-bit register can never land in a 16-bit
+ * register or in `%pc`, `%sp` or `%flags`
+ */
+fn register_encodings_are_distinct() -> bool {
+    let encodings: Vec<_> = Register::all()
+        .iter()
+        .map(|register| {
+            let operand = Operand::Register(*register);
+
+            (operand.mode(), operand.data())
+        })
+        .collect();
+
+    encodings
+        .iter()
+        .enumerate()
+        .all(|(index, encoding)| !encodings[..index].contains(encoding))
+}
+
+/**
+ * Encode every instruction and constant in the program at the addresses from the layout.
+ *
+ * `values` are the deferred values from `resolve::evaluate_values`, in the order they appear in the program.
+ */
+pub fn compile_program(
+    program: &Program,
+    layout: Layout,
+    values: Vec<u16>,
+    endian: Endian,
+) -> Assembly {
+    debug_assert!(
+        register_encodings_are_distinct(),
+        "Two registers share an encoding"
+    );
+
+    let mut fragments = Vec::new();
+    let mut values = values.into_iter();
+
+    let mut checksum = None;
+
+    // Symbols are in the same order as the labels, text labels first and then those of named sections
+    let mut symbols = layout.symbols.iter();
+
+    for text in program
+        .text
+        .iter()
+        .chain(program.sections.iter().map(|section| &section.text))
+    {
+        for label in &text.labels {
+            let symbol = symbols.next().unwrap();
+            let mut address = symbol.address;
+
+            for (index, item) in label.contents.iter().enumerate() {
+                if let Some(origin) = parse::continuation_at(&label.continuations, index) {
+                    address = origin.node;
+                }
+
+                let (bytes, kind) = match &item.node {
+                    SubroutineItem::Instruction {
+                        instruction,
+                        deferred,
+                    } => {
+                        let mut operands = instruction.operands();
+
+                        for operand in deferred {
+                            operands[operand.index] =
+                                operands[operand.index].with_data(values.next().unwrap());
+                        }
+
+                        (
+                            instruction.encode(&operands, address, endian),
+                            FragmentKind::Instruction {
+                                mnemonic: instruction.mnemonic(),
+                                operands,
+                            },
+                        )
+                    }
+                    SubroutineItem::Constant(constant) => {
+                        (constant.encode(endian, &mut values), FragmentKind::Data)
+                    }
+                };
+
+                fragments.push(Fragment {
+                    span: item.span,
+                    address,
+                    kind,
+                    bytes,
+                    section: symbol.section.clone(),
+                });
+
+                address = address.wrapping_add(item.node.size());
+            }
+        }
+    }
+
+    if let Some(data) = &program.data {
+        for label in &data.labels {
+            let mut address = symbols.next().unwrap().address;
+
+            for (index, constant) in label.constants.iter().enumerate() {
+                if let Some(origin) = parse::continuation_at(&label.continuations, index) {
+                    address = origin.node;
+                }
+
+                if let ConstantLabelType::Checksum(algorithm) = constant.node {
+                    checksum = Some(Checksum {
+                        algorithm,
+                        address,
+                        endian,
+                    });
+                }
+
+                fragments.push(Fragment {
+                    span: constant.span,
+                    address,
+                    bytes: constant.node.encode(endian, &mut values),
+                    kind: FragmentKind::Data,
+                    section: Section::Data,
+                });
+
+                address = address.wrapping_add(constant.node.size());
+            }
+        }
+    }
+
+    Assembly {
+        fragments,
+        symbols: layout.symbols,
+        fill: 0,
+        rom_size: None,
+        checksum,
+        entry: layout.entry,
+        externs: layout.externs,
+        sources: SourceMap::default(),
+        messages: Vec::new(),
+        warnings: Vec::new(),
+    }
+}
@@ -0,0 +1,238 @@
+use std::{collections::VecDeque, path::Path};
+
+use crate::{
+    diagnostic::{Diagnostic, Diagnostics, WarningOptions},
+    token::{self, Span, Token, TokenType},
+    DEFAULT_MAX_ERRORS,
+};
+
+/**
+ * What a piece of source text is, either a token the parser uses or trivia that it skips
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxKind {
+    Token(TokenType),
+    Whitespace,
+    Comment,
+    // The line ending, `\n` or `\r\n`
+    Newline,
+}
+
+impl SyntaxKind {
+    pub fn is_trivia(&self) -> bool {
+        !matches!(self, SyntaxKind::Token(_))
+    }
+}
+
+/**
+ * A piece of source text along with where it was found
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub kind: SyntaxKind,
+    pub text: String,
+    pub span: Span,
+}
+
+/**
+ * What a line of source declares, from its first non-trivia token
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    // Only whitespace and comments
+    Blank,
+    Label,
+    Directive,
+    Instruction,
+}
+
+/**
+ * A single line of source, including its trivia and line ending
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxLine {
+    pub kind: LineKind,
+    pub tokens: Vec<SyntaxToken>,
+}
+
+impl SyntaxLine {
+    /**
+     * The tokens the parser sees on this line, without whitespace and comments
+     */
+    pub fn significant_tokens(&self) -> impl Iterator<Item = &SyntaxToken> {
+        self.tokens.iter().filter(|token| !token.kind.is_trivia())
+    }
+}
+
+/**
+ * Lossless syntax tree of a source file.
+ *
+ * Unlike `Program`, nothing from the source is dropped: every character belongs to exactly one
+ * token, so tools such as formatters and refactorings can edit tokens and write the file back out
+ * with `text()` without disturbing comments or layout.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxTree {
+    pub lines: Vec<SyntaxLine>,
+}
+
+impl SyntaxTree {
+    /**
+     * Build the syntax tree of a source file.
+     *
+     * `path` is only used when reporting errors. Source that cannot be lexed returns its errors instead.
+     */
+    pub fn parse(path: &Path, source: &str) -> Result<SyntaxTree, Vec<Diagnostic>> {
+        let lines: Vec<_> = source.lines().map(|string| string.to_owned()).collect();
+
+        let mut diagnostics = Diagnostics::new(DEFAULT_MAX_ERRORS, WarningOptions::default());
+
+        let mut tokens = token::tokenize_lines(path, &lines, &mut diagnostics)
+            .map_err(|error| diagnostics.fail(*error))?;
+        diagnostics.stop_on_errors()?;
+
+        let syntax_lines = source
+            .split_inclusive('\n')
+            .enumerate()
+            .map(|(line_number, raw_line)| {
+                let line_number = line_number as u32;
+
+                let mut line_tokens = VecDeque::new();
+
+                while let Some(token) =
+                    tokens.pop_front_if(|token| token.line_number == line_number)
+                {
+                    line_tokens.push_back(token);
+                }
+
+                build_line(line_number, raw_line, line_tokens)
+            })
+            .collect();
+
+        Ok(SyntaxTree {
+            lines: syntax_lines,
+        })
+    }
+
+    /**
+     * Every token in the tree in source order, including trivia
+     */
+    pub fn tokens(&self) -> impl Iterator<Item = &SyntaxToken> {
+        self.lines.iter().flat_map(|line| &line.tokens)
+    }
+
+    pub fn tokens_mut(&mut self) -> impl Iterator<Item = &mut SyntaxToken> {
+        self.lines.iter_mut().flat_map(|line| &mut line.tokens)
+    }
+
+    /**
+     * The source text of the tree, identical to the source it was parsed from unless tokens were edited
+     */
+    pub fn text(&self) -> String {
+        self.tokens().map(|token| token.text.as_str()).collect()
+    }
+}
+
+/**
+ * Fill the gaps between the tokens of a line with whitespace, comment and newline trivia
+ */
+fn build_line(line_number: u32, raw_line: &str, line_tokens: VecDeque<Token>) -> SyntaxLine {
+    let content = raw_line.trim_end_matches(['\n', '\r']);
+    let chars: Vec<_> = content.chars().collect();
+
+    let kind = match line_tokens.front().map(|token| &token.token_type) {
+        None => LineKind::Blank,
+        Some(TokenType::Label(_)) => LineKind::Label,
+        Some(TokenType::Directive(_)) => LineKind::Directive,
+        Some(_) => LineKind::Instruction,
+    };
+
+    let text = |start: u32, end: u32| {
+        chars[start as usize..end as usize]
+            .iter()
+            .collect::<String>()
+    };
+
+    let span = |column_start: u32, column_end: u32| Span {
+        line_number,
+        column_start,
+        column_end,
+    };
+
+    let mut syntax_tokens = Vec::new();
+    let mut column = 0;
+
+    for token in line_tokens {
+        // The value of a token is its exact source text
+        let column_end = token.column_start + token.value.chars().count() as u32;
+
+        // The gap before a token is whitespace, unless it holds a block comment
+        let gap: Vec<_> = chars[column as usize..token.column_start as usize].to_vec();
+        let leading = gap.iter().take_while(|c| c.is_whitespace()).count() as u32;
+        let trailing = gap.iter().rev().take_while(|c| c.is_whitespace()).count() as u32;
+
+        let comment_start = column + leading;
+        let comment_end = (token.column_start - trailing).max(comment_start);
+
+        for (kind, start, end) in [
+            (SyntaxKind::Whitespace, column, comment_start),
+            (SyntaxKind::Comment, comment_start, comment_end),
+            (SyntaxKind::Whitespace, comment_end, token.column_start),
+        ] {
+            if end > start {
+                syntax_tokens.push(SyntaxToken {
+                    kind,
+                    text: text(start, end),
+                    span: span(start, end),
+                });
+            }
+        }
+
+        column = column_end;
+
+        syntax_tokens.push(SyntaxToken {
+            text: token.value,
+            span: span(token.column_start, column_end),
+            kind: SyntaxKind::Token(token.token_type),
+        });
+    }
+
+    // Whatever follows the last token is whitespace and then possibly a comment
+    let rest = text(column, chars.len() as u32);
+    let whitespace = rest.len() - rest.trim_start().len();
+
+    if whitespace > 0 {
+        let end = column + rest[..whitespace].chars().count() as u32;
+
+        syntax_tokens.push(SyntaxToken {
+            kind: SyntaxKind::Whitespace,
+            text: rest[..whitespace].to_owned(),
+            span: span(column, end),
+        });
+
+        column = end;
+    }
+
+    if whitespace < rest.len() {
+        syntax_tokens.push(SyntaxToken {
+            kind: SyntaxKind::Comment,
+            text: rest[whitespace..].to_owned(),
+            span: span(column, chars.len() as u32),
+        });
+    }
+
+    let newline = &raw_line[content.len()..];
+
+    if !newline.is_empty() {
+        syntax_tokens.push(SyntaxToken {
+            kind: SyntaxKind::Newline,
+            text: newline.to_owned(),
+            span: span(chars.len() as u32, chars.len() as u32),
+        });
+    }
+
+    SyntaxLine {
+        kind,
+        tokens: syntax_tokens,
+    }
+}
@@ -0,0 +1,770 @@
+use ansi_term::{Colour, Style};
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    env, fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+};
+use unicode_width::UnicodeWidthChar;
+
+use crate::{code::Code, include, token::Span, DEFAULT_TAB_WIDTH};
+
+/**
+ * The result of a part of the assembler, which stops at the first error it cannot carry on from
+ */
+pub type Result<T> = std::result::Result<T, Box<Diagnostic>>;
+
+thread_local! {
+    // Tab width of the file being assembled, so carets line up with the source shown in errors
+    pub(crate) static TAB_WIDTH: Cell<u32> = const { Cell::new(DEFAULT_TAB_WIDTH) };
+
+    // Whether messages are coloured, chosen with --color
+    static COLOR_CHOICE: Cell<ColorChoice> = const { Cell::new(ColorChoice::Auto) };
+
+    // How the paths of locations are shown, chosen with --diagnostic-paths
+    static DIAGNOSTIC_PATHS: Cell<DiagnosticPaths> = const { Cell::new(DiagnosticPaths::Absolute) };
+}
+
+/**
+ * How serious a diagnostic is, which decides its label and colour
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn label(&self) -> &'static str {
+        match self {
+            Severity::Error => "ERROR",
+            Severity::Warning => "WARNING",
+        }
+    }
+
+    fn colour(&self) -> Colour {
+        match self {
+            Severity::Error => Colour::Red,
+            Severity::Warning => Colour::Yellow,
+        }
+    }
+}
+
+/**
+ * A kind of warning, which can be turned off with `-Wno-<name>` and back on with `-W<name>`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Warning {
+    // An instruction that leaves everything as it was, such as `mov %eax, %eax`
+    Redundant,
+    // A label nothing refers to, such as a table that is no longer read
+    UnusedLabel,
+    // A negative value stored as the unsigned value with the same bits, such as `.byte -1` as $FF
+    Truncation,
+}
+
+impl Warning {
+    pub const ALL: &'static [Warning] = &[
+        Warning::Redundant,
+        Warning::UnusedLabel,
+        Warning::Truncation,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Warning::Redundant => "redundant",
+            Warning::UnusedLabel => "unused-label",
+            Warning::Truncation => "truncation",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Warning> {
+        match name {
+            // The shorter name this warning had at first
+            "unused" => Some(Warning::UnusedLabel),
+            _ => Warning::ALL
+                .iter()
+                .copied()
+                .find(|warning| warning.name() == name),
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        match self {
+            Warning::Redundant => Code::Redundant,
+            Warning::UnusedLabel => Code::UnusedLabel,
+            Warning::Truncation => Code::Truncation,
+        }
+    }
+}
+
+/**
+ * How the command line tool prints diagnostics, selected with `--message-format`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageFormat {
+    // Coloured messages with the source they point at
+    #[default]
+    Human,
+    // A SARIF log on stdout, for code scanning dashboards
+    Sarif,
+}
+
+impl MessageFormat {
+    pub const NAMES: &'static [&'static str] = &["human", "sarif"];
+
+    pub fn from_name(name: &str) -> Option<MessageFormat> {
+        match name {
+            "human" => Some(MessageFormat::Human),
+            "sarif" => Some(MessageFormat::Sarif),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * When the command line tool colours its messages, selected with `--color`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    // Colour messages written to a terminal, unless the NO_COLOR environment variable is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub const NAMES: &'static [&'static str] = &["auto", "always", "never"];
+
+    pub fn from_name(name: &str) -> Option<ColorChoice> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Choose when messages printed from now on are coloured
+ */
+pub fn set_color_choice(choice: ColorChoice) {
+    COLOR_CHOICE.set(choice);
+}
+
+/**
+ * How the path of the file a message is about is shown, selected with `--diagnostic-paths`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiagnosticPaths {
+    #[default]
+    Absolute,
+    // Relative to the working directory, which keeps CI logs short and the same on every machine
+    Relative,
+}
+
+impl DiagnosticPaths {
+    pub const NAMES: &'static [&'static str] = &["absolute", "relative"];
+
+    pub fn from_name(name: &str) -> Option<DiagnosticPaths> {
+        match name {
+            "absolute" => Some(DiagnosticPaths::Absolute),
+            "relative" => Some(DiagnosticPaths::Relative),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Choose how paths are shown in messages printed from now on
+ */
+pub fn set_diagnostic_paths(paths: DiagnosticPaths) {
+    DIAGNOSTIC_PATHS.set(paths);
+}
+
+/**
+ * `style` if messages written to `stream` are coloured, otherwise a plain style that prints no escape codes
+ */
+pub(crate) fn style_for(stream: &impl IsTerminal, style: impl Into<Style>) -> Style {
+    let coloured = match COLOR_CHOICE.get() {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        // See https://no-color.org, an empty NO_COLOR does not count
+        ColorChoice::Auto => {
+            env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && stream.is_terminal()
+        }
+    };
+
+    if coloured {
+        style.into()
+    } else {
+        Style::new()
+    }
+}
+
+/**
+ * The style of a message printed to stderr, where diagnostics go
+ */
+pub(crate) fn style(style: impl Into<Style>) -> Style {
+    style_for(&io::stderr(), style)
+}
+
+/**
+ * Which warnings are reported and whether they fail assembly
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WarningOptions {
+    pub enabled: HashSet<Warning>,
+    // Report warnings as errors, so CI builds fail on them (-Werror)
+    pub as_errors: bool,
+}
+
+impl Default for WarningOptions {
+    fn default() -> WarningOptions {
+        WarningOptions {
+            enabled: Warning::ALL.iter().copied().collect(),
+            as_errors: false,
+        }
+    }
+}
+
+/**
+ * A problem found while assembling, with everything needed to show it once the sources are gone
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+    // Shown below the snippet, starting with how to fix the problem and followed by where the line was included from
+    pub notes: Vec<String>,
+    // Suggestions for fixing the problem, shown after the notes
+    pub help: Vec<String>,
+    pub location: Option<Location>,
+    // Other places the problem involves, such as the first declaration of something declared twice
+    pub labels: Vec<Label>,
+    // Edits that fix the problem, which `--fix` applies
+    pub fixes: Vec<Fix>,
+    // The kind of warning this is, even if -Werror made it an error
+    pub warning: Option<Warning>,
+}
+
+/**
+ * Another span a diagnostic underlines, with a message explaining what is there
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub message: String,
+    pub location: Location,
+}
+
+/**
+ * A replacement in a file that fixes a diagnostic, such as removing a stray `#` or adding a missing `)`
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    // What the fix does, which is also shown as help
+    pub message: String,
+    pub path: PathBuf,
+    // The characters to replace in the file, an empty span inserts before its start
+    pub span: Span,
+    pub replacement: String,
+}
+
+/**
+ * Where in a file a diagnostic points
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    // The file the problem is in, which is an included file rather than the one being assembled if it came from one
+    pub path: PathBuf,
+    // Line and columns in that file
+    pub span: Span,
+    // The line with the problem and up to two lines before it
+    pub context: Vec<String>,
+}
+
+impl Diagnostic {
+    /**
+     * A diagnostic that is not about a place in the source, such as a bad argument
+     */
+    pub fn new(severity: Severity, code: Code, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message: message.to_owned(),
+            notes: Vec::new(),
+            help: Vec::new(),
+            location: None,
+            labels: Vec::new(),
+            fixes: Vec::new(),
+            warning: None,
+        }
+    }
+
+    /**
+     * A diagnostic that covers `span` in the expanded source, located in the file it was written in
+     */
+    pub(crate) fn at(
+        severity: Severity,
+        code: Code,
+        message: &str,
+        note: Option<&str>,
+        path: &Path,
+        lines: &[String],
+        span: Span,
+    ) -> Diagnostic {
+        let (location, origin_notes) = Location::find(path, lines, span);
+
+        let notes = note
+            .map(|note| note.to_owned())
+            .into_iter()
+            .chain(origin_notes)
+            .collect();
+
+        Diagnostic {
+            severity,
+            code,
+            message: message.to_owned(),
+            notes,
+            help: Vec::new(),
+            location: Some(location),
+            labels: Vec::new(),
+            fixes: Vec::new(),
+            warning: None,
+        }
+    }
+
+    /**
+     * Also underline `span` in the expanded source, explained by `message`
+     */
+    pub(crate) fn with_label(
+        mut self,
+        message: &str,
+        path: &Path,
+        lines: &[String],
+        span: Span,
+    ) -> Diagnostic {
+        let (location, _) = Location::find(path, lines, span);
+
+        self.labels.push(Label {
+            message: message.to_owned(),
+            location,
+        });
+
+        self
+    }
+
+    /**
+     * Suggest replacing `span` in the expanded source with `replacement`, described by `message`
+     */
+    pub(crate) fn with_fix(
+        mut self,
+        message: &str,
+        path: &Path,
+        lines: &[String],
+        span: Span,
+        replacement: &str,
+    ) -> Diagnostic {
+        // Columns in a line expanded from a macro do not match the file, so the fix is only described there
+        let expanded =
+            include::locate_error(lines, span.line_number).is_some_and(|origin| origin.expanded);
+
+        if !expanded {
+            let (location, _) = Location::find(path, lines, span);
+
+            self.fixes.push(Fix {
+                message: message.to_owned(),
+                path: location.path,
+                span: location.span,
+                replacement: replacement.to_owned(),
+            });
+        }
+
+        self.with_help(message)
+    }
+
+    pub fn with_note(mut self, note: &str) -> Diagnostic {
+        self.notes.push(note.to_owned());
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Diagnostic {
+        self.help.push(help.to_owned());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /**
+     * Print the diagnostic with the source lines leading up to it and the span underlined
+     */
+    pub fn print(&self) {
+        let colour = self.severity.colour();
+
+        // Print the message, followed by the option that turns it off for warnings
+        eprint!(
+            "{} {}",
+            style(colour.bold()).paint(format!("[{} {}]", self.severity.label(), self.code.id())),
+            style(colour).paint(&self.message)
+        );
+
+        match self.warning {
+            Some(warning) => eprintln!(
+                " {}",
+                style(Colour::Fixed(246)).paint(format!("[-W{}]", warning.name()))
+            ),
+            None => eprintln!(),
+        }
+
+        if let Some(location) = &self.location {
+            print_location(location, colour, '^', "here");
+        }
+
+        for label in &self.labels {
+            print_location(&label.location, Colour::Blue, '-', &label.message);
+        }
+
+        for note in &self.notes {
+            eprintln!("{} {note}", style(Colour::Cyan.bold()).paint("[NOTE]"));
+        }
+
+        for help in &self.help {
+            eprintln!("{} {help}", style(Colour::Green.bold()).paint("[HELP]"));
+        }
+    }
+
+    /**
+     * Return the diagnostic as the error of the part of the assembler that found it
+     */
+    pub(crate) fn raise<T>(self) -> Result<T> {
+        Err(Box::new(self))
+    }
+}
+
+/**
+ * Warnings reported and errors recovered from while assembling a file, returned together once assembly stops
+ */
+pub(crate) struct Diagnostics {
+    recorded: Vec<Diagnostic>,
+    // Errors recorded before assembly stops, set with --max-errors (0 for no limit)
+    max_errors: u32,
+    // Warnings turned on or off with -W options
+    warnings: WarningOptions,
+}
+
+impl Diagnostics {
+    pub(crate) fn new(max_errors: u32, warnings: WarningOptions) -> Diagnostics {
+        Diagnostics {
+            recorded: Vec::new(),
+            max_errors,
+            warnings,
+        }
+    }
+
+    /**
+     * Record the error of part of a stage, such as a single line, so the rest of the stage can go on.
+     *
+     * Returns `None` if there was an error, the caller then skips ahead to where it can carry on. Once there are too
+     * many errors the whole stage stops instead.
+     */
+    pub(crate) fn recover<T>(&mut self, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            // The error limit was already reached by an inner stage, so it stops this one as well
+            Err(diagnostic) if diagnostic.code == Code::TooManyErrors => Err(diagnostic),
+            Err(diagnostic) => {
+                self.record(*diagnostic)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /**
+     * Report a warning unless it was turned off, as an error with -Werror
+     */
+    pub(crate) fn warn(
+        &mut self,
+        warning: Warning,
+        message: &str,
+        path: &Path,
+        lines: &[String],
+        span: Span,
+    ) -> Result<()> {
+        if !self.warnings.enabled.contains(&warning) {
+            return Ok(());
+        }
+
+        let severity = if self.warnings.as_errors {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+
+        self.record(Diagnostic {
+            warning: Some(warning),
+            ..Diagnostic::at(severity, warning.code(), message, None, path, lines, span)
+        })
+    }
+
+    /**
+     * Stop assembling if any errors were recovered from so far, returning everything recorded.
+     *
+     * Later stages would only report errors caused by the parts that were skipped, such as labels that are missing
+     * because their line could not be parsed.
+     */
+    pub(crate) fn stop_on_errors(&mut self) -> std::result::Result<(), Vec<Diagnostic>> {
+        if self.recorded.iter().any(Diagnostic::is_error) {
+            return Err(std::mem::take(&mut self.recorded));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Stop assembling at an error a stage could not carry on from, returning it after everything recorded before it
+     */
+    pub(crate) fn fail(&mut self, diagnostic: Diagnostic) -> Vec<Diagnostic> {
+        self.recorded.push(diagnostic);
+
+        std::mem::take(&mut self.recorded)
+    }
+
+    /**
+     * The warnings of a stage that succeeded
+     */
+    pub(crate) fn into_warnings(self) -> Vec<Diagnostic> {
+        self.recorded
+    }
+
+    fn record(&mut self, diagnostic: Diagnostic) -> Result<()> {
+        self.recorded.push(diagnostic);
+
+        let errors = self
+            .recorded
+            .iter()
+            .filter(|diagnostic| diagnostic.is_error())
+            .count();
+
+        if self.max_errors == 0 || errors < self.max_errors as usize {
+            return Ok(());
+        }
+
+        // Errors after the limit are usually caused by the ones before it, so the rest of the file is not checked
+        Diagnostic::new(
+            Severity::Error,
+            Code::TooManyErrors,
+            format!("Too many errors, stopping after {}!", self.max_errors).as_str(),
+        )
+        .with_help(
+            "Fix the first errors and assemble again, or use `--max-errors` to change the limit",
+        )
+        .raise()
+    }
+}
+
+impl Location {
+    /**
+     * The location of `span` in the expanded source, in the file it was written in, along with notes on where
+     * that file was included from
+     */
+    fn find(path: &Path, lines: &[String], span: Span) -> (Location, Vec<String>) {
+        // Diagnostics in an included file are shown in that file
+        let origin = include::locate_error(lines, span.line_number);
+
+        let (path, lines, line_number) = match &origin {
+            Some(origin) => (
+                origin.path.as_path(),
+                origin.lines.as_slice(),
+                origin.line_number,
+            ),
+            None => (path, lines, span.line_number),
+        };
+
+        let start = line_number.saturating_sub(2) as usize;
+
+        let context = lines
+            .iter()
+            .take(line_number as usize + 1)
+            .skip(start)
+            .cloned()
+            .collect();
+
+        let location = Location {
+            path: path.to_path_buf(),
+            span: Span {
+                line_number,
+                ..span
+            },
+            context,
+        };
+
+        (
+            location,
+            origin.map(|origin| origin.notes).unwrap_or_default(),
+        )
+    }
+}
+
+/**
+ * The path of a file for a location, in full or relative to the working directory. A file that can no longer be
+ * found (or a path such as `-` that was never a file) is shown as it was given.
+ */
+fn display_path(path: &Path) -> String {
+    let Ok(full_path) = fs::canonicalize(path) else {
+        return path.to_string_lossy().into_owned();
+    };
+
+    if DIAGNOSTIC_PATHS.get() == DiagnosticPaths::Relative {
+        // Files outside the working directory keep the path they were given, which may already be relative
+        let relative = env::current_dir()
+            .and_then(fs::canonicalize)
+            .ok()
+            .and_then(|directory| {
+                full_path
+                    .strip_prefix(directory)
+                    .ok()
+                    .map(Path::to_path_buf)
+            });
+
+        return relative
+            .as_deref()
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned();
+    }
+
+    let path = full_path.to_string_lossy();
+
+    // Conanicalization is platform specific
+    if cfg!(target_os = "windows") {
+        path.trim_start_matches("\\\\?\\").to_owned()
+    } else {
+        path.into_owned()
+    }
+}
+
+/**
+ * Print the path, line and column of a location with the lines around it, the span underlined with `marker`
+ * and `message` below it
+ */
+fn print_location(location: &Location, colour: Colour, marker: char, message: &str) {
+    let colour = style(colour);
+
+    let line_number = location.span.line_number;
+    let (col_start, col_end) = (location.span.column_start, location.span.column_end);
+
+    // Print the file path with the line and col number
+    eprintln!(
+        "{}",
+        style(Colour::Fixed(246)).paint(format!(
+            "{}:{}:{}",
+            display_path(&location.path),
+            line_number + 1,
+            col_start + 1
+        ))
+    );
+
+    // Print the lines around and including the one with the error
+    let start = line_number + 1 - location.context.len() as u32;
+
+    for (n, line) in (start..).zip(&location.context) {
+        eprintln!(
+            "{}: {}",
+            style(Colour::Blue).paint(format!("{:>3}", n + 1)),
+            expand_tabs(line)
+        );
+    }
+
+    // Columns count characters, but tabs and wide characters take up more than one column when the line is shown
+    let line = location
+        .context
+        .last()
+        .map(String::as_str)
+        .unwrap_or_default();
+    let (col_start, col_end) = (
+        display_column(line, col_start),
+        display_column(line, col_end),
+    );
+
+    // Characters such as combining accents take up no columns of their own, but still get a caret
+    let col_end = col_end.max(col_start + 1);
+
+    // Print the space before the highlight
+    for _ in 0..col_start + 5 {
+        eprint!(" ");
+    }
+
+    // Print the underline highlight
+    for _ in col_start..col_end {
+        eprint!("{}", colour.paint(marker.to_string()));
+    }
+
+    eprintln!();
+
+    // Print the space before the message
+    for _ in 0..col_start + 5 {
+        eprint!(" ");
+    }
+
+    eprintln!("{}", colour.paint(message));
+}
+
+/**
+ * Width of a character at `column` when a line is shown in a terminal.
+ *
+ * Tabs move to the next tab stop, wide characters such as CJK and emoji take up two columns and
+ * combining characters none.
+ */
+fn char_width(c: char, column: u32) -> u32 {
+    match c {
+        '\t' => {
+            let tab_width = TAB_WIDTH.get().max(1);
+            tab_width - column % tab_width
+        }
+        _ => c.width().unwrap_or(0) as u32,
+    }
+}
+
+/**
+ * A line as it is shown in errors, with tabs replaced by spaces up to the next tab stop
+ */
+fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::new();
+    let mut column = 0;
+
+    for c in line.chars() {
+        let width = char_width(c, column);
+
+        match c {
+            '\t' => expanded.extend(std::iter::repeat_n(' ', width as usize)),
+            _ => expanded.push(c),
+        }
+
+        column += width;
+    }
+
+    expanded
+}
+
+/**
+ * Column the character at `column` is shown at, counting past the end of the line as one column each
+ */
+fn display_column(line: &str, column: u32) -> u32 {
+    let mut display = 0;
+    let mut chars = line.chars();
+
+    for _ in 0..column {
+        display += match chars.next() {
+            Some(c) => char_width(c, display),
+            None => 1,
+        };
+    }
+
+    display
+}
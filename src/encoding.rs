@@ -0,0 +1,94 @@
+use std::{path::Path, string::FromUtf8Error};
+
+use crate::{
+    code::Code,
+    diagnostic::{Diagnostic, Severity},
+    token::Span,
+};
+
+/**
+ * Character encoding of source files, selected with `--encoding`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    // ISO 8859-1, used by older editors for accented characters in comments and strings
+    Latin1,
+}
+
+impl Encoding {
+    pub const NAMES: &'static [&'static str] = &["utf8", "latin1"];
+
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name {
+            "utf8" | "utf-8" => Some(Encoding::Utf8),
+            "latin1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    /**
+     * Decode the contents of a source file. Every byte is a character in Latin-1, so only UTF-8 can fail.
+     */
+    pub(crate) fn decode(&self, bytes: Vec<u8>) -> Result<String, FromUtf8Error> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes),
+            Encoding::Latin1 => Ok(bytes.into_iter().map(char::from).collect()),
+        }
+    }
+
+    /**
+     * Encode a source file to be written back, the inverse of `decode`.
+     *
+     * Characters Latin-1 has no byte for are written as `?`, fixes only ever insert ASCII.
+     */
+    pub(crate) fn encode(&self, source: String) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 => source.into_bytes(),
+            Encoding::Latin1 => source
+                .chars()
+                .map(|c| u8::try_from(c).unwrap_or(b'?'))
+                .collect(),
+        }
+    }
+}
+
+/**
+ * The error for a source file at `path` that is not UTF-8, pointing at the first byte that is not part of a character
+ */
+pub(crate) fn invalid_utf8(path: &Path, error: &FromUtf8Error) -> Diagnostic {
+    let bytes = error.as_bytes();
+    let offset = error.utf8_error().valid_up_to();
+
+    // Everything before the offset is valid, so the line and column of the byte can be counted in it
+    let valid = String::from_utf8_lossy(&bytes[..offset]);
+    let line_number = valid.matches('\n').count() as u32;
+    let column = valid
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count() as u32;
+
+    // The byte is shown as a replacement character in the snippet
+    let lines: Vec<_> = String::from_utf8_lossy(bytes)
+        .lines()
+        .map(|line| line.to_owned())
+        .collect();
+
+    Diagnostic::at(
+        Severity::Error,
+        Code::InvalidEncoding,
+        format!("Byte ${:02X} at offset {offset} is not valid UTF-8!", bytes[offset]).as_str(),
+        None,
+        path,
+        &lines,
+        Span {
+            line_number,
+            column_start: column,
+            column_end: column + 1,
+        },
+    )
+    .with_help("Convert the file to UTF-8, or assemble it with `--encoding latin1` if it was saved as Latin-1")
+}
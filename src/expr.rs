@@ -0,0 +1,668 @@
+use std::{collections::VecDeque, path::Path};
+
+use crate::{
+    code::Code,
+    diagnostic::{self, Diagnostic, Severity},
+    parse::Parsable,
+    preprocess, report_error, report_span_error, report_span_error_with_help,
+    resolve::Section,
+    suggest,
+    token::{Span, Token, TokenType},
+};
+
+/**
+ * Names of the builtin functions
+ */
+pub(crate) const FUNCTIONS: &[&str] = &["defined", "sizeof", "lengthof", "lo", "hi", "section"];
+
+/**
+ * An expression used by conditional assembly, `.assert` directives and values computed from labels
+ */
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Number(i64, Span),
+    String(String, Span),
+    Identifier(String, Span),
+    Call {
+        name: String,
+        arguments: Vec<Expression>,
+        span: Span,
+    },
+    Unary {
+        operator: String,
+        operand: Box<Expression>,
+        span: Span,
+    },
+    Binary {
+        operator: String,
+        left: Box<Expression>,
+        right: Box<Expression>,
+        span: Span,
+    },
+}
+
+/**
+ * The result of evaluating an expression
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(i64),
+    String(String),
+}
+
+/**
+ * Everything an expression can ask about the program while it is being evaluated
+ */
+pub trait Context {
+    /**
+     * Checks if a variable was defined on the command line
+     */
+    fn is_defined(&self, name: &str) -> bool;
+
+    /**
+     * Value of a name used on its own in an expression, such as a variable defined with NAME=VALUE
+     */
+    fn value_of(&self, name: &str) -> Option<u16>;
+
+    /**
+     * Number of bytes a label assembles to, if the label is known. `span` is where the label is used, for errors
+     */
+    fn size_of(&self, label: &str, span: Span) -> diagnostic::Result<Option<u32>>;
+
+    /**
+     * Number of values in the data of a label, such as the characters of a string, if the label is known
+     */
+    fn length_of(&self, label: &str) -> diagnostic::Result<Option<u32>>;
+
+    /**
+     * The section currently being assembled
+     */
+    fn section(&self) -> Option<Section>;
+
+    /**
+     * Every label and variable an expression can use, to suggest one in place of a name that is not known
+     */
+    fn names(&self) -> Vec<&str>;
+}
+
+/**
+ * Binary operators from lowest to highest precedence
+ */
+const BINARY_OPERATORS: [&[&str]; 4] = [
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<", "<=", ">", ">="],
+    &["+", "-"],
+];
+
+impl Parsable for Expression {
+    fn parse(
+        path: &Path,
+        lines: &[String],
+        tokens: &mut VecDeque<Token>,
+    ) -> diagnostic::Result<Expression> {
+        assert!(
+            !tokens.is_empty(),
+            "Vec passed to Expression parser should contain at least one token"
+        );
+
+        let expression = parse_binary(path, lines, tokens, 0)?;
+
+        // The whole token list should have been consumed by the expression
+        if let Some(illegal_token) = tokens.pop_front() {
+            return report_error(
+                Code::InvalidExpression,
+                format!("Unexpected token `{}` in expression!", illegal_token.value).as_str(),
+                path,
+                lines,
+                illegal_token.line_number,
+                illegal_token.column_start,
+                illegal_token.column_end,
+            );
+        }
+
+        Ok(expression)
+    }
+}
+
+fn parse_binary(
+    path: &Path,
+    lines: &[String],
+    tokens: &mut VecDeque<Token>,
+    precedence: usize,
+) -> diagnostic::Result<Expression> {
+    if precedence == BINARY_OPERATORS.len() {
+        return parse_unary(path, lines, tokens);
+    }
+
+    let mut left = parse_binary(path, lines, tokens, precedence + 1)?;
+
+    while let Some(Token {
+        token_type: TokenType::Operator(operator),
+        ..
+    }) = tokens.front()
+    {
+        if !BINARY_OPERATORS[precedence].contains(&operator.as_str()) {
+            break;
+        }
+
+        let operator = operator.clone();
+        let operator_token = tokens.pop_front().unwrap();
+
+        if tokens.is_empty() {
+            return report_error(
+                Code::InvalidExpression,
+                format!("Expected expression after operator `{operator}`!").as_str(),
+                path,
+                lines,
+                operator_token.line_number,
+                operator_token.column_start,
+                operator_token.column_end,
+            );
+        }
+
+        let right = parse_binary(path, lines, tokens, precedence + 1)?;
+        let span = left.span().to(&right.span());
+
+        left = Expression::Binary {
+            operator,
+            left: Box::new(left),
+            right: Box::new(right),
+            span,
+        };
+    }
+
+    Ok(left)
+}
+
+fn parse_unary(
+    path: &Path,
+    lines: &[String],
+    tokens: &mut VecDeque<Token>,
+) -> diagnostic::Result<Expression> {
+    let Some(first_token) = tokens.pop_front() else {
+        panic!("parse_unary should only be called with tokens left");
+    };
+
+    Ok(match &first_token.token_type {
+        // `<` and `>` before a value select its low and high byte
+        TokenType::Operator(operator) if matches!(operator.as_str(), "!" | "-" | "<" | ">") => {
+            if tokens.is_empty() {
+                return report_error(
+                    Code::InvalidExpression,
+                    format!("Expected expression after operator `{operator}`!").as_str(),
+                    path,
+                    lines,
+                    first_token.line_number,
+                    first_token.column_start,
+                    first_token.column_end,
+                );
+            }
+
+            let operand = parse_unary(path, lines, tokens)?;
+            let span = first_token.span().to(&operand.span());
+
+            Expression::Unary {
+                operator: operator.clone(),
+                operand: Box::new(operand),
+                span,
+            }
+        }
+        TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_) => Expression::Number(
+            first_token.parse_u16(path, lines)? as i64,
+            first_token.span(),
+        ),
+        TokenType::AsciiString(string) => Expression::String(string.clone(), first_token.span()),
+        TokenType::Identifier(name) => {
+            // A bare identifier unless it is followed by an argument list
+            let Some(Token {
+                token_type: TokenType::OpenParenthesis,
+                ..
+            }) = tokens.front()
+            else {
+                return Ok(Expression::Identifier(name.clone(), first_token.span()));
+            };
+
+            tokens.pop_front();
+
+            let mut arguments = Vec::new();
+
+            loop {
+                let Some(token) = tokens.front() else {
+                    return report_error(
+                        Code::InvalidExpression,
+                        format!("Expected closing parenthesis after arguments to `{name}`!")
+                            .as_str(),
+                        path,
+                        lines,
+                        first_token.line_number,
+                        first_token.column_start,
+                        first_token.column_end,
+                    );
+                };
+
+                if token.token_type == TokenType::CloseParenthesis {
+                    break;
+                }
+
+                if !arguments.is_empty() {
+                    if token.token_type != TokenType::Comma {
+                        return report_error(
+                            Code::InvalidExpression,
+                            format!(
+                                "Unexpected token `{}` in argument list! Expected `,` or `)`!",
+                                token.value
+                            )
+                            .as_str(),
+                            path,
+                            lines,
+                            token.line_number,
+                            token.column_start,
+                            token.column_end,
+                        );
+                    }
+
+                    let comma_token = tokens.pop_front().unwrap();
+
+                    if matches!(
+                        tokens.front().map(|token| &token.token_type),
+                        None | Some(TokenType::CloseParenthesis)
+                    ) {
+                        return report_error(
+                            Code::UnexpectedToken,
+                            "Unexpected argument separator `,`!",
+                            path,
+                            lines,
+                            comma_token.line_number,
+                            comma_token.column_start,
+                            comma_token.column_end,
+                        );
+                    }
+                }
+
+                arguments.push(parse_binary(path, lines, tokens, 0)?);
+            }
+
+            let close_token = tokens.pop_front().unwrap();
+
+            Expression::Call {
+                name: name.clone(),
+                arguments,
+                span: first_token.span().to(&close_token.span()),
+            }
+        }
+        TokenType::OpenParenthesis => {
+            if tokens.is_empty() {
+                return report_error(
+                    Code::InvalidExpression,
+                    "Expected expression after opening parenthesis `(`!",
+                    path,
+                    lines,
+                    first_token.line_number,
+                    first_token.column_start,
+                    first_token.column_end,
+                );
+            }
+
+            let expression = parse_binary(path, lines, tokens, 0)?;
+
+            match tokens.pop_front() {
+                Some(Token {
+                    token_type: TokenType::CloseParenthesis,
+                    ..
+                }) => {}
+                // Something else is where the `)` should be, so it is not known where the `)` belongs
+                Some(token) => {
+                    return report_span_error(
+                        Code::InvalidExpression,
+                        format!(
+                            "Unexpected token `{}` in expression! Expected closing parenthesis!",
+                            token.value
+                        )
+                        .as_str(),
+                        path,
+                        lines,
+                        token.span(),
+                    )
+                }
+                None => {
+                    return Diagnostic::at(
+                        Severity::Error,
+                        Code::InvalidExpression,
+                        "Expected closing parenthesis after expression!",
+                        None,
+                        path,
+                        lines,
+                        first_token.span(),
+                    )
+                    .with_fix(
+                        "Add the missing closing parenthesis `)`",
+                        path,
+                        lines,
+                        Span {
+                            column_start: expression.span().column_end,
+                            ..expression.span()
+                        },
+                        ")",
+                    )
+                    .raise()
+                }
+            }
+
+            expression
+        }
+        _ => {
+            return report_error(
+                Code::InvalidExpression,
+                format!("Unexpected token `{}` in expression!", first_token.value).as_str(),
+                path,
+                lines,
+                first_token.line_number,
+                first_token.column_start,
+                first_token.column_end,
+            )
+        }
+    })
+}
+
+impl Expression {
+    /**
+     * Parse an expression from the front of the tokens, leaving whatever follows it
+     */
+    pub fn parse_prefix(
+        path: &Path,
+        lines: &[String],
+        tokens: &mut VecDeque<Token>,
+    ) -> diagnostic::Result<Expression> {
+        parse_binary(path, lines, tokens, 0)
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Number(_, span)
+            | Expression::String(_, span)
+            | Expression::Identifier(_, span) => *span,
+            Expression::Call { span, .. }
+            | Expression::Unary { span, .. }
+            | Expression::Binary { span, .. } => *span,
+        }
+    }
+
+    /**
+     * Every name the expression uses, including labels given to functions such as `sizeof()`
+     */
+    pub fn identifiers(&self) -> Vec<&str> {
+        match self {
+            Expression::Number(..) | Expression::String(..) => Vec::new(),
+            Expression::Identifier(name, _) => vec![name.as_str()],
+            Expression::Call { arguments, .. } => {
+                arguments.iter().flat_map(Expression::identifiers).collect()
+            }
+            Expression::Unary { operand, .. } => operand.identifiers(),
+            Expression::Binary { left, right, .. } => {
+                let mut identifiers = left.identifiers();
+                identifiers.extend(right.identifiers());
+                identifiers
+            }
+        }
+    }
+
+    pub fn evaluate(
+        &self,
+        path: &Path,
+        lines: &[String],
+        context: &dyn Context,
+    ) -> diagnostic::Result<Value> {
+        Ok(match self {
+            Expression::Number(value, _) => Value::Number(*value),
+            Expression::String(string, _) => Value::String(string.clone()),
+            Expression::Identifier(name, span) => match context.value_of(name) {
+                Some(value) => Value::Number(value as i64),
+                None if context.is_defined(name) => return report_span_error(
+                    Code::UnknownIdentifier,
+                    format!("`{name}` was defined without a value! Give it one with -D {name}=<value>, or use defined({name})").as_str(),
+                    path,
+                    lines,
+                    *span,
+                ),
+                None => return report_span_error_with_help(
+                    Code::UnknownIdentifier,
+                    if name.contains('.') {
+                        format!("Unknown local label {}!", preprocess::source_name(name))
+                    } else {
+                        format!("Unknown identifier `{name}` in expression!")
+                    }
+                    .as_str(),
+                    suggest::did_you_mean_label(name, context.names()).as_deref(),
+                    path,
+                    lines,
+                    *span,
+                ),
+            },
+            Expression::Call {
+                name,
+                arguments,
+                span,
+            } => evaluate_call(path, lines, context, name, arguments, *span)?,
+            Expression::Unary {
+                operator, operand, ..
+            } => {
+                let value = operand.evaluate_number(path, lines, context)?;
+
+                match operator.as_str() {
+                    "!" => Value::Number((value == 0) as i64),
+                    "-" => Value::Number(-value),
+                    "<" => Value::Number(value & 0xFF),
+                    ">" => Value::Number((value >> 8) & 0xFF),
+                    _ => panic!("Unexpected unary operator `{operator}`"),
+                }
+            }
+            Expression::Binary {
+                operator,
+                left,
+                right,
+                span,
+            } => match operator.as_str() {
+                // Short circuit logical operators
+                "&&" => Value::Number(
+                    (left.evaluate_number(path, lines, context)? != 0
+                        && right.evaluate_number(path, lines, context)? != 0)
+                        as i64,
+                ),
+                "||" => Value::Number(
+                    (left.evaluate_number(path, lines, context)? != 0
+                        || right.evaluate_number(path, lines, context)? != 0)
+                        as i64,
+                ),
+                _ => {
+                    let left = left.evaluate(path, lines, context)?;
+                    let right = right.evaluate(path, lines, context)?;
+
+                    match (operator.as_str(), &left, &right) {
+                        ("==", _, _) if same_type(&left, &right) => {
+                            Value::Number((left == right) as i64)
+                        }
+                        ("!=", _, _) if same_type(&left, &right) => {
+                            Value::Number((left != right) as i64)
+                        }
+                        (operator, Value::Number(left), Value::Number(right)) => {
+                            Value::Number(match operator {
+                                "<" => (left < right) as i64,
+                                "<=" => (left <= right) as i64,
+                                ">" => (left > right) as i64,
+                                ">=" => (left >= right) as i64,
+                                "+" => left + right,
+                                "-" => left - right,
+                                _ => panic!("Unexpected binary operator `{operator}`"),
+                            })
+                        }
+                        _ => return report_span_error(
+                            Code::InvalidExpression,
+                            format!("Operator `{operator}` cannot be used to compare a number with a string!")
+                                .as_str(),
+                            path,
+                            lines,
+                            *span,
+                        ),
+                    }
+                }
+            },
+        })
+    }
+
+    /**
+     * Evaluate an expression that must produce a number, such as a condition
+     */
+    pub fn evaluate_number(
+        &self,
+        path: &Path,
+        lines: &[String],
+        context: &dyn Context,
+    ) -> diagnostic::Result<i64> {
+        match self.evaluate(path, lines, context)? {
+            Value::Number(value) => Ok(value),
+            Value::String(_) => report_span_error(
+                Code::InvalidExpression,
+                "Expected a number but the expression is a string!",
+                path,
+                lines,
+                self.span(),
+            ),
+        }
+    }
+}
+
+fn same_type(left: &Value, right: &Value) -> bool {
+    matches!(
+        (left, right),
+        (Value::Number(_), Value::Number(_)) | (Value::String(_), Value::String(_))
+    )
+}
+
+/**
+ * Evaluate one of the builtin functions
+ */
+fn evaluate_call(
+    path: &Path,
+    lines: &[String],
+    context: &dyn Context,
+    name: &str,
+    arguments: &[Expression],
+    span: Span,
+) -> diagnostic::Result<Value> {
+    Ok(match name {
+        // defined(NAME) - Checks if NAME was defined with -D
+        "defined" => {
+            let name = expect_identifier_argument(path, lines, name, arguments, span)?;
+
+            Value::Number(context.is_defined(name) as i64)
+        }
+        // sizeof(label) - Number of bytes a previously declared label assembles to
+        "sizeof" => {
+            let label = expect_identifier_argument(path, lines, name, arguments, span)?;
+
+            let Some(size) = context.size_of(label, arguments[0].span())? else {
+                return report_span_error_with_help(
+                    Code::UnknownLabel,
+                    format!("Unknown label {}! Labels must be declared before sizeof() can be used on them.", preprocess::source_name(label)).as_str(),
+                    suggest::did_you_mean_label(label, context.names()).as_deref(),
+                    path,
+                    lines,
+                    arguments[0].span(),
+                );
+            };
+
+            Value::Number(size as i64)
+        }
+        // lengthof(label) - Number of values in the data of a previously declared label
+        "lengthof" => {
+            let label = expect_identifier_argument(path, lines, name, arguments, span)?;
+
+            let Some(length) = context.length_of(label)? else {
+                return report_span_error_with_help(
+                    Code::UnknownLabel,
+                    format!("Unknown label {}! Labels must be declared before lengthof() can be used on them.", preprocess::source_name(label)).as_str(),
+                    suggest::did_you_mean_label(label, context.names()).as_deref(),
+                    path,
+                    lines,
+                    arguments[0].span(),
+                );
+            };
+
+            Value::Number(length as i64)
+        }
+        // lo(value) and hi(value) - Low and high byte of a 16-bit value
+        "lo" | "hi" => {
+            let [argument] = arguments else {
+                return report_span_error(
+                    Code::InvalidExpression,
+                    format!("`{name}()` expects 1 argument, but got {}", arguments.len()).as_str(),
+                    path,
+                    lines,
+                    span,
+                );
+            };
+
+            let value = argument.evaluate_number(path, lines, context)?;
+
+            Value::Number(if name == "lo" { value & 0xFF } else { (value >> 8) & 0xFF })
+        }
+        // section() - Name of the current section
+        "section" => {
+            if !arguments.is_empty() {
+                return report_span_error(
+                    Code::InvalidExpression,
+                    "`section()` does not take any arguments!",
+                    path,
+                    lines,
+                    span,
+                );
+            }
+
+            Value::String(
+                context
+                    .section()
+                    .map(|section| section.name().to_owned())
+                    .unwrap_or_default(),
+            )
+        }
+        _ => return report_span_error(
+            Code::UnknownIdentifier,
+            format!("Unknown function `{name}`! Expected one of `defined`, `sizeof`, `lengthof`, `lo`, `hi` or `section`")
+                .as_str(),
+            path,
+            lines,
+            span,
+        ),
+    })
+}
+
+fn expect_identifier_argument<'a>(
+    path: &Path,
+    lines: &[String],
+    function: &str,
+    arguments: &'a [Expression],
+    span: Span,
+) -> diagnostic::Result<&'a str> {
+    match arguments {
+        [Expression::Identifier(name, _)] => Ok(name),
+        [argument] => report_span_error(
+            Code::InvalidExpression,
+            format!("`{function}()` expects a name as its argument!").as_str(),
+            path,
+            lines,
+            argument.span(),
+        ),
+        _ => report_span_error(
+            Code::InvalidExpression,
+            format!(
+                "`{function}()` expects 1 argument, but got {}",
+                arguments.len()
+            )
+            .as_str(),
+            path,
+            lines,
+            span,
+        ),
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{code::Code, diagnostic::Diagnostic, diagnostic::Fix, encoding::Encoding, io_error};
+
+/**
+ * Apply the fixes attached to diagnostics to the files they point at, for `--fix`. Files are read and written
+ * back in `encoding`.
+ *
+ * Returns the number of fixes applied to each file that changed, or the error for a file that could not be read or
+ * written, in which case the files after it are left as they were. A fix that overlaps one that was already
+ * applied is skipped, assembling again finds it again if it is still needed.
+ */
+pub fn apply_fixes(
+    diagnostics: &[Diagnostic],
+    encoding: Encoding,
+) -> Result<Vec<(PathBuf, usize)>, Vec<Diagnostic>> {
+    let mut fixes: Vec<&Fix> = diagnostics
+        .iter()
+        .flat_map(|diagnostic| &diagnostic.fixes)
+        .collect();
+
+    // Fixes are applied from the end of each file, so earlier ones still point at the right characters
+    fixes.sort_by(|a, b| {
+        (&a.path, b.span.line_number, b.span.column_start).cmp(&(
+            &b.path,
+            a.span.line_number,
+            a.span.column_start,
+        ))
+    });
+    fixes.dedup();
+
+    let mut applied = Vec::new();
+
+    for path_fixes in fixes.chunk_by(|a, b| a.path == b.path) {
+        let path = &path_fixes[0].path;
+
+        let source = fs::read(path).map_err(|err| {
+            vec![io_error(
+                Code::UnreadableFile,
+                "read source file",
+                path,
+                &err,
+            )]
+        })?;
+
+        // The files were decoded when they were assembled, so this only fails if one changed since
+        let Ok(mut source) = encoding.decode(source) else {
+            continue;
+        };
+
+        let mut count = 0;
+        let mut limit = None;
+
+        for fix in path_fixes {
+            let (Some(start), Some(end)) = (
+                byte_offset(&source, fix.span.line_number, fix.span.column_start),
+                byte_offset(&source, fix.span.line_number, fix.span.column_end),
+            ) else {
+                continue;
+            };
+
+            if limit.is_some_and(|limit| end > limit) {
+                continue;
+            }
+
+            source.replace_range(start..end, &fix.replacement);
+            limit = Some(start);
+            count += 1;
+        }
+
+        if count > 0 {
+            write_source(path, encoding.encode(source))?;
+            applied.push((path.clone(), count));
+        }
+    }
+
+    Ok(applied)
+}
+
+/**
+ * Byte offset of a character column in a line, which can be the end of the line
+ */
+fn byte_offset(source: &str, line_number: u32, column: u32) -> Option<usize> {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(line_number as usize)
+        .map(str::len)
+        .sum();
+
+    let line = source[line_start..].lines().next().unwrap_or_default();
+
+    match line.char_indices().nth(column as usize) {
+        Some((offset, _)) => Some(line_start + offset),
+        None if column as usize == line.chars().count() => Some(line_start + line.len()),
+        None => None,
+    }
+}
+
+fn write_source(path: &Path, source: Vec<u8>) -> Result<(), Vec<Diagnostic>> {
+    fs::write(path, source).map_err(|err| {
+        vec![io_error(
+            Code::UnwritableFile,
+            "write source file",
+            path,
+            &err,
+        )]
+    })
+}
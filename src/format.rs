@@ -0,0 +1,289 @@
+use std::io::{self, Write};
+
+use crate::{compile::Assembly, resolve::Section};
+
+/**
+ * Writes an assembled program to the output file in some format.
+ *
+ * Implement this to add a custom format and pass it as `OutputFormat::Custom`.
+ */
+pub trait OutputWriter: std::fmt::Debug {
+    fn write(&self, assembly: &Assembly, output: &mut dyn Write) -> io::Result<()>;
+
+    /**
+     * Extension used for the output file when no output path is given
+     */
+    fn extension(&self) -> &str;
+}
+
+/**
+ * The format of the output file, selected with `-f/--format`
+ */
+#[derive(Debug)]
+pub enum OutputFormat {
+    Bin,
+    IntelHex,
+    SRecord,
+    Hexdump,
+    Sis16Exe,
+    Custom(Box<dyn OutputWriter>),
+}
+
+impl OutputFormat {
+    /**
+     * Names of the builtin formats accepted by `from_name`
+     */
+    pub const NAMES: &'static [&'static str] = &["bin", "ihex", "srec", "hexdump", "sis16-exe"];
+
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        let format = match name {
+            "bin" => OutputFormat::Bin,
+            "ihex" => OutputFormat::IntelHex,
+            "srec" => OutputFormat::SRecord,
+            "hexdump" => OutputFormat::Hexdump,
+            "sis16-exe" => OutputFormat::Sis16Exe,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+
+    pub fn writer(&self) -> &dyn OutputWriter {
+        match self {
+            OutputFormat::Bin => &BinWriter,
+            OutputFormat::IntelHex => &IntelHexWriter,
+            OutputFormat::SRecord => &SRecordWriter,
+            OutputFormat::Hexdump => &HexdumpWriter,
+            OutputFormat::Sis16Exe => &Sis16ExeWriter,
+            OutputFormat::Custom(writer) => writer.as_ref(),
+        }
+    }
+}
+
+/**
+ * Raw binary image
+ */
+#[derive(Debug)]
+pub struct BinWriter;
+
+impl OutputWriter for BinWriter {
+    fn write(&self, assembly: &Assembly, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&assembly.image())
+    }
+
+    fn extension(&self) -> &str {
+        "bin"
+    }
+}
+
+/**
+ * Intel HEX records with 16 data bytes per line
+ */
+#[derive(Debug)]
+pub struct IntelHexWriter;
+
+impl OutputWriter for IntelHexWriter {
+    fn write(&self, assembly: &Assembly, output: &mut dyn Write) -> io::Result<()> {
+        let origin = assembly.origin();
+
+        for (i, chunk) in assembly.image().chunks(16).enumerate() {
+            let address = origin.wrapping_add(i as u16 * 16);
+
+            // Record length, address, and record type 00 (data)
+            let mut record = vec![chunk.len() as u8];
+            record.extend(address.to_be_bytes());
+            record.push(0x00);
+            record.extend(chunk);
+
+            let checksum = record
+                .iter()
+                .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+                .wrapping_neg();
+
+            writeln!(output, ":{}{checksum:02X}", hex_string(&record))?;
+        }
+
+        // End of file record
+        writeln!(output, ":00000001FF")
+    }
+
+    fn extension(&self) -> &str {
+        "hex"
+    }
+}
+
+/**
+ * Motorola S-records using 16-bit addresses (S1/S9)
+ */
+#[derive(Debug)]
+pub struct SRecordWriter;
+
+impl SRecordWriter {
+    fn write_record(
+        output: &mut dyn Write,
+        record_type: u8,
+        address: u16,
+        data: &[u8],
+    ) -> io::Result<()> {
+        // Byte count covers the address, data, and checksum
+        let mut record = vec![data.len() as u8 + 3];
+        record.extend(address.to_be_bytes());
+        record.extend(data);
+
+        let checksum = !record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+        writeln!(
+            output,
+            "S{record_type}{}{checksum:02X}",
+            hex_string(&record)
+        )
+    }
+}
+
+impl OutputWriter for SRecordWriter {
+    fn write(&self, assembly: &Assembly, output: &mut dyn Write) -> io::Result<()> {
+        let origin = assembly.origin();
+
+        // Header record
+        SRecordWriter::write_record(output, 0, 0, &[])?;
+
+        for (i, chunk) in assembly.image().chunks(16).enumerate() {
+            SRecordWriter::write_record(output, 1, origin.wrapping_add(i as u16 * 16), chunk)?;
+        }
+
+        // Termination record with the start address
+        SRecordWriter::write_record(output, 9, origin, &[])
+    }
+
+    fn extension(&self) -> &str {
+        "srec"
+    }
+}
+
+/**
+ * Human readable hex dump with an ascii column
+ */
+#[derive(Debug)]
+pub struct HexdumpWriter;
+
+impl OutputWriter for HexdumpWriter {
+    fn write(&self, assembly: &Assembly, output: &mut dyn Write) -> io::Result<()> {
+        let origin = assembly.origin();
+
+        for (i, chunk) in assembly.image().chunks(16).enumerate() {
+            let bytes: Vec<_> = chunk.iter().map(|byte| format!("{byte:02X}")).collect();
+
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+
+            writeln!(
+                output,
+                "{:04X}: {:<47}  |{ascii}|",
+                origin.wrapping_add(i as u16 * 16),
+                bytes.join(" ")
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn extension(&self) -> &str {
+        "txt"
+    }
+}
+
+/**
+ * Executable with a header that tells loaders where to put each section and where to start.
+ *
+ * All header fields are little endian:
+ *
+ * | Offset | Size | Field                                        |
+ * | ------ | ---- | -------------------------------------------- |
+ * | 0      | 4    | Magic `S16X`                                 |
+ * | 4      | 1    | Format version (1)                           |
+ * | 5      | 1    | Number of sections                           |
+ * | 6      | 2    | Entry address                                |
+ * | 8      | 6n   | Section table, one entry per section         |
+ *
+ * Every section table entry is a type byte (0 for text and named sections, 1 for data), a reserved zero byte,
+ * the load address and the size in bytes. The contents of each section follow the table in order.
+ */
+#[derive(Debug)]
+pub struct Sis16ExeWriter;
+
+impl Sis16ExeWriter {
+    pub const MAGIC: &'static [u8; 4] = b"S16X";
+    pub const VERSION: u8 = 1;
+}
+
+impl OutputWriter for Sis16ExeWriter {
+    fn write(&self, assembly: &Assembly, output: &mut dyn Write) -> io::Result<()> {
+        let sections = assembly.section_runs();
+
+        // Without an entry point execution starts at the lowest address
+        let entry = assembly.entry.unwrap_or(assembly.origin());
+
+        let Ok(section_count) = u8::try_from(sections.len()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Too many sections for a sis16-exe file ({}, max is 255)",
+                    sections.len()
+                ),
+            ));
+        };
+
+        let mut header = Self::MAGIC.to_vec();
+        header.push(Self::VERSION);
+        header.push(section_count);
+        header.extend(entry.to_le_bytes());
+
+        for (section, address, bytes) in &sections {
+            header.push(match section {
+                Section::Text | Section::Named(_) => 0,
+                Section::Data => 1,
+                // .bss has no bytes, so it never has a run
+                Section::Bss => unreachable!(),
+            });
+            header.push(0);
+            header.extend(address.to_le_bytes());
+
+            // A section that fills all 64 KiB of memory is one byte too large for its size
+            let Ok(size) = u16::try_from(bytes.len()) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Section at ${address:04X} is too large for a sis16-exe file ({} bytes, max is 65535)",
+                        bytes.len()
+                    ),
+                ));
+            };
+
+            header.extend(size.to_le_bytes());
+        }
+
+        output.write_all(&header)?;
+
+        for (_, _, bytes) in &sections {
+            output.write_all(bytes)?;
+        }
+
+        Ok(())
+    }
+
+    fn extension(&self) -> &str {
+        "s16"
+    }
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02X}")).collect()
+}
@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use crate::{assemble_source, encoding, AssemblerArguments};
+
+/**
+ * Assemble arbitrary input with the default options, through every stage from include expansion to the output.
+ *
+ * Errors the assembler reports for bad input are returned as `Err` with their message.
+ * Any other panic is a bug in the assembler and is passed on so the fuzzer records it.
+ */
+pub fn fuzz_assemble(bytes: &[u8]) -> Result<(), String> {
+    let path = Path::new("fuzz.asm");
+    let args = AssemblerArguments::default();
+
+    let result = args
+        .encoding
+        .decode(bytes.to_vec())
+        .map_err(|err| vec![encoding::invalid_utf8(path, &err)])
+        .and_then(|source| assemble_source(path, &source, &args));
+
+    match result {
+        Ok(assembly) => {
+            // Writing the image is part of assembling, but its bytes do not matter here
+            let _ = args.format.writer().write(&assembly, &mut Vec::new());
+
+            Ok(())
+        }
+        Err(diagnostics) => Err(diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect::<Vec<_>>()
+            .join("\n")),
+    }
+}
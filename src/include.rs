@@ -0,0 +1,458 @@
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use regex::Regex;
+
+use crate::{
+    code::Code,
+    diagnostic,
+    encoding::{invalid_utf8, Encoding},
+    io_error_note, report_error, report_error_with_note, token,
+};
+
+/**
+ * A file that was read while expanding `.include` directives
+ */
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub lines: Vec<String>,
+    // File index and line number of the `.include` that read this file
+    pub included_from: Option<(usize, u32)>,
+}
+
+/**
+ * Where a line of the expanded source came from
+ */
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LineOrigin {
+    pub file: usize,
+    pub line: u32,
+    // Index of the macro expansion that produced the line, if it came from a macro body
+    pub expansion: Option<usize>,
+}
+
+/**
+ * A macro call or a pass through a repeated block, whose lines were assembled in its place
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct MacroExpansion {
+    // Name of the macro, or the directive that repeated the block
+    pub name: String,
+    // Which pass through a repeated block this is, counting from 0
+    pub iteration: Option<u32>,
+    // The line that called the macro or started the block
+    pub call: LineOrigin,
+}
+
+/**
+ * Maps the lines of the source after includes and macros were expanded back to the files they came from
+ */
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    pub files: Vec<SourceFile>,
+    // Every line with all includes and macros expanded, this is what gets assembled
+    pub lines: Vec<String>,
+    // Where every expanded line came from
+    pub(crate) origins: Vec<LineOrigin>,
+    pub(crate) expansions: Vec<MacroExpansion>,
+}
+
+impl SourceMap {
+    /**
+     * The file an expanded line came from and its line number in that file.
+     *
+     * Lines from a macro body are located in the macro definition.
+     */
+    pub fn locate(&self, line_number: u32) -> Option<(&SourceFile, u32)> {
+        let origin = self.origins.get(line_number as usize)?;
+
+        Some((&self.files[origin.file], origin.line))
+    }
+
+    /**
+     * The macro an expanded line came from, along with the file and line it was called from
+     */
+    pub fn macro_call(&self, line_number: u32) -> Option<(&str, &SourceFile, u32)> {
+        let expansion = self.outermost_expansion(line_number)?;
+
+        Some((
+            expansion.name.as_str(),
+            &self.files[expansion.call.file],
+            expansion.call.line,
+        ))
+    }
+
+    fn outermost_expansion(&self, line_number: u32) -> Option<&MacroExpansion> {
+        let mut expansion = &self.expansions[self.origins.get(line_number as usize)?.expansion?];
+
+        // Repeated blocks inside of a macro belong to the macro call
+        while expansion.iteration.is_some() {
+            expansion = &self.expansions[expansion.call.expansion?];
+        }
+
+        Some(expansion)
+    }
+
+    /**
+     * The expanded lines that came from a line of the file at `path`. For a line that calls a macro, these are the
+     * lines of the macro body.
+     */
+    pub fn expanded_lines(&self, path: &Path, line_number: u32) -> Vec<u32> {
+        let files: Vec<_> = (0..self.files.len())
+            .filter(|&file| is_same_file(&self.files[file].path, path))
+            .collect();
+
+        (0..self.origins.len() as u32)
+            .filter(|&expanded| {
+                let origin = match self.outermost_expansion(expanded) {
+                    Some(expansion) => expansion.call,
+                    None => self.origins[expanded as usize],
+                };
+
+                files.contains(&origin.file) && origin.line == line_number
+            })
+            .collect()
+    }
+
+    /**
+     * Checks if a line was produced by a macro or repeated block, rather than written as is
+     */
+    pub fn is_expanded(&self, line_number: u32) -> bool {
+        self.origins
+            .get(line_number as usize)
+            .is_some_and(|origin| origin.expansion.is_some())
+    }
+
+    /**
+     * Where the line was expanded from and included from, innermost first
+     */
+    fn notes(&self, line_number: u32) -> Vec<String> {
+        match self.origins.get(line_number as usize) {
+            Some(origin) => self.origin_notes(*origin),
+            None => Vec::new(),
+        }
+    }
+
+    pub(crate) fn origin_notes(&self, mut origin: LineOrigin) -> Vec<String> {
+        let mut notes = Vec::new();
+
+        // Macro calls lead back to a line written in a file, which is what the include chain starts from
+        while let Some(expansion) = origin.expansion {
+            let expansion = &self.expansions[expansion];
+            origin = expansion.call;
+
+            let location = format!(
+                "{}:{}",
+                self.files[origin.file].path.display(),
+                origin.line + 1
+            );
+
+            notes.push(match expansion.iteration {
+                Some(iteration) => format!(
+                    "In pass {} of .{} at {location}",
+                    iteration + 1,
+                    expansion.name
+                ),
+                None => format!("In expansion of macro `{}` at {location}", expansion.name),
+            });
+        }
+
+        let mut file = &self.files[origin.file];
+
+        while let Some((parent, line)) = file.included_from {
+            file = &self.files[parent];
+            notes.push(format!(
+                "Included from {}:{}",
+                file.path.display(),
+                line + 1
+            ));
+        }
+
+        notes
+    }
+}
+
+thread_local! {
+    // Source map of the file being assembled, so errors can be reported against included files
+    static ACTIVE_SOURCE_MAP: RefCell<Option<Rc<SourceMap>>> = const { RefCell::new(None) };
+}
+
+/**
+ * Make errors in `sources.lines` report the file and line they were included from
+ */
+pub fn set_active_source_map(sources: Option<Rc<SourceMap>>) {
+    ACTIVE_SOURCE_MAP.with(|active| *active.borrow_mut() = sources);
+}
+
+/**
+ * Where an error in the expanded source should be reported
+ */
+pub struct ErrorLocation {
+    pub path: PathBuf,
+    pub lines: Vec<String>,
+    pub line_number: u32,
+    // Where the line was expanded and included from
+    pub notes: Vec<String>,
+    // Whether the line comes from a macro or repeated block, so its columns differ from the file
+    pub expanded: bool,
+}
+
+/**
+ * Find the file an error is in, if `lines` are the expanded lines of the file being assembled
+ */
+pub fn locate_error(lines: &[String], line_number: u32) -> Option<ErrorLocation> {
+    ACTIVE_SOURCE_MAP.with(|active| {
+        let active = active.borrow();
+        let sources = active.as_ref()?;
+
+        // Errors in other files, such as .inctable tables, already have the right location
+        if !std::ptr::eq(lines, sources.lines.as_slice()) {
+            return None;
+        }
+
+        let (file, line) = sources.locate(line_number)?;
+
+        let mut lines = file.lines.clone();
+        let expanded = sources.is_expanded(line_number);
+
+        // Show macro body lines with their arguments substituted, which is what the error columns refer to
+        if expanded {
+            lines[line as usize] = sources.lines[line_number as usize].clone();
+        }
+
+        Some(ErrorLocation {
+            path: file.path.clone(),
+            lines,
+            line_number: line,
+            notes: sources.notes(line_number),
+            expanded,
+        })
+    })
+}
+
+/**
+ * Path of the file a line of the source came from, which relative paths in that line are based on
+ */
+pub fn source_path(path: &Path, lines: &[String], line_number: u32) -> PathBuf {
+    match locate_error(lines, line_number) {
+        Some(location) => location.path,
+        None => path.to_owned(),
+    }
+}
+
+/**
+ * Replace every `.include "<file>"` line with the lines of that file.
+ *
+ * Included files are found relative to the file including them, then in each of `include_paths`.
+ * With `ignore_case`, `.INCLUDE` is accepted as well. Included files are decoded with `encoding`.
+ */
+pub fn expand_includes(
+    path: &Path,
+    source: &str,
+    include_paths: &[PathBuf],
+    ignore_case: bool,
+    encoding: Encoding,
+) -> diagnostic::Result<SourceMap> {
+    let mut sources = SourceMap::default();
+
+    let lines = source.lines().map(|string| string.to_owned()).collect();
+
+    expand_file(
+        &mut sources,
+        path.to_owned(),
+        lines,
+        None,
+        include_paths,
+        ignore_case,
+        encoding,
+    )?;
+
+    Ok(sources)
+}
+
+fn expand_file(
+    sources: &mut SourceMap,
+    path: PathBuf,
+    lines: Vec<String>,
+    included_from: Option<(usize, u32)>,
+    include_paths: &[PathBuf],
+    ignore_case: bool,
+    encoding: Encoding,
+) -> diagnostic::Result<()> {
+    let file = sources.files.len();
+
+    // Lines inside block comments are blanked out, so a commented out `.include` or macro is not expanded
+    let code = token::mask_block_comments(&path, &lines)?;
+
+    sources.files.push(SourceFile {
+        path,
+        lines,
+        included_from,
+    });
+
+    let include_re = Regex::new(if ignore_case {
+        r#"^\s*(?i:\.include)\s+"([^"]*)"\s*(;.*)?$"#
+    } else {
+        r#"^\s*\.include\s+"([^"]*)"\s*(;.*)?$"#
+    })
+    .unwrap();
+
+    for (line_number, line) in code.iter().enumerate() {
+        let line_number = line_number as u32;
+
+        let trimmed = line.trim_start();
+
+        let is_include = trimmed.get(..".include".len()).is_some_and(|directive| {
+            directive == ".include" || ignore_case && directive.eq_ignore_ascii_case(".include")
+        }) && trimmed[".include".len()..]
+            .chars()
+            .next()
+            .is_none_or(|c| matches!(c, ' ' | '\t' | '"'));
+
+        if !is_include {
+            sources.lines.push(line.clone());
+            sources.origins.push(LineOrigin {
+                file,
+                line: line_number,
+                expansion: None,
+            });
+            continue;
+        }
+
+        let path = &sources.files[file].path;
+        let lines = &sources.files[file].lines;
+
+        let column_start = (line.chars().count() - trimmed.chars().count()) as u32;
+        let column_end = line.trim_end().chars().count() as u32;
+
+        let Some(captures) = include_re.captures(line) else {
+            return report_error(
+                Code::InvalidDirectiveArgument,
+                "Expected file name string after .include directive!",
+                path,
+                lines,
+                line_number,
+                column_start,
+                column_end,
+            );
+        };
+
+        let file_name = &captures[1];
+
+        let Some(include_path) = find_include(path, file_name, include_paths) else {
+            let searched: Vec<_> = include_paths
+                .iter()
+                .map(|directory| format!("`{}`", directory.display()))
+                .collect();
+
+            let note = if searched.is_empty() {
+                "Included files are found relative to the file including them, add a search path with -I <dir>".to_owned()
+            } else {
+                format!("Also searched the include paths {}", searched.join(", "))
+            };
+
+            return report_error_with_note(
+                Code::UnreadableFile,
+                format!("Could not find included file `{file_name}`!").as_str(),
+                Some(note.as_str()),
+                path,
+                lines,
+                line_number,
+                column_start,
+                column_end,
+            );
+        };
+
+        // A file that includes itself, directly or not, would never finish expanding
+        let mut parent = Some((file, line_number));
+
+        while let Some((ancestor, _)) = parent {
+            if is_same_file(&sources.files[ancestor].path, &include_path) {
+                return report_error(
+                    Code::RecursiveInclude,
+                    format!("File `{file_name}` is already being included, includes cannot be recursive!").as_str(),
+                    path,
+                    lines,
+                    line_number,
+                    column_start,
+                    column_end,
+                );
+            }
+
+            parent = sources.files[ancestor].included_from;
+        }
+
+        let content = match fs::read(&include_path) {
+            Ok(content) => content,
+            Err(err) => {
+                return report_error_with_note(
+                    Code::UnreadableFile,
+                    format!("Could not read included file `{file_name}`: {err}").as_str(),
+                    io_error_note(&err),
+                    path,
+                    lines,
+                    line_number,
+                    column_start,
+                    column_end,
+                )
+            }
+        };
+
+        let content = match encoding.decode(content) {
+            Ok(content) => content,
+            Err(err) => {
+                return invalid_utf8(&include_path, &err)
+                    .with_note(
+                        format!("Included from {}:{}", path.display(), line_number + 1).as_str(),
+                    )
+                    .raise()
+            }
+        };
+
+        let included_lines = content.lines().map(|string| string.to_owned()).collect();
+
+        // The .include line is kept as an empty line so it still shows up in the listing
+        sources.lines.push(String::new());
+        sources.origins.push(LineOrigin {
+            file,
+            line: line_number,
+            expansion: None,
+        });
+
+        expand_file(
+            sources,
+            include_path,
+            included_lines,
+            Some((file, line_number)),
+            include_paths,
+            ignore_case,
+            encoding,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn find_include(path: &Path, file_name: &str, include_paths: &[PathBuf]) -> Option<PathBuf> {
+    let relative = path.parent().unwrap_or(Path::new("")).join(file_name);
+
+    std::iter::once(relative)
+        .chain(
+            include_paths
+                .iter()
+                .map(|directory| directory.join(file_name)),
+        )
+        .find(|candidate| candidate.is_file())
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
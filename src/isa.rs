@@ -0,0 +1,243 @@
+use std::sync::OnceLock;
+
+use crate::parse::Register;
+
+/**
+ * The kind of argument an instruction accepts in one of its operand positions
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandKind {
+    Register,              // %eax
+    Immediate,             // #$F354
+    MemoryAddress,         // $F354
+    Label,                 // boot_loader
+    RegisterIndirect,      // (%ebx)
+    Indexed,               // $8000(%ecx)
+    MemoryAddressIndirect, // ($FFFE)
+}
+
+impl OperandKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperandKind::Register => "register",
+            OperandKind::Immediate => "immediate",
+            OperandKind::MemoryAddress => "address",
+            OperandKind::Label => "label",
+            OperandKind::RegisterIndirect => "register pointer",
+            OperandKind::Indexed => "indexed address",
+            OperandKind::MemoryAddressIndirect => "address pointer",
+        }
+    }
+
+    /**
+     * How the operand kind is written in the ISA spec and in the forms shown in errors
+     */
+    pub fn syntax(&self) -> &'static str {
+        match self {
+            OperandKind::Register => "%reg",
+            OperandKind::Immediate => "#imm",
+            OperandKind::MemoryAddress => "$addr",
+            OperandKind::Label => "label",
+            OperandKind::RegisterIndirect => "(%reg)",
+            OperandKind::Indexed => "index",
+            OperandKind::MemoryAddressIndirect => "($addr)",
+        }
+    }
+
+    fn from_syntax(syntax: &str) -> Option<OperandKind> {
+        let kind = match syntax {
+            "%reg" => OperandKind::Register,
+            "#imm" => OperandKind::Immediate,
+            "$addr" => OperandKind::MemoryAddress,
+            "label" => OperandKind::Label,
+            "(%reg)" => OperandKind::RegisterIndirect,
+            "index" => OperandKind::Indexed,
+            "($addr)" => OperandKind::MemoryAddressIndirect,
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+}
+
+/**
+ * An instruction mnemonic the assembler accepts, its opcode, and every combination of operands it can be given
+ */
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mnemonic {
+    pub name: &'static str,
+    pub opcode: u8,
+    pub signatures: Vec<Vec<OperandKind>>,
+}
+
+/**
+ * Number of I/O ports, `in` and `out` can address ports 0 to `IO_PORTS - 1`
+ */
+pub const IO_PORTS: u16 = 8;
+
+/**
+ * Declarative description of the instruction set, see the comments at the top of the file for the format
+ */
+const ISA_SPEC: &str = include_str!("isa.spec");
+
+static MNEMONICS: OnceLock<Vec<Mnemonic>> = OnceLock::new();
+
+/**
+ * Read the mnemonics out of an ISA spec, grouping the forms of each mnemonic together
+ *
+ * The spec is part of the binary, so a mistake in it is a bug in the assembler and panics.
+ */
+fn parse_spec(spec: &'static str) -> Vec<Mnemonic> {
+    let mut mnemonics: Vec<Mnemonic> = Vec::new();
+
+    for (index, line) in spec.lines().enumerate() {
+        let line = line.split(';').next().unwrap().trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let fail = |reason: &str| -> ! { panic!("ISA spec line {}: {reason}", index + 1) };
+
+        let (opcode, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let (name, operands) = rest
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((rest.trim(), ""));
+        let operands = operands.trim();
+
+        if name.is_empty() {
+            fail("expected a mnemonic after the opcode")
+        }
+
+        let opcode = opcode
+            .strip_prefix("0x")
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .unwrap_or_else(|| fail(&format!("invalid opcode `{opcode}`")));
+
+        let signature = match operands {
+            "" => Vec::new(),
+            operands => operands
+                .split(',')
+                .map(|operand| {
+                    let operand = operand.trim();
+
+                    OperandKind::from_syntax(operand)
+                        .unwrap_or_else(|| fail(&format!("unknown operand kind `{operand}`")))
+                })
+                .collect(),
+        };
+
+        match mnemonics.iter_mut().find(|mnemonic| mnemonic.name == name) {
+            Some(mnemonic) if mnemonic.opcode != opcode => fail(&format!(
+                "`{name}` already has opcode {:#04X}",
+                mnemonic.opcode
+            )),
+            Some(mnemonic) => mnemonic.signatures.push(signature),
+            None => {
+                if let Some(other) = mnemonics.iter().find(|mnemonic| mnemonic.opcode == opcode) {
+                    fail(&format!(
+                        "opcode {opcode:#04X} is already assigned to `{}`",
+                        other.name
+                    ))
+                }
+
+                mnemonics.push(Mnemonic {
+                    name,
+                    opcode,
+                    signatures: vec![signature],
+                })
+            }
+        }
+    }
+
+    mnemonics
+}
+
+impl Mnemonic {
+    /**
+     * Every mnemonic the assembler accepts, in the order of the ISA spec
+     */
+    pub fn all() -> &'static [Mnemonic] {
+        MNEMONICS.get_or_init(|| parse_spec(ISA_SPEC))
+    }
+
+    pub fn from_name(name: &str) -> Option<&'static Mnemonic> {
+        Mnemonic::all()
+            .iter()
+            .find(|mnemonic| mnemonic.name == name)
+    }
+
+    /**
+     * Every form of the instruction written out as in source, such as `mov %reg, #imm`
+     */
+    pub fn forms(&self) -> Vec<String> {
+        self.signatures
+            .iter()
+            .map(|signature| {
+                let operands: Vec<_> = signature.iter().map(|kind| kind.syntax()).collect();
+
+                if operands.is_empty() {
+                    self.name.to_owned()
+                } else {
+                    format!("{} {}", self.name, operands.join(", "))
+                }
+            })
+            .collect()
+    }
+}
+
+impl Register {
+    /**
+     * Every register, 8-bit registers first
+     */
+    pub fn all() -> &'static [Register] {
+        &[
+            Register::AX,
+            Register::BX,
+            Register::CX,
+            Register::DX,
+            Register::EX,
+            Register::EAX,
+            Register::EBX,
+            Register::ECX,
+            Register::EDX,
+            Register::EEX,
+            Register::SP,
+            Register::PC,
+            Register::FLAGS,
+        ]
+    }
+
+    /**
+     * Size of the register in bits
+     */
+    pub fn width(&self) -> u8 {
+        match self {
+            Register::AX | Register::BX | Register::CX | Register::DX | Register::EX => 8,
+            Register::EAX | Register::EBX | Register::ECX | Register::EDX | Register::EEX => 16,
+            Register::SP | Register::PC | Register::FLAGS => 16,
+        }
+    }
+
+    /**
+     * Instructions that accept the register, or `None` if it is general purpose
+     *
+     * The special registers are limited to the instructions the CPU wires them to
+     */
+    pub fn allowed_mnemonics(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Register::SP => Some(&["mov", "add", "sub", "inc", "dec", "cmp", "push", "pop"]),
+            Register::PC => Some(&["mov", "push"]),
+            Register::FLAGS => Some(&["mov", "push", "pop"]),
+            _ => None,
+        }
+    }
+
+    /**
+     * Whether the register can hold an address for a register indirect or indexed operand
+     */
+    pub fn can_address(&self) -> bool {
+        !matches!(self, Register::PC | Register::FLAGS)
+    }
+}
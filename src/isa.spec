@@ -0,0 +1,111 @@
+; The SIS16 instruction set
+;
+; Each line is one form of an instruction: its opcode, mnemonic and operand kinds, destination first. The encoder
+; takes opcodes from here, and operands that match none of the forms of an instruction are reported with this list.
+;
+; Operand kinds:
+;   %reg     register
+;   #imm     immediate
+;   $addr    memory address
+;   ($addr)  memory at the address stored at a memory address
+;   (%reg)   memory at the address held in a register
+;   index    indexed address, such as `table(%ecx)`
+;   label    address of a label
+;
+; opcode  mnemonic  operands
+0x00    nop
+0x01    mov       $addr, %reg
+0x01    mov       %reg, $addr
+0x01    mov       %reg, #imm
+0x01    mov       %reg, %reg
+0x01    mov       $addr, #imm
+0x01    mov       (%reg), %reg
+0x01    mov       %reg, (%reg)
+0x01    mov       (%reg), #imm
+0x01    mov       index, %reg
+0x01    mov       %reg, index
+0x02    add       %reg
+0x02    add       #imm
+0x02    add       %reg, %reg
+0x02    add       %reg, #imm
+0x03    inc
+0x03    inc       %reg
+0x04    dec
+0x04    dec       %reg
+0x05    jmp       #imm
+0x05    jmp       %reg
+0x05    jmp       $addr
+0x05    jmp       label
+0x06    jsr       #imm
+0x06    jsr       %reg
+0x06    jsr       $addr
+0x06    jsr       ($addr)
+0x06    jsr       label
+0x07    ret
+0x08    syscall
+0x09    ssc       #imm
+0x0A    push      #imm
+0x0A    push      $addr
+0x0A    push      %reg
+0x0B    pop       $addr
+0x0B    pop       %reg
+0x0C    sub       %reg
+0x0C    sub       #imm
+0x0C    sub       %reg, %reg
+0x0C    sub       %reg, #imm
+0x0D    mul       %reg
+0x0D    mul       #imm
+0x0D    mul       %reg, %reg
+0x0D    mul       %reg, #imm
+0x0E    div       %reg
+0x0E    div       #imm
+0x0E    div       %reg, %reg
+0x0E    div       %reg, #imm
+0x0F    and       %reg
+0x0F    and       #imm
+0x0F    and       %reg, %reg
+0x0F    and       %reg, #imm
+0x10    or        %reg
+0x10    or        #imm
+0x10    or        %reg, %reg
+0x10    or        %reg, #imm
+0x11    xor       %reg
+0x11    xor       #imm
+0x11    xor       %reg, %reg
+0x11    xor       %reg, #imm
+0x12    not
+0x12    not       %reg
+0x13    shl       %reg, #imm
+0x13    shl       %reg, %reg
+0x14    shr       %reg, #imm
+0x14    shr       %reg, %reg
+0x15    rol       %reg, #imm
+0x15    rol       %reg, %reg
+0x16    ror       %reg, #imm
+0x16    ror       %reg, %reg
+0x17    cmp       %reg
+0x17    cmp       #imm
+0x17    cmp       %reg, %reg
+0x17    cmp       %reg, #imm
+0x18    beq       label
+0x19    bne       label
+0x1A    blt       label
+0x1B    bge       label
+0x1C    bcs       label
+0x1D    bcc       label
+0x1E    mov.b     $addr, #imm
+0x1E    mov.b     (%reg), #imm
+0x1F    pusha
+0x20    popa
+0x21    xchg      %reg, %reg
+0x21    xchg      %reg, $addr
+0x21    xchg      $addr, %reg
+0x22    hlt
+0x23    brk
+0x24    in        %reg, #imm
+0x24    in        %reg, %reg
+0x25    out       #imm, %reg
+0x25    out       %reg, %reg
+0x26    cli
+0x27    sei
+0x28    rti
@@ -1,116 +1,791 @@
-use std::fs;
 use ansi_term::Colour;
-use std::{collections::HashSet, path::PathBuf};
+use std::fs;
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
+mod capabilities;
+mod checksum;
+mod code;
+mod compile;
+mod cst;
+mod diagnostic;
+mod encoding;
+mod expr;
+mod fix;
+mod format;
+#[cfg(feature = "fuzz")]
+mod fuzz;
+mod include;
+mod isa;
+mod json;
+mod lint;
+mod macros;
+mod manifest;
 mod parse;
+mod preprocess;
+mod refactor;
+mod resolve;
+mod sarif;
+mod suggest;
+mod table;
 mod token;
 
+pub use capabilities::{capabilities, Capabilities, API_VERSION};
+pub use checksum::ChecksumAlgorithm;
+pub use code::Code;
+pub use compile::{Assembly, Checksum, Endian, Fragment, FragmentKind, Operand};
+pub use cst::{LineKind, SyntaxKind, SyntaxLine, SyntaxToken, SyntaxTree};
+pub use diagnostic::{
+    set_color_choice, set_diagnostic_paths, ColorChoice, Diagnostic, DiagnosticPaths, Fix, Label,
+    Location, MessageFormat, Severity, Warning, WarningOptions,
+};
+pub use encoding::Encoding;
+pub use fix::apply_fixes;
+pub use format::{
+    BinWriter, HexdumpWriter, IntelHexWriter, OutputFormat, OutputWriter, SRecordWriter,
+    Sis16ExeWriter,
+};
+#[cfg(feature = "fuzz")]
+pub use fuzz::fuzz_assemble;
+pub use include::{SourceFile, SourceMap};
+pub use isa::{Mnemonic, OperandKind};
+pub use manifest::{verify_manifest, ManifestCheck};
+pub use parse::Register;
+pub use refactor::{
+    find_label_references, is_valid_label_name, rename_label, rename_label_in_files, LabelReference,
+};
+pub use resolve::{Section, Symbol};
+pub use sarif::sarif_log;
+pub use token::{Span, TokenType};
+
+use diagnostic::Diagnostics;
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct AssemblerArguments {
     pub file_name: String,
     pub output_path: String,
     pub debug: bool,
-    pub defines: HashSet<String>,
+    pub annotate: bool,
+    pub format: OutputFormat,
+    // Names defined with -D, along with their value if given as NAME=VALUE
+    pub defines: HashMap<String, Option<u16>>,
+    // Directories searched for .include files, after the directory of the including file
+    pub include_paths: Vec<PathBuf>,
+    // Load address of .text, defaults to $0000
+    pub text_org: Option<u16>,
+    // Load address of .data, defaults to directly after .text
+    pub data_org: Option<u16>,
+    // Exact size of the output image in bytes, padded with the fill byte
+    pub rom_size: Option<u32>,
+    // Byte used for gaps and padding, defaults to $FF with a ROM size and $00 without
+    pub fill: Option<u8>,
+    // Byte order of words, unless the source sets it with .endian
+    pub endian: Endian,
+    // Checksum appended to the image, or written where the source has a .checksum
+    pub checksum: Option<ChecksumAlgorithm>,
+    // Write an integrity manifest next to the output
+    pub manifest: bool,
+    // Match instruction mnemonics and directives regardless of case
+    pub ignore_case: bool,
+    // Columns between tab stops when source lines are shown in errors
+    pub tab_width: u32,
+    // Use the long form of branches whose target is out of reach of a short branch
+    pub relax: bool,
+    // Warnings to report, and whether they are errors
+    pub warnings: WarningOptions,
+    // How the command line tool prints warnings and errors
+    pub message_format: MessageFormat,
+    // Apply the fixes suggested by diagnostics to the source files
+    pub fix: bool,
+    // When the command line tool colours its messages
+    pub color: ColorChoice,
+    // Character encoding of the source file and the files it includes
+    pub encoding: Encoding,
+    // Errors to report before assembly stops, or 0 to report them all
+    pub max_errors: u32,
+    // Whether the command line tool shows paths in messages in full or relative to the working directory
+    pub diagnostic_paths: DiagnosticPaths,
+    // Show the explanation of each kind of error after it is first printed by the command line tool
+    pub explain_on_error: bool,
 }
 
-pub fn assemble_file(args: AssemblerArguments) {
-    let path = PathBuf::from(args.file_name);
-
-    // Check if input file exists
-    if !&path.exists() {
-        println!("Path {path:?} does not exist!");
-        std::process::exit(1);
+impl Default for AssemblerArguments {
+    /**
+     * The options the command line tool uses when none are given, with no input or output file
+     */
+    fn default() -> AssemblerArguments {
+        AssemblerArguments {
+            file_name: String::new(),
+            output_path: String::new(),
+            debug: false,
+            annotate: false,
+            format: OutputFormat::Bin,
+            defines: HashMap::new(),
+            include_paths: Vec::new(),
+            text_org: None,
+            data_org: None,
+            rom_size: None,
+            fill: None,
+            endian: Endian::default(),
+            checksum: None,
+            manifest: false,
+            ignore_case: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            relax: true,
+            warnings: WarningOptions::default(),
+            message_format: MessageFormat::default(),
+            fix: false,
+            color: ColorChoice::default(),
+            encoding: Encoding::default(),
+            max_errors: DEFAULT_MAX_ERRORS,
+            diagnostic_paths: DiagnosticPaths::default(),
+            explain_on_error: false,
+        }
     }
+}
+
+/**
+ * Tab width used to show source lines in errors when none is given
+ */
+pub const DEFAULT_TAB_WIDTH: u32 = 4;
+
+/**
+ * Errors reported before assembly stops when no limit is given
+ */
+pub const DEFAULT_MAX_ERRORS: u32 = 20;
+
+/**
+ * Exit code when the source has an error
+ */
+pub const EXIT_ASSEMBLY_ERROR: i32 = 1;
+
+/**
+ * Exit code when a file could not be read or written (EX_IOERR from sysexits.h)
+ */
+pub const EXIT_IO_ERROR: i32 = 74;
+
+/**
+ * Assemble the file named in `args` and write the outputs next to `args.output_path`.
+ *
+ * Returns the warnings found, or every diagnostic if the source could not be assembled, in which case nothing
+ * is written.
+ */
+pub fn assemble_file(args: AssemblerArguments) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let path = PathBuf::from(&args.file_name);
 
     // Read entire file
-    let content = fs::read(&path).expect("Could not read file");
+    let content = fs::read(&path).map_err(|err| {
+        vec![io_error(
+            Code::UnreadableFile,
+            "read source file",
+            &path,
+            &err,
+        )]
+    })?;
+
+    let content = args
+        .encoding
+        .decode(content)
+        .map_err(|err| vec![encoding::invalid_utf8(&path, &err)])?;
+
+    let assembly = assemble_source(&path, &content, &args)?;
+
+    // A SARIF log is written to stdout, so everything else goes to stderr to keep the log valid. The messages are
+    // only informational, so failing to print them does not stop the outputs being written.
+    let _ = match args.message_format {
+        MessageFormat::Human => print_output(&assembly, args.annotate, &mut io::stdout()),
+        MessageFormat::Sarif => print_output(&assembly, args.annotate, &mut io::stderr()),
+    };
+
+    // The warnings are still shown when an output cannot be written
+    match write_outputs(&args, &assembly) {
+        Ok(()) => Ok(assembly.warnings),
+        Err(error) => Err(assembly.warnings.into_iter().chain([*error]).collect()),
+    }
+}
+
+/**
+ * Write the output file in the requested format, and the files that go alongside it
+ */
+fn write_outputs(args: &AssemblerArguments, assembly: &Assembly) -> Result<(), Box<Diagnostic>> {
+    let output_path = Path::new(&args.output_path);
+
+    let mut output = fs::File::create(output_path).map_err(|err| {
+        io_error(
+            Code::UnwritableFile,
+            "create output file",
+            output_path,
+            &err,
+        )
+    })?;
+
+    args.format
+        .writer()
+        .write(assembly, &mut output)
+        .map_err(|err| io_error(Code::UnwritableFile, "write output file", output_path, &err))?;
+
+    // Write the symbol table alongside the binary
+    write_file(
+        "symbol file",
+        &output_path.with_extension("sym"),
+        assembly.symbol_file(),
+    )?;
+
+    // Write the source map for source-level debugging
+    if args.debug {
+        write_file(
+            "source map",
+            &output_path.with_extension("debug.json"),
+            assembly.source_map(&args.file_name),
+        )?;
+    }
+
+    // Write the manifest used by `spasm verify`
+    if args.manifest {
+        write_file(
+            "manifest",
+            &output_path.with_extension("manifest.json"),
+            assembly.manifest(),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_file(description: &str, path: &Path, contents: String) -> Result<(), Box<Diagnostic>> {
+    fs::write(path, contents).map_err(|err| {
+        io_error(
+            Code::UnwritableFile,
+            format!("write {description}").as_str(),
+            path,
+            &err,
+        )
+    })?;
+
+    Ok(())
+}
 
-    // Parse the file as a utf-8 encoded string
-    let content = String::from_utf8(content).expect("Could not parse file as utf-8");
+/**
+ * An error for a file that could not be read or written, with the error from the OS and a hint on how to fix it
+ */
+pub fn io_error(code: Code, action: &str, path: &Path, error: &io::Error) -> Diagnostic {
+    let message = format!("Could not {action} `{}`: {error}", path.display());
+    let diagnostic = Diagnostic::new(Severity::Error, code, message.as_str());
 
-    // Map the file contents into a Vec of lines
-    let lines: Vec<_> = content.lines().map(|string| string.to_owned()).collect();
+    match io_error_note(error) {
+        Some(note) => diagnostic.with_note(note),
+        None => diagnostic,
+    }
+}
+
+/**
+ * How to fix an error from the OS, for the kinds of error that have one likely cause
+ */
+pub(crate) fn io_error_note(error: &io::Error) -> Option<&'static str> {
+    match error.kind() {
+        io::ErrorKind::NotFound => {
+            Some("Check that the path is spelled correctly and that its directory exists")
+        }
+        io::ErrorKind::PermissionDenied => {
+            Some("Check that you are allowed to access the file and its directory")
+        }
+        io::ErrorKind::IsADirectory => {
+            Some("The path is a directory, give the path of a file instead")
+        }
+        io::ErrorKind::StorageFull => Some("The disk is full, free up some space and try again"),
+        io::ErrorKind::ReadOnlyFilesystem => {
+            Some("The file system is read only, choose another output path with -o")
+        }
+        _ => None,
+    }
+}
+
+/**
+ * Assemble source code that is already in memory, such as an editor buffer.
+ *
+ * `path` is only used when reporting errors. Errors in the source are returned rather than printed.
+ */
+pub fn assemble_source(
+    path: &Path,
+    source: &str,
+    args: &AssemblerArguments,
+) -> Result<Assembly, Vec<Diagnostic>> {
+    let assembly = assemble(path, source, args);
+
+    // An error returns early from assemble, which would leave the source map of the failed file active
+    include::set_active_source_map(None);
+
+    assembly
+}
+
+fn assemble(
+    path: &Path,
+    source: &str,
+    args: &AssemblerArguments,
+) -> Result<Assembly, Vec<Diagnostic>> {
+    let mut diagnostics = Diagnostics::new(args.max_errors, args.warnings.clone());
+
+    // Map the file contents into a Vec of lines, with the contents of included files in place of each .include
+    // and the body of a macro in place of each call or repeated block
+    let sources = include::expand_includes(
+        path,
+        source,
+        &args.include_paths,
+        args.ignore_case,
+        args.encoding,
+    )
+    .map_err(|error| diagnostics.fail(*error))?;
+    let sources = macros::expand_macros(sources, &args.defines, args.ignore_case)
+        .map_err(|error| diagnostics.fail(*error))?;
+    let sources = Rc::new(sources);
+    let lines = &sources.lines;
+
+    // Errors in included files are reported against the file they are in
+    include::set_active_source_map(Some(sources.clone()));
+    diagnostic::TAB_WIDTH.set(args.tab_width);
 
     // Lex the file into a token vector
-    let mut tokens = token::tokenize_lines(&path, &lines);
+    let mut tokens = token::tokenize_lines(path, lines, &mut diagnostics)
+        .map_err(|error| diagnostics.fail(*error))?;
+
+    if args.ignore_case {
+        token::fold_case(&mut tokens);
+    }
+
+    // Every line is lexed even if some have errors, but parsing lines with tokens missing would only add more
+    diagnostics.stop_on_errors()?;
+
+    // Drop everything excluded by conditional assembly
+    let (mut tokens, condition_names) =
+        preprocess::preprocess(path, lines, &mut tokens, &args.defines, args.relax)
+            .map_err(|error| diagnostics.fail(*error))?;
+
+    // Give local and numeric labels names that are unique in the program
+    preprocess::scope_local_labels(path, lines, &mut tokens)
+        .map_err(|error| diagnostics.fail(*error))?;
 
     // Build the program from the token vector
-    let program = parse::build_program(&path, &lines, &mut tokens);
+    let mut program = parse::build_program(path, lines, &mut tokens, &mut diagnostics)
+        .map_err(|error| diagnostics.fail(*error))?;
+
+    // References to a label declared twice would be ambiguous
+    resolve::check_duplicate_labels(path, lines, &program, &mut diagnostics)
+        .map_err(|error| diagnostics.fail(*error))?;
+
+    // Lines that could not be parsed are missing from the program, so stop before their labels are looked up
+    diagnostics.stop_on_errors()?;
+
+    // Warn about instructions that have no effect
+    lint::lint_program(path, lines, &program, &mut diagnostics)
+        .map_err(|error| diagnostics.fail(*error))?;
+
+    // Resolve all labels, making branches long where they have to be
+    let layout = resolve::relax_branches(
+        path,
+        lines,
+        &mut program,
+        args.text_org.unwrap_or(0),
+        args.data_org,
+        args.relax,
+        &mut diagnostics,
+    )
+    .map_err(|error| diagnostics.fail(*error))?;
+
+    // Check .assert conditions and evaluate .print messages now that every label has an address
+    resolve::check_assertions(
+        path,
+        lines,
+        &program,
+        &layout,
+        &args.defines,
+        &mut diagnostics,
+    )
+    .map_err(|error| diagnostics.fail(*error))?;
+    let messages = resolve::evaluate_messages(
+        path,
+        lines,
+        &program,
+        &layout,
+        &args.defines,
+        &mut diagnostics,
+    )
+    .map_err(|error| diagnostics.fail(*error))?;
+    let values = resolve::evaluate_values(
+        path,
+        lines,
+        &program,
+        &layout,
+        &args.defines,
+        &mut diagnostics,
+    )
+    .map_err(|error| diagnostics.fail(*error))?;
+
+    // Every unknown label has been reported by now, and the values they were used in are missing
+    diagnostics.stop_on_errors()?;
 
-    println!("{program:#?}")
+    // Warn about labels that nothing refers to, now that every reference is known to resolve
+    lint::lint_unused_labels(path, lines, &program, &condition_names, &mut diagnostics)
+        .map_err(|error| diagnostics.fail(*error))?;
 
-    // TODO - Resolve all labels
+    // Warnings turned into errors by -Werror stop assembly like any other error
+    diagnostics.stop_on_errors()?;
 
-    // TODO - Compile into binary output file
+    // Compile into the final binary
+    let endian = program
+        .endian
+        .as_ref()
+        .map(|endian| endian.node)
+        .unwrap_or(args.endian);
+
+    let mut assembly = compile::compile_program(&program, layout, values, endian);
+    assembly.messages = messages;
+
+    // Unprogrammed EEPROM bytes read as $FF
+    assembly.fill = args
+        .fill
+        .unwrap_or(if args.rom_size.is_some() { 0xFF } else { 0x00 });
+
+    if let Some(rom_size) = args.rom_size {
+        check_rom_size(path, lines, &assembly, rom_size)
+            .map_err(|error| diagnostics.fail(*error))?;
+
+        assembly.rom_size = Some(rom_size);
+    }
+
+    if let Some(algorithm) = args.checksum {
+        place_checksum(path, lines, &mut assembly, algorithm, endian)
+            .map_err(|error| diagnostics.fail(*error))?;
+    }
+
+    assembly.patch_checksum();
+
+    include::set_active_source_map(None);
+    assembly.sources = Rc::unwrap_or_clone(sources);
+    assembly.warnings = diagnostics.into_warnings();
+
+    Ok(assembly)
 }
 
-pub fn report_error(
-    error: &str,
-    path: &PathBuf,
-    lines: &Vec<String>,
-    line_number: u32,
-    col_start: u32,
-    col_end: u32,
-) -> ! {
-    // Print error message
-    eprintln!(
-        "{} {}",
-        Colour::Red.bold().paint("[ERROR]"),
-        Colour::Red.paint(error)
-    );
-
-    // Print the file path with the line and col number
-    eprintln!(
-        "{}",
-        Colour::Fixed(246).paint(format!(
-            "{}:{}:{}",
-            // Conanicalization is platform specific
-            if cfg!(target_os = "windows") {
-                let path = fs::canonicalize(path).unwrap();
-                path.to_str()
-                    .unwrap()
-                    .trim_start_matches("\\\\?\\")
-                    .to_owned()
-            } else {
-                let path = fs::canonicalize(path).unwrap();
-                path.to_str().unwrap().to_owned()
-            },
-            line_number + 1,
-            col_start + 1
-        ))
-    );
-
-    // Print the lines around and including the one with the error
-    let start = if line_number < 2 { 0 } else { line_number - 2 };
-
-    for n in start..line_number + 1 {
-        eprintln!(
-            "{}: {}",
-            Colour::Blue.paint(format!("{:>3}", n + 1)),
-            lines.get(n as usize).unwrap()
+/**
+ * Put the checksum from `--checksum` at the end of the image, or in the last bytes of the ROM if it has a size.
+ *
+ * A `.checksum` in the source already marks where it goes, so it only has to use the same algorithm.
+ */
+fn place_checksum(
+    path: &Path,
+    lines: &[String],
+    assembly: &mut Assembly,
+    algorithm: ChecksumAlgorithm,
+    endian: Endian,
+) -> diagnostic::Result<()> {
+    if let Some(checksum) = assembly.checksum {
+        if checksum.algorithm != algorithm {
+            let fragment = assembly
+                .fragments
+                .iter()
+                .find(|fragment| fragment.address == checksum.address)
+                .unwrap();
+
+            return report_span_error(
+                Code::ChecksumMismatch,
+                format!(
+                    "Checksum directive uses `{}` but `--checksum {}` was given!",
+                    checksum.algorithm.name(),
+                    algorithm.name()
+                )
+                .as_str(),
+                path,
+                lines,
+                fragment.span,
+            );
+        }
+
+        return Ok(());
+    }
+
+    let size = algorithm.size() as u32;
+
+    let address = match assembly.rom_size {
+        Some(rom_size) => (assembly.origin() as u32 + rom_size).saturating_sub(size),
+        None => assembly
+            .fragments
+            .iter()
+            .map(|fragment| fragment.address as u32 + fragment.size() as u32)
+            .max()
+            .unwrap_or(0),
+    };
+
+    if let Some(fragment) = assembly.fragments.iter().find(|fragment| {
+        fragment.address as u32 + fragment.size() as u32 > address
+            || address + size > u16::MAX as u32 + 1
+    }) {
+        return report_span_error(
+            Code::ProgramTooLarge,
+            format!("No room for the {size} byte checksum at ${address:04X} after the program!")
+                .as_str(),
+            path,
+            lines,
+            fragment.span,
         );
     }
 
-    // Print the space before the highlight
-    for _ in 0..col_start + 5 {
-        eprint!(" ");
+    assembly.checksum = Some(Checksum {
+        algorithm,
+        address: address as u16,
+        endian,
+    });
+
+    Ok(())
+}
+
+/**
+ * Make sure the whole image fits in a ROM of `rom_size` bytes starting at the origin
+ */
+fn check_rom_size(
+    path: &Path,
+    lines: &[String],
+    assembly: &Assembly,
+    rom_size: u32,
+) -> diagnostic::Result<()> {
+    let end = assembly.origin() as u32 + rom_size;
+
+    if let Some(fragment) = assembly
+        .fragments
+        .iter()
+        .find(|fragment| fragment.address as u32 + fragment.size() as u32 > end)
+    {
+        return report_span_error(
+            Code::ProgramTooLarge,
+            format!(
+                "Program does not fit in the {rom_size} byte ROM! (It ends at ${:04X})",
+                end - 1
+            )
+            .as_str(),
+            path,
+            lines,
+            fragment.span,
+        );
     }
 
-    // Print the underline highlight
-    for _ in col_start..col_end {
-        eprint!("{}", Colour::Red.paint("^"));
+    Ok(())
+}
+
+/**
+ * Print the messages from the source to `stream`, and the annotated listing if `annotate` is set
+ */
+fn print_output(
+    assembly: &Assembly,
+    annotate: bool,
+    stream: &mut (impl Write + IsTerminal),
+) -> io::Result<()> {
+    print_messages(assembly, stream)?;
+
+    if annotate {
+        print_annotated_listing(assembly, stream)?;
     }
 
-    eprintln!("");
+    Ok(())
+}
+
+/**
+ * Print the text of every .print and .info directive along with where it is in the source
+ */
+fn print_messages(assembly: &Assembly, stream: &mut (impl Write + IsTerminal)) -> io::Result<()> {
+    for message in &assembly.messages {
+        let location = match assembly.sources.locate(message.span.line_number) {
+            Some((file, line_number)) => format!("{}:{}", file.path.display(), line_number + 1),
+            None => format!("line {}", message.span.line_number + 1),
+        };
 
-    // Print the space before "here"
-    for _ in 0..col_start + 5 {
-        eprint!(" ");
+        writeln!(
+            stream,
+            "{} {} {}",
+            diagnostic::style_for(stream, Colour::Green.bold()).paint("[INFO]"),
+            diagnostic::style_for(stream, Colour::Fixed(246)).paint(location),
+            message.node
+        )?;
     }
 
-    eprintln!("{}", Colour::Red.paint("here"));
+    Ok(())
+}
+
+/**
+ * Print every source line next to the address and bytes it assembled to
+ */
+fn print_annotated_listing(assembly: &Assembly, stream: &mut impl Write) -> io::Result<()> {
+    const BYTES_PER_ROW: usize = 6;
+
+    for (line_number, line) in assembly.sources.lines.iter().enumerate() {
+        // Included files are listed in place, numbered by their own lines, and macro bodies with their arguments
+        let (line, source_line_number) = match assembly.sources.locate(line_number as u32) {
+            Some((_, source_line_number))
+                if !line.is_empty() && assembly.sources.is_expanded(line_number as u32) =>
+            {
+                (line, source_line_number)
+            }
+            Some((file, source_line_number)) => {
+                (&file.lines[source_line_number as usize], source_line_number)
+            }
+            None => (line, line_number as u32),
+        };
+
+        let mut rows: Vec<(u16, &[u8])> = Vec::new();
+
+        for fragment in assembly.fragments_on_line(line_number as u32) {
+            for (i, chunk) in fragment.bytes.chunks(BYTES_PER_ROW).enumerate() {
+                rows.push((fragment.address + (i * BYTES_PER_ROW) as u16, chunk));
+            }
+        }
+
+        let Some(((address, bytes), rest)) = rows.split_first() else {
+            writeln!(
+                stream,
+                "{:4}  {:18}  {:>3}: {line}",
+                "",
+                "",
+                source_line_number + 1
+            )?;
+            continue;
+        };
+
+        // Bytes from a macro body also name the call they were expanded from
+        let expansion = match assembly.sources.macro_call(line_number as u32) {
+            Some((name, file, call_line_number)) => {
+                format!(
+                    "  ; expanded from `{name}` at {}:{}",
+                    file.path.display(),
+                    call_line_number + 1
+                )
+            }
+            None => String::new(),
+        };
+
+        writeln!(
+            stream,
+            "{address:04X}  {:18}  {:>3}: {line}{expansion}",
+            format_bytes(bytes),
+            source_line_number + 1
+        )?;
+
+        for (address, bytes) in rest {
+            writeln!(stream, "{address:04X}  {}", format_bytes(bytes))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn format_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/**
+ * Report an error that covers everything in `span`, with a suggestion below the snippet if there is one
+ */
+pub(crate) fn report_span_error_with_help<T>(
+    code: Code,
+    error: &str,
+    help: Option<&str>,
+    path: &Path,
+    lines: &[String],
+    span: Span,
+) -> diagnostic::Result<T> {
+    let diagnostic = Diagnostic::at(Severity::Error, code, error, None, path, lines, span);
+
+    match help {
+        Some(help) => diagnostic.with_help(help).raise(),
+        None => diagnostic.raise(),
+    }
+}
+
+/**
+ * Report an error that covers everything in `span`, also underlining `label_span` with `label` below it
+ */
+pub(crate) fn report_span_error_with_label<T>(
+    code: Code,
+    error: &str,
+    path: &Path,
+    lines: &[String],
+    span: Span,
+    label: &str,
+    label_span: Span,
+) -> diagnostic::Result<T> {
+    Diagnostic::at(Severity::Error, code, error, None, path, lines, span)
+        .with_label(label, path, lines, label_span)
+        .raise()
+}
+
+/**
+ * Report an error that covers everything in `span`
+ */
+pub(crate) fn report_span_error<T>(
+    code: Code,
+    error: &str,
+    path: &Path,
+    lines: &[String],
+    span: Span,
+) -> diagnostic::Result<T> {
+    report_error(
+        code,
+        error,
+        path,
+        lines,
+        span.line_number,
+        span.column_start,
+        span.column_end,
+    )
+}
+
+pub fn report_error<T>(
+    code: Code,
+    error: &str,
+    path: &Path,
+    lines: &[String],
+    line_number: u32,
+    col_start: u32,
+    col_end: u32,
+) -> diagnostic::Result<T> {
+    report_error_with_note(
+        code,
+        error,
+        None,
+        path,
+        lines,
+        line_number,
+        col_start,
+        col_end,
+    )
+}
+
+/**
+ * Report an error with a note below the snippet explaining how to fix it
+ */
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn report_error_with_note<T>(
+    code: Code,
+    error: &str,
+    note: Option<&str>,
+    path: &Path,
+    lines: &[String],
+    line_number: u32,
+    col_start: u32,
+    col_end: u32,
+) -> diagnostic::Result<T> {
+    let span = Span {
+        line_number,
+        column_start: col_start,
+        column_end: col_end,
+    };
 
-    // Exit with non-zero code to signal an error occurred
-    std::process::exit(1);
+    // Errors are returned up to the entry point, so the caller decides what to do
+    Diagnostic::at(Severity::Error, code, error, note, path, lines, span).raise()
 }
@@ -0,0 +1,311 @@
+use std::{collections::HashSet, path::Path};
+
+use crate::{
+    compile::Operand,
+    diagnostic::{self, Diagnostics, Warning},
+    expr::Expression,
+    parse::{
+        ConstantLabelType, DeferredOperand, Instruction, Program, SubroutineItem, SubroutineLabel,
+    },
+    preprocess,
+    token::Span,
+};
+
+/**
+ * Warn about instructions that assemble fine but have no effect, such as `mov %eax, %eax`, `add %eax, #0`
+ * or a jump to the instruction right after it
+ */
+pub fn lint_program(
+    path: &Path,
+    lines: &[String],
+    program: &Program,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<()> {
+    let sections = program
+        .text
+        .iter()
+        .chain(program.sections.iter().map(|section| &section.text));
+
+    for text in sections {
+        for (index, label) in text.labels.iter().enumerate() {
+            for item in &label.contents {
+                let SubroutineItem::Instruction {
+                    instruction,
+                    deferred,
+                } = &item.node
+                else {
+                    continue;
+                };
+
+                if is_redundant(instruction, deferred) {
+                    diagnostics.warn(
+                        Warning::Redundant,
+                        format!("`{}` has no effect!", source_text(lines, item.span)).as_str(),
+                        path,
+                        lines,
+                        item.span,
+                    )?;
+                }
+            }
+
+            // A jump at the end of a label that lands on the label after it only falls through
+            let Some(last) = label.contents.last() else {
+                continue;
+            };
+
+            let SubroutineItem::Instruction { instruction, .. } = &last.node else {
+                continue;
+            };
+
+            let target = match instruction {
+                Instruction::jmp_Label(target) => target,
+                instruction => match instruction.branch() {
+                    Some(branch) => &branch.label,
+                    None => continue,
+                },
+            };
+
+            if falls_through_to(&text.labels[index + 1..], target) {
+                diagnostics.warn(
+                    Warning::Redundant,
+                    format!(
+                        "`{}` has no effect, it jumps to the next instruction!",
+                        source_text(lines, last.span)
+                    )
+                    .as_str(),
+                    path,
+                    lines,
+                    last.span,
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * Warn about labels that nothing refers to, such as a table that is no longer read or a subroutine that is no
+ * longer called.
+ *
+ * Labels exported with .global, the entry point and labels that execution reaches on its own are used even without
+ * a reference: the first label of .text when there is no .entry, code labels that the label before them runs into,
+ * and labels placed with .org. `condition_names` are the names used by `.if` conditions, which count as references.
+ */
+pub fn lint_unused_labels(
+    path: &Path,
+    lines: &[String],
+    program: &Program,
+    condition_names: &HashSet<String>,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<()> {
+    let mut used = referenced_labels(program);
+    used.extend(condition_names.iter().map(String::as_str));
+
+    let code_sections = program
+        .text
+        .iter()
+        .map(|text| (text, program.entry.is_none()))
+        .chain(
+            program
+                .sections
+                .iter()
+                .map(|section| (&section.text, false)),
+        );
+
+    let mut labels = Vec::new();
+
+    for (text, starts_execution) in code_sections {
+        for (index, label) in text.labels.iter().enumerate() {
+            let reached = match index {
+                0 => starts_execution,
+                _ => falls_through(&text.labels[index - 1]),
+            };
+
+            if !reached && label.origin.is_none() {
+                labels.push((&label.name, label.span));
+            }
+        }
+    }
+
+    let data_labels = program
+        .data
+        .iter()
+        .chain(&program.bss)
+        .flat_map(|data| &data.labels)
+        .filter(|label| label.origin.is_none())
+        .map(|label| (&label.name, label.span));
+
+    labels.extend(data_labels);
+    labels.sort_by_key(|(_, span)| (span.line_number, span.column_start));
+
+    for (name, span) in labels {
+        // Numeric and anonymous labels are only there to be jumped to nearby, and have no name of their own
+        if used.contains(name.as_str()) || name.contains('@') {
+            continue;
+        }
+
+        diagnostics.warn(
+            Warning::UnusedLabel,
+            format!("Label {} is never used!", preprocess::source_name(name)).as_str(),
+            path,
+            lines,
+            span,
+        )?;
+    }
+
+    Ok(())
+}
+
+/**
+ * Every name used by an instruction, a value, .assert, .print, .entry or .global
+ */
+fn referenced_labels(program: &Program) -> HashSet<&str> {
+    let code = program
+        .text
+        .iter()
+        .chain(program.sections.iter().map(|section| &section.text))
+        .flat_map(|text| &text.labels)
+        .flat_map(|label| &label.contents)
+        .flat_map(|item| match &item.node {
+            SubroutineItem::Instruction { deferred, .. } => {
+                deferred.iter().map(|operand| &operand.value).collect()
+            }
+            SubroutineItem::Constant(constant) => deferred_expression(constant)
+                .into_iter()
+                .collect::<Vec<_>>(),
+        });
+
+    let data = program
+        .data
+        .iter()
+        .flat_map(|data| &data.labels)
+        .flat_map(|label| &label.constants)
+        .filter_map(|constant| deferred_expression(&constant.node));
+
+    let assertions = program
+        .assertions
+        .iter()
+        .map(|assertion| &assertion.condition);
+    let messages = program
+        .messages
+        .iter()
+        .flat_map(|message| &message.arguments);
+
+    let expressions = code.chain(data).chain(assertions).chain(messages);
+
+    let directives = program
+        .entry
+        .iter()
+        .chain(&program.globals)
+        .map(|label| label.node.as_str());
+
+    expressions
+        .flat_map(|expression| expression.identifiers())
+        .chain(directives)
+        .collect()
+}
+
+fn deferred_expression(constant: &ConstantLabelType) -> Option<&Expression> {
+    match constant {
+        ConstantLabelType::DeferredWord(expression)
+        | ConstantLabelType::DeferredByte(expression) => Some(expression),
+        _ => None,
+    }
+}
+
+/**
+ * Whether execution runs into the label after `label`, because it does not end with a jump, return or halt
+ */
+fn falls_through(label: &SubroutineLabel) -> bool {
+    match label.contents.last().map(|item| &item.node) {
+        Some(SubroutineItem::Instruction { instruction, .. }) => {
+            !matches!(instruction.mnemonic(), "jmp" | "ret" | "rti" | "hlt")
+        }
+        _ => true,
+    }
+}
+
+/**
+ * Whether an instruction leaves every register and memory location as it was
+ */
+fn is_redundant(instruction: &Instruction, deferred: &[DeferredOperand]) -> bool {
+    match instruction {
+        Instruction::mov_RegisterToRegister(dest, src) => return dest == src,
+        Instruction::xchg_Registers(first, second) => return first == second,
+        _ => {}
+    }
+
+    // Values computed from labels are not known yet, so only literal immediates are checked
+    let immediate = instruction
+        .operands()
+        .iter()
+        .enumerate()
+        .find_map(|(index, operand)| match operand {
+            Operand::Immediate(value) => Some((index, *value)),
+            _ => None,
+        });
+
+    let Some((index, value)) = immediate else {
+        return false;
+    };
+
+    if deferred.iter().any(|operand| operand.index == index) {
+        return false;
+    }
+
+    let identity = match instruction {
+        Instruction::add_ImmediateToAccumulator(_)
+        | Instruction::add_ImmediateToRegister(..)
+        | Instruction::sub_ImmediateFromAccumulator(_)
+        | Instruction::sub_ImmediateFromRegister(..)
+        | Instruction::or_AccumulatorImmediate(_)
+        | Instruction::or_RegisterImmediate(..)
+        | Instruction::xor_AccumulatorImmediate(_)
+        | Instruction::xor_RegisterImmediate(..)
+        | Instruction::shl_Immediate(..)
+        | Instruction::shr_Immediate(..)
+        | Instruction::rol_Immediate(..)
+        | Instruction::ror_Immediate(..) => 0,
+        Instruction::mul_AccumulatorImmediate(_)
+        | Instruction::mul_RegisterImmediate(..)
+        | Instruction::div_AccumulatorImmediate(_)
+        | Instruction::div_RegisterImmediate(..) => 1,
+        _ => return false,
+    };
+
+    value == identity
+}
+
+/**
+ * The source code covered by `span`, as it is quoted in warnings
+ */
+fn source_text(lines: &[String], span: Span) -> String {
+    lines[span.line_number as usize]
+        .chars()
+        .skip(span.column_start as usize)
+        .take((span.column_end - span.column_start) as usize)
+        .collect()
+}
+
+/**
+ * Whether execution reaches `target` straight after the end of the label before `following`
+ */
+fn falls_through_to(following: &[SubroutineLabel], target: &str) -> bool {
+    for label in following {
+        // A label placed with .org can be anywhere
+        if label.origin.is_some() {
+            return false;
+        }
+
+        if label.name == target {
+            return true;
+        }
+
+        if !label.contents.is_empty() {
+            return false;
+        }
+    }
+
+    false
+}
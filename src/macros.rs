@@ -0,0 +1,1078 @@
+use std::{collections::HashMap, vec::IntoIter};
+
+use regex::Regex;
+
+use crate::{
+    code::Code,
+    diagnostic::{self, Diagnostic, Severity},
+    include::{LineOrigin, MacroExpansion, SourceMap},
+    isa::Mnemonic,
+    token::Span,
+};
+
+/**
+ * A parameter of a macro, which can have a default for calls that leave it out
+ */
+#[derive(Debug, Clone)]
+struct Parameter {
+    name: String,
+    default: Option<String>,
+}
+
+/**
+ * A macro declared with `.macro <name> [parameters]` and closed with `.endm`
+ */
+#[derive(Debug, Clone)]
+struct Macro {
+    parameters: Vec<Parameter>,
+    // Lines between .macro and .endm, along with where each was written
+    body: Vec<(String, LineOrigin)>,
+    origin: LineOrigin,
+    // The `.macro` line, to point at the name in errors about calls
+    header: String,
+}
+
+/**
+ * A piece of a line along with the columns it spans, used for arguments and parameters
+ */
+struct Part {
+    text: String,
+    column_start: u32,
+    column_end: u32,
+}
+
+struct Expander<'a> {
+    sources: SourceMap,
+    macros: HashMap<String, Macro>,
+    // Names of the macros currently being expanded, innermost last
+    stack: Vec<String>,
+    // Variables defined on the command line, which can be used as repeat counts
+    defines: &'a HashMap<String, Option<u16>>,
+    // Match directives such as `.MACRO` regardless of case
+    ignore_case: bool,
+}
+
+/**
+ * The largest count a `.rept` block can be repeated, the number of words in the address space
+ */
+const MAX_REPEAT_COUNT: u64 = u16::MAX as u64;
+
+/**
+ * Replace every call to a macro with the lines of its body, with the arguments of the call
+ * substituted for its parameters.
+ *
+ * Macros are declared with `.macro <name> [<parameter>[=<default>], ...]` and the lines up to
+ * `.endm`, and called like an instruction with `<name> [<argument>, ...]`. Arguments are given in
+ * order, or by name with `<parameter>=<argument>`. Lines of a macro body are mapped back to the
+ * macro declaration, with the call they were expanded from, so errors point at both.
+ *
+ * Blocks between `.rept <count>[, <counter>]` and `.endr` are output `<count>` times in the same way,
+ * and blocks between `.irp <parameter>, <values...>` and `.endr` once for every value.
+ */
+pub fn expand_macros(
+    mut sources: SourceMap,
+    defines: &HashMap<String, Option<u16>>,
+    ignore_case: bool,
+) -> diagnostic::Result<SourceMap> {
+    let lines = std::mem::take(&mut sources.lines);
+    let origins = std::mem::take(&mut sources.origins);
+
+    let mut expander = Expander {
+        sources,
+        macros: HashMap::new(),
+        stack: Vec::new(),
+        defines,
+        ignore_case,
+    };
+
+    expander.process(lines.into_iter().zip(origins).collect())?;
+
+    Ok(expander.sources)
+}
+
+impl Expander<'_> {
+    /**
+     * Declare macros, repeat blocks and expand macro calls in a list of lines, outputting the result
+     */
+    fn process(&mut self, lines: Vec<(String, LineOrigin)>) -> diagnostic::Result<()> {
+        let mut input = lines.into_iter();
+
+        while let Some((line, origin)) = input.next() {
+            match self.directive(&line).as_deref() {
+                Some(".macro") => self.declare_macro(line, origin, &mut input)?,
+                Some(".rept") => self.repeat(line, origin, &mut input)?,
+                Some(".irp") => self.iterate(line, origin, &mut input)?,
+                Some(".endm") => {
+                    return self.report(
+                        Code::UnbalancedBlock,
+                        "Unexpected .endm directive without a matching .macro!",
+                        None,
+                        origin,
+                        &line,
+                        0,
+                        line.len(),
+                    )
+                }
+                Some(".endr") => {
+                    return self.report(
+                        Code::UnbalancedBlock,
+                        "Unexpected .endr directive without a matching .rept or .irp!",
+                        None,
+                        origin,
+                        &line,
+                        0,
+                        line.len(),
+                    )
+                }
+                _ => self.expand_line(line, origin)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * The first word of a line, lowercased if directives are matched regardless of case
+     */
+    fn directive(&self, line: &str) -> Option<String> {
+        let word = first_word(line)?;
+
+        Some(if self.ignore_case {
+            word.to_ascii_lowercase()
+        } else {
+            word.to_owned()
+        })
+    }
+
+    fn push(&mut self, line: String, origin: LineOrigin) {
+        self.sources.lines.push(line);
+        self.sources.origins.push(origin);
+    }
+
+    /**
+     * Read the body of a macro up to its `.endm`
+     */
+    fn declare_macro(
+        &mut self,
+        line: String,
+        origin: LineOrigin,
+        input: &mut IntoIter<(String, LineOrigin)>,
+    ) -> diagnostic::Result<()> {
+        let (name, parameters) = self.parse_header(&line, origin)?;
+
+        // Declarations are kept as empty lines so they still show up in the listing
+        self.push(String::new(), origin);
+
+        let mut body = Vec::new();
+
+        loop {
+            let Some((body_line, body_origin)) = input.next() else {
+                return self.report(
+                    Code::UnbalancedBlock,
+                    format!("Expected .endm directive to close macro `{name}`!").as_str(),
+                    None,
+                    origin,
+                    &line,
+                    0,
+                    line.len(),
+                );
+            };
+
+            self.push(String::new(), body_origin);
+
+            match self.directive(&body_line).as_deref() {
+                Some(".endm") => {
+                    expect_end_of_line(self, &body_line, body_origin, ".endm")?;
+                    break;
+                }
+                Some(".macro") => return self.report(
+                    Code::InvalidMacro,
+                    format!("Unexpected .macro directive inside of macro `{name}`, macros cannot be declared inside other macros!").as_str(),
+                    None,
+                    body_origin,
+                    &body_line,
+                    0,
+                    body_line.len(),
+                ),
+                _ => body.push((body_line, body_origin)),
+            }
+        }
+
+        self.macros.insert(
+            name,
+            Macro {
+                parameters,
+                body,
+                origin,
+                header: line,
+            },
+        );
+
+        Ok(())
+    }
+
+    /**
+     * Output the lines of a `.rept <count>[, <counter>]` block up to its `.endr` once for every repetition
+     */
+    fn repeat(
+        &mut self,
+        line: String,
+        origin: LineOrigin,
+        input: &mut IntoIter<(String, LineOrigin)>,
+    ) -> diagnostic::Result<()> {
+        let directive_end = directive_end(&line, ".rept");
+        let content_end = comment_start(&line);
+
+        let parts = split_arguments(self, &line, directive_end, content_end, origin)?;
+
+        let (count, counter) = match parts.as_slice() {
+            [count] => (count, None),
+            [count, counter] => (count, Some(counter)),
+            _ => return self.report(
+                Code::InvalidDirectiveArgument,
+                "Expected a repeat count and an optional counter name after .rept directive! (e.g. `.rept 4, i`)",
+                None,
+                origin,
+                &line,
+                0,
+                content_end,
+            ),
+        };
+
+        let part_span = |part: &Part| {
+            (
+                byte_offset(&line, part.column_start),
+                byte_offset(&line, part.column_end),
+            )
+        };
+
+        let Some(repetitions) = self.repeat_count(&count.text) else {
+            let (start, end) = part_span(count);
+
+            return self.report(
+                Code::InvalidDirectiveArgument,
+                format!("Invalid repeat count `{}`! Expected a number or a variable defined with -D NAME=VALUE", count.text).as_str(),
+                Some("Blocks are repeated before labels are placed, so the count cannot use labels"),
+                origin,
+                &line,
+                start,
+                end,
+            );
+        };
+
+        if repetitions > MAX_REPEAT_COUNT {
+            let (start, end) = part_span(count);
+
+            return self.report(
+                Code::InvalidDirectiveArgument,
+                format!("Repeat count {repetitions} exceeds the maximum of {MAX_REPEAT_COUNT}!")
+                    .as_str(),
+                None,
+                origin,
+                &line,
+                start,
+                end,
+            );
+        }
+
+        if let Some(counter) = counter {
+            if !is_identifier(&counter.text) {
+                let (start, end) = part_span(counter);
+
+                return self.report(
+                    Code::InvalidDirectiveArgument,
+                    format!(
+                        "Invalid counter name `{}`! Expected a name like `i`",
+                        counter.text
+                    )
+                    .as_str(),
+                    None,
+                    origin,
+                    &line,
+                    start,
+                    end,
+                );
+            }
+        }
+
+        // Blocks are kept as empty lines so they still show up in the listing
+        self.push(String::new(), origin);
+
+        let body = self.read_block(&line, origin, input)?;
+
+        for iteration in 0..repetitions as u32 {
+            let arguments: Vec<_> = counter
+                .iter()
+                .map(|counter| (counter.text.clone(), iteration.to_string()))
+                .collect();
+
+            self.expand_body(
+                MacroExpansion {
+                    name: "rept".to_owned(),
+                    iteration: Some(iteration),
+                    call: origin,
+                },
+                &body,
+                &arguments,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Output the lines of an `.irp <parameter>, <values...>` block up to its `.endr` once for every value
+     */
+    fn iterate(
+        &mut self,
+        line: String,
+        origin: LineOrigin,
+        input: &mut IntoIter<(String, LineOrigin)>,
+    ) -> diagnostic::Result<()> {
+        let directive_end = directive_end(&line, ".irp");
+        let content_end = comment_start(&line);
+
+        let mut parts =
+            split_arguments(self, &line, directive_end, content_end, origin)?.into_iter();
+
+        let Some(parameter) = parts
+            .next()
+            .filter(|parameter| is_identifier(&parameter.text))
+        else {
+            return self.report(
+                Code::InvalidDirectiveArgument,
+                "Expected a parameter name and a list of values after .irp directive! (e.g. `.irp reg, %eax, %ebx`)",
+                None,
+                origin,
+                &line,
+                0,
+                content_end,
+            );
+        };
+
+        // Blocks are kept as empty lines so they still show up in the listing
+        self.push(String::new(), origin);
+
+        let body = self.read_block(&line, origin, input)?;
+
+        for (iteration, value) in parts.enumerate() {
+            self.expand_body(
+                MacroExpansion {
+                    name: "irp".to_owned(),
+                    iteration: Some(iteration as u32),
+                    call: origin,
+                },
+                &body,
+                &[(parameter.text.clone(), value.text)],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Read the lines of a repeated block up to the `.endr` that closes it
+     */
+    fn read_block(
+        &mut self,
+        line: &str,
+        origin: LineOrigin,
+        input: &mut IntoIter<(String, LineOrigin)>,
+    ) -> diagnostic::Result<Vec<(String, LineOrigin)>> {
+        let mut body = Vec::new();
+        let mut depth = 0;
+
+        loop {
+            let Some((body_line, body_origin)) = input.next() else {
+                return self.report(
+                    Code::UnbalancedBlock,
+                    "Expected .endr directive to close block!",
+                    None,
+                    origin,
+                    line,
+                    0,
+                    line.len(),
+                );
+            };
+
+            self.push(String::new(), body_origin);
+
+            // Blocks can be nested, those are repeated along with the rest of the body
+            match self.directive(&body_line).as_deref() {
+                Some(".rept" | ".irp") => depth += 1,
+                Some(".endr") if depth == 0 => {
+                    expect_end_of_line(self, &body_line, body_origin, ".endr")?;
+                    return Ok(body);
+                }
+                Some(".endr") => depth -= 1,
+                _ => (),
+            }
+
+            body.push((body_line, body_origin));
+        }
+    }
+
+    /**
+     * Output the lines of a macro body or repeated block, with the given names substituted
+     */
+    fn expand_body(
+        &mut self,
+        expansion: MacroExpansion,
+        body: &[(String, LineOrigin)],
+        arguments: &[(String, String)],
+    ) -> diagnostic::Result<()> {
+        let index = self.sources.expansions.len();
+        self.sources.expansions.push(expansion);
+
+        let lines = body
+            .iter()
+            .map(|(body_line, body_origin)| {
+                (
+                    substitute(body_line, arguments),
+                    LineOrigin {
+                        expansion: Some(index),
+                        ..*body_origin
+                    },
+                )
+            })
+            .collect();
+
+        self.process(lines)?;
+
+        Ok(())
+    }
+
+    /**
+     * A repeat count written as a number, or the name of a variable defined with a value
+     */
+    fn repeat_count(&self, text: &str) -> Option<u64> {
+        if let Some(value) = self.defines.get(text) {
+            return value.map(u64::from);
+        }
+
+        let parsed = if let Some(hex) = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+            u64::from_str_radix(hex, 16)
+        } else if let Some(binary) = text.strip_prefix('%').or_else(|| text.strip_prefix("0b")) {
+            u64::from_str_radix(binary, 2)
+        } else {
+            text.parse::<u64>()
+        };
+
+        parsed.ok()
+    }
+
+    /**
+     * Report an error in a line that is being expanded, between two byte offsets of the line
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn report<T>(
+        &self,
+        code: Code,
+        message: &str,
+        note: Option<&str>,
+        origin: LineOrigin,
+        line: &str,
+        start: usize,
+        end: usize,
+    ) -> diagnostic::Result<T> {
+        self.diagnostic(code, message, note, origin, line, start, end)
+            .raise()
+    }
+
+    /**
+     * An error in a line that is being expanded, for errors that point at more than the line
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn diagnostic(
+        &self,
+        code: Code,
+        message: &str,
+        note: Option<&str>,
+        origin: LineOrigin,
+        line: &str,
+        start: usize,
+        end: usize,
+    ) -> Diagnostic {
+        let file = &self.sources.files[origin.file];
+
+        // The line being expanded can differ from the file when it comes from a macro body
+        let mut lines = file.lines.clone();
+        lines[origin.line as usize] = line.to_owned();
+
+        let notes = self.sources.origin_notes(origin);
+        let note = note.or(notes.first().map(|note| note.as_str()));
+
+        let span = Span {
+            line_number: origin.line,
+            column_start: column(line, start),
+            column_end: column(line, end.max(start + 1)),
+        };
+
+        Diagnostic::at(
+            Severity::Error,
+            code,
+            message,
+            note,
+            &file.path,
+            &lines,
+            span,
+        )
+    }
+
+    /**
+     * Underline the name of a macro where it was declared, with `message` below it
+     */
+    fn with_declaration(
+        &self,
+        diagnostic: Diagnostic,
+        definition: &Macro,
+        message: &str,
+    ) -> Diagnostic {
+        let file = &self.sources.files[definition.origin.file];
+
+        let mut lines = file.lines.clone();
+        lines[definition.origin.line as usize] = definition.header.clone();
+
+        let (start, end) = macro_name_range(&definition.header);
+
+        let span = Span {
+            line_number: definition.origin.line,
+            column_start: column(&definition.header, start),
+            column_end: column(&definition.header, end.max(start + 1)),
+        };
+
+        diagnostic.with_label(message, &file.path, &lines, span)
+    }
+
+    /**
+     * Parse the name and parameters after a `.macro` directive
+     */
+    fn parse_header(
+        &self,
+        line: &str,
+        origin: LineOrigin,
+    ) -> diagnostic::Result<(String, Vec<Parameter>)> {
+        let directive_end = directive_end(line, ".macro");
+        let directive_start = directive_end - ".macro".len();
+        let content_end = comment_start(line);
+
+        let (name_start, name_end) = macro_name_range(line);
+        let name = &line[name_start..name_end];
+
+        if !is_identifier(name) {
+            return self.report(
+                Code::InvalidMacro,
+                "Expected a macro name after .macro directive! (e.g. `.macro store reg, address`)",
+                None,
+                origin,
+                line,
+                directive_start,
+                content_end.max(directive_end),
+            );
+        }
+
+        if Mnemonic::from_name(name).is_some() {
+            return self.report(
+                Code::InvalidMacro,
+                format!("`{name}` is an instruction and cannot be used as a macro name!").as_str(),
+                None,
+                origin,
+                line,
+                name_start,
+                name_end,
+            );
+        }
+
+        if let Some(existing) = self.macros.get(name) {
+            let diagnostic = self.diagnostic(
+                Code::InvalidMacro,
+                format!("Duplicate macro `{name}`!").as_str(),
+                None,
+                origin,
+                line,
+                name_start,
+                name_end,
+            );
+
+            return self
+                .with_declaration(diagnostic, existing, "first declared here")
+                .raise();
+        }
+
+        let mut parameters: Vec<Parameter> = Vec::new();
+
+        for part in split_arguments(self, line, name_end, content_end, origin)? {
+            let (parameter_name, default) = match split_named(&part.text) {
+                Some((parameter_name, default)) => (parameter_name, Some(default)),
+                None => (part.text.as_str(), None),
+            };
+
+            if !is_identifier(parameter_name) || default.is_some_and(|default| default.is_empty()) {
+                return self.report(
+                    Code::InvalidMacro,
+                    format!("Invalid macro parameter `{}`! Parameters are names with an optional default, e.g. `count` or `count=1`", part.text).as_str(),
+                    None,
+                    origin,
+                    line,
+                    byte_offset(line, part.column_start),
+                    byte_offset(line, part.column_end),
+                );
+            }
+
+            if parameters
+                .iter()
+                .any(|parameter| parameter.name == parameter_name)
+            {
+                return self.report(
+                    Code::InvalidMacro,
+                    format!("Duplicate parameter `{parameter_name}` in macro `{name}`!").as_str(),
+                    None,
+                    origin,
+                    line,
+                    byte_offset(line, part.column_start),
+                    byte_offset(line, part.column_end),
+                );
+            }
+
+            parameters.push(Parameter {
+                name: parameter_name.to_owned(),
+                default: default.map(|default| default.to_owned()),
+            });
+        }
+
+        Ok((name.to_owned(), parameters))
+    }
+
+    /**
+     * Output a line, or the lines of the macro it calls
+     */
+    fn expand_line(&mut self, line: String, origin: LineOrigin) -> diagnostic::Result<()> {
+        let Some((name, name_start)) = call_name(&line) else {
+            self.push(line, origin);
+            return Ok(());
+        };
+
+        let Some(definition) = self.macros.get(name).cloned() else {
+            self.push(line, origin);
+            return Ok(());
+        };
+
+        let name = name.to_owned();
+        let name_end = name_start + name.len();
+
+        if self.stack.contains(&name) {
+            return self.report(
+                Code::InvalidMacroCall,
+                format!("Macro `{name}` cannot call itself!").as_str(),
+                Some(format!("Macros are expanded in place, so `{}` -> `{name}` would never finish expanding", self.stack.join("` -> `")).as_str()),
+                origin,
+                &line,
+                name_start,
+                name_end,
+            );
+        }
+
+        let arguments = self.bind_arguments(&name, &definition, &line, name_end, origin)?;
+
+        // The call is kept as an empty line so it still shows up in the listing
+        self.push(String::new(), origin);
+
+        self.stack.push(name.clone());
+
+        self.expand_body(
+            MacroExpansion {
+                name,
+                iteration: None,
+                call: origin,
+            },
+            &definition.body,
+            &arguments,
+        )?;
+
+        self.stack.pop();
+
+        Ok(())
+    }
+
+    /**
+     * Match the arguments of a call to the parameters of a macro, returning the text of every parameter
+     */
+    fn bind_arguments(
+        &self,
+        name: &str,
+        definition: &Macro,
+        line: &str,
+        name_end: usize,
+        origin: LineOrigin,
+    ) -> diagnostic::Result<Vec<(String, String)>> {
+        let declared_here = format!("`{name}` is declared here");
+
+        let mut values: Vec<Option<String>> = vec![None; definition.parameters.len()];
+        let mut positional = 0;
+        let mut named = false;
+
+        for part in split_arguments(self, line, name_end, comment_start(line), origin)? {
+            let start = byte_offset(line, part.column_start);
+            let end = byte_offset(line, part.column_end);
+
+            let index = match split_named(&part.text) {
+                Some((parameter_name, value)) => {
+                    named = true;
+
+                    let Some(index) = definition
+                        .parameters
+                        .iter()
+                        .position(|parameter| parameter.name == parameter_name)
+                    else {
+                        let diagnostic = self.diagnostic(
+                            Code::InvalidMacroCall,
+                            format!("Macro `{name}` has no parameter named `{parameter_name}`!")
+                                .as_str(),
+                            None,
+                            origin,
+                            line,
+                            start,
+                            end,
+                        );
+
+                        return self
+                            .with_declaration(diagnostic, definition, &declared_here)
+                            .raise();
+                    };
+
+                    if values[index].is_some() {
+                        return self.report(
+                            Code::InvalidMacroCall,
+                            format!("Parameter `{parameter_name}` of macro `{name}` was given more than once!").as_str(),
+                            None,
+                            origin,
+                            line,
+                            start,
+                            end,
+                        );
+                    }
+
+                    values[index] = Some(value.to_owned());
+                    continue;
+                }
+                None if named => {
+                    return self.report(
+                        Code::InvalidMacroCall,
+                        "Positional arguments must come before named arguments!",
+                        None,
+                        origin,
+                        line,
+                        start,
+                        end,
+                    )
+                }
+                None => positional,
+            };
+
+            if index >= definition.parameters.len() {
+                let diagnostic = self.diagnostic(
+                    Code::InvalidMacroCall,
+                    format!(
+                        "Too many arguments for macro `{name}`, it takes {}!",
+                        definition.parameters.len()
+                    )
+                    .as_str(),
+                    None,
+                    origin,
+                    line,
+                    start,
+                    end,
+                );
+
+                return self
+                    .with_declaration(diagnostic, definition, &declared_here)
+                    .raise();
+            }
+
+            values[index] = Some(part.text);
+            positional += 1;
+        }
+
+        definition
+            .parameters
+            .iter()
+            .zip(values)
+            .map(|(parameter, value)| {
+                let Some(value) = value.or(parameter.default.clone()) else {
+                    let diagnostic = self.diagnostic(
+                        Code::InvalidMacroCall,
+                        format!(
+                            "Missing argument `{}` in call to macro `{name}`!",
+                            parameter.name
+                        )
+                        .as_str(),
+                        None,
+                        origin,
+                        line,
+                        name_end - name.len(),
+                        name_end,
+                    );
+
+                    return self
+                        .with_declaration(diagnostic, definition, &declared_here)
+                        .raise();
+                };
+
+                Ok((parameter.name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+/**
+ * Byte offsets of the name in a `.macro` line
+ */
+fn macro_name_range(line: &str) -> (usize, usize) {
+    let directive_end = directive_end(line, ".macro");
+    let content_end = comment_start(line);
+
+    let rest = &line[directive_end..content_end];
+    let name_start = directive_end + (rest.len() - rest.trim_start().len());
+    let name_end = line[name_start..content_end]
+        .find(char::is_whitespace)
+        .map_or(content_end, |end| name_start + end);
+
+    (name_start, name_end)
+}
+
+fn expect_end_of_line(
+    expander: &Expander<'_>,
+    line: &str,
+    origin: LineOrigin,
+    directive: &str,
+) -> diagnostic::Result<()> {
+    let directive_end = directive_end(line, directive);
+    let rest = &line[directive_end..comment_start(line)];
+
+    if !rest.trim().is_empty() {
+        let start = directive_end + (rest.len() - rest.trim_start().len());
+
+        return expander.report(
+            Code::InvalidDirectiveArgument,
+            format!("Unexpected `{}` after {directive} directive!", rest.trim()).as_str(),
+            None,
+            origin,
+            line,
+            start,
+            directive_end + rest.trim_end().len(),
+        );
+    }
+
+    Ok(())
+}
+
+/**
+ * The first word of a line, ignoring leading whitespace
+ */
+fn first_word(line: &str) -> Option<&str> {
+    line.split_whitespace().next()
+}
+
+/**
+ * Byte offset of the end of the directive a line starts with
+ */
+fn directive_end(line: &str, directive: &str) -> usize {
+    line.len() - line.trim_start().len() + directive.len()
+}
+
+/**
+ * The name and byte offset of the first word of a line if it could be a macro call
+ */
+fn call_name(line: &str) -> Option<(&str, usize)> {
+    let word = first_word(line)?;
+    let start = line.find(word).unwrap();
+
+    // Stop at the first character that cannot be part of a name, such as the `:` of a label
+    let end = word
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(word.len());
+
+    if end < word.len() && !word[end..].starts_with(';') {
+        return None;
+    }
+
+    let name = &word[..end];
+
+    is_identifier(name).then_some((name, start))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let re = Regex::new(r"^[a-zA-Z_][a-zA-Z0-9_]*$").unwrap();
+    re.is_match(name)
+}
+
+/**
+ * Split `<name>=<value>` into its name and value, unless the `=` is part of `==`
+ */
+fn split_named(text: &str) -> Option<(&str, &str)> {
+    let equals = text.find('=')?;
+
+    if text[equals + 1..].starts_with('=') {
+        return None;
+    }
+
+    let name = text[..equals].trim();
+
+    is_identifier(name).then(|| (name, text[equals + 1..].trim()))
+}
+
+/**
+ * Byte offset of the comment in a line, or its length if it has none
+ */
+fn comment_start(line: &str) -> usize {
+    let mut quote = None;
+
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, ';') => return index,
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            _ => (),
+        }
+    }
+
+    line.len()
+}
+
+/**
+ * Split the text of a line between two byte offsets on the commas that are not inside of strings or parentheses
+ */
+fn split_arguments(
+    expander: &Expander<'_>,
+    line: &str,
+    start: usize,
+    end: usize,
+    origin: LineOrigin,
+) -> diagnostic::Result<Vec<Part>> {
+    let text = &line[start..end];
+
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut depth = 0;
+    let mut part_start = 0;
+
+    for (index, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ',')))
+    {
+        match (quote, c) {
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => (),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth -= 1,
+            (None, ',') if depth <= 0 => {
+                let part = &text[part_start..index];
+                let trimmed = part.trim();
+
+                let trimmed_start = start + part_start + (part.len() - part.trim_start().len());
+
+                if trimmed.is_empty() {
+                    // The separator after an empty argument, or the last one if the line ends with a `,`
+                    let comma = if index < text.len() {
+                        start + index
+                    } else {
+                        start + text.rfind(',').unwrap()
+                    };
+
+                    return expander.report(
+                        Code::UnexpectedToken,
+                        "Unexpected argument separator `,`!",
+                        None,
+                        origin,
+                        line,
+                        comma,
+                        comma + 1,
+                    );
+                }
+
+                parts.push(Part {
+                    text: trimmed.to_owned(),
+                    column_start: column(line, trimmed_start),
+                    column_end: column(line, trimmed_start + trimmed.len()),
+                });
+
+                part_start = index + 1;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(parts)
+}
+
+/**
+ * Replace every parameter name in a line of a macro body with its argument.
+ *
+ * Names inside of strings and comments, and names that are part of registers (`%name`),
+ * directives (`.name`) and hex literals (`$name`), are left alone.
+ */
+fn substitute(line: &str, arguments: &[(String, String)]) -> String {
+    let mut result = String::new();
+    let mut quote = None;
+    let mut previous: Option<char> = None;
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => (),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, ';') => {
+                result.push_str(&line[index..]);
+                break;
+            }
+            (None, _) if c.is_alphabetic() || c == '_' => {
+                let mut end = index + c.len_utf8();
+
+                while let Some(&(next_index, next)) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_') {
+                        break;
+                    }
+
+                    end = next_index + next.len_utf8();
+                    chars.next();
+                }
+
+                let word = &line[index..end];
+                let is_prefixed = matches!(previous, Some(p) if p == '%' || p == '.' || p == '$' || p.is_alphanumeric());
+
+                match arguments.iter().find(|(name, _)| name == word) {
+                    Some((_, value)) if !is_prefixed => result.push_str(value),
+                    _ => result.push_str(word),
+                }
+
+                previous = word.chars().last();
+                continue;
+            }
+            _ => (),
+        }
+
+        result.push(c);
+        previous = Some(c);
+    }
+
+    result
+}
+
+/**
+ * Column of a byte offset in a line, counted in characters
+ */
+fn column(line: &str, offset: usize) -> u32 {
+    line[..offset.min(line.len())].chars().count() as u32
+}
+
+fn byte_offset(line: &str, column: u32) -> usize {
+    line.char_indices()
+        .nth(column as usize)
+        .map_or(line.len(), |(offset, _)| offset)
+}
@@ -1,9 +1,17 @@
 use std::{
-    collections::{HashSet, VecDeque},
-    env,
+    collections::{HashMap, HashSet, VecDeque},
+    env, fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
 };
 
-use spasm::{assemble_file, AssemblerArguments};
+use spasm::{
+    apply_fixes, assemble_file, capabilities, io_error, is_valid_label_name, rename_label_in_files,
+    sarif_log, set_color_choice, set_diagnostic_paths, verify_manifest, AssemblerArguments,
+    ChecksumAlgorithm, Code, ColorChoice, Diagnostic, DiagnosticPaths, Encoding, Endian,
+    MessageFormat, OutputFormat, Severity, Warning, WarningOptions, DEFAULT_MAX_ERRORS,
+    DEFAULT_TAB_WIDTH, EXIT_ASSEMBLY_ERROR, EXIT_IO_ERROR,
+};
 
 fn main() {
     let mut args: VecDeque<_> = env::args().collect();
@@ -11,12 +19,142 @@ fn main() {
     // Remove binary name from argv
     args.pop_front();
 
+    if args.front().is_some_and(|command| command == "verify") {
+        args.pop_front();
+        verify(args);
+    }
+
+    if args.contains(&"--capabilities".to_owned()) {
+        print_capabilities(args);
+    }
+
+    if args.front().is_some_and(|arg| arg == "--explain") {
+        args.pop_front();
+        explain(args);
+    }
+
+    if args.front().is_some_and(|command| command == "rename") {
+        args.pop_front();
+        rename(args);
+    }
+
     // Parse command line arguments
     let args = parse_args(args);
 
     // println!("{args:?}");
 
-    assemble_file(args);
+    let message_format = args.message_format;
+    let fix = args.fix;
+    let encoding = args.encoding;
+
+    // Explanations are long, so they are only shown to someone reading the errors as they happen
+    let explain = args.explain_on_error && io::stderr().is_terminal();
+
+    set_color_choice(args.color);
+    set_diagnostic_paths(args.diagnostic_paths);
+
+    let result = assemble_file(args);
+
+    let diagnostics = match &result {
+        Ok(warnings) => warnings,
+        Err(diagnostics) => diagnostics,
+    };
+
+    print_diagnostics(diagnostics, message_format, explain);
+
+    if fix {
+        match apply_fixes(diagnostics, encoding) {
+            Ok(applied) => {
+                for (path, count) in applied {
+                    eprintln!("{}: applied {count} fix(es)", path.display());
+                }
+            }
+            Err(errors) => {
+                print_diagnostics(&errors, MessageFormat::Human, false);
+                std::process::exit(EXIT_IO_ERROR);
+            }
+        }
+    }
+
+    if let Err(diagnostics) = &result {
+        std::process::exit(exit_code(diagnostics));
+    }
+}
+
+/**
+ * Exit code for diagnostics that stopped assembly, files that could not be read or written have their own
+ */
+fn exit_code(diagnostics: &[Diagnostic]) -> i32 {
+    let io_error = diagnostics.iter().any(|diagnostic| {
+        diagnostic.is_error()
+            && matches!(diagnostic.code, Code::UnreadableFile | Code::UnwritableFile)
+    });
+
+    if io_error {
+        EXIT_IO_ERROR
+    } else {
+        EXIT_ASSEMBLY_ERROR
+    }
+}
+
+/**
+ * Report a file that could not be read or written and exit with `EXIT_IO_ERROR`
+ */
+fn report_io_error(code: Code, action: &str, path: &Path, error: &io::Error) -> ! {
+    io_error(code, action, path, error).print();
+
+    std::process::exit(EXIT_IO_ERROR);
+}
+
+/**
+ * Prints warnings and errors in the format chosen with --message-format, with the explanation of each kind of
+ * error after the first one if `explain` is set
+ */
+fn print_diagnostics(diagnostics: &[Diagnostic], format: MessageFormat, explain: bool) {
+    match format {
+        MessageFormat::Human => {
+            let mut explained = HashSet::new();
+
+            for diagnostic in diagnostics {
+                diagnostic.print();
+
+                if explain && diagnostic.is_error() && explained.insert(diagnostic.code) {
+                    print_explanation(diagnostic.code);
+                }
+            }
+
+            if explain {
+                return;
+            }
+
+            if let Some(error) = diagnostics.iter().find(|diagnostic| diagnostic.is_error()) {
+                eprintln!(
+                    "For more information about an error, try `spasm --explain {}`",
+                    error.code.id()
+                );
+            }
+        }
+        MessageFormat::Sarif => print!("{}", sarif_log(diagnostics)),
+    }
+}
+
+/**
+ * Prints the explanation of a code below an error, indented to set it apart from the errors around it
+ */
+fn print_explanation(code: Code) {
+    eprintln!();
+    eprintln!("    {}: {}", code.id(), code.title());
+    eprintln!();
+
+    for line in code.explanation().lines() {
+        if line.is_empty() {
+            eprintln!();
+        } else {
+            eprintln!("    {line}");
+        }
+    }
+
+    eprintln!();
 }
 
 /**
@@ -26,7 +164,28 @@ fn parse_args(mut args: VecDeque<String>) -> AssemblerArguments {
     let mut file_name: Option<String> = None;
     let mut output_path: Option<String> = None;
     let mut debug: bool = false;
-    let mut defines: HashSet<String> = HashSet::new();
+    let mut annotate: bool = false;
+    let mut format: Option<OutputFormat> = None;
+    let mut defines: HashMap<String, Option<u16>> = HashMap::new();
+    let mut include_paths: Vec<PathBuf> = Vec::new();
+    let mut text_org: Option<u16> = None;
+    let mut data_org: Option<u16> = None;
+    let mut rom_size: Option<u32> = None;
+    let mut fill: Option<u8> = None;
+    let mut endian: Option<Endian> = None;
+    let mut checksum: Option<ChecksumAlgorithm> = None;
+    let mut manifest: bool = false;
+    let mut ignore_case: bool = false;
+    let mut relax: bool = true;
+    let mut tab_width: Option<u32> = None;
+    let mut max_errors: Option<u32> = None;
+    let mut warnings = WarningOptions::default();
+    let mut message_format: Option<MessageFormat> = None;
+    let mut fix: bool = false;
+    let mut explain_on_error: bool = false;
+    let mut color: Option<ColorChoice> = None;
+    let mut encoding: Option<Encoding> = None;
+    let mut diagnostic_paths: Option<DiagnosticPaths> = None;
 
     if args.is_empty() {
         print_help_statement();
@@ -35,7 +194,17 @@ fn parse_args(mut args: VecDeque<String>) -> AssemblerArguments {
 
     while !args.is_empty() {
         // We know since the argv is not empty that we can unwrap
-        let arg = args.pop_front().unwrap();
+        let mut arg = args.pop_front().unwrap();
+
+        // The message format, colour, encoding and path style can also be given as --message-format=<format> and so on
+        if let Some((
+            option @ ("--message-format" | "--color" | "--encoding" | "--diagnostic-paths"),
+            value,
+        )) = arg.split_once('=')
+        {
+            args.push_front(value.to_owned());
+            arg = option.to_owned();
+        }
 
         match arg.as_str() {
             "-h" | "--help" => {
@@ -58,19 +227,357 @@ fn parse_args(mut args: VecDeque<String>) -> AssemblerArguments {
             "-d" | "--debug" => {
                 debug = true;
             }
+            "-f" | "--format" => {
+                if args.is_empty() {
+                    eprintln!("Expected format name after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if format.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let name = args.pop_front().unwrap();
+
+                format = match OutputFormat::from_name(&name) {
+                    Some(format) => Some(format),
+                    None => {
+                        eprintln!(
+                            "Unknown output format '{name}'! Expected one of {}",
+                            OutputFormat::NAMES.join(", ")
+                        );
+                        print_help_statement();
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "-a" | "--annotate" => {
+                annotate = true;
+            }
             "-D" | "--define" => {
                 if args.is_empty() {
-                    eprintln!("Expected file name after {arg} argument!");
+                    eprintln!("Expected variable name after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let define = args.pop_front().unwrap();
+
+                match define.strip_prefix('@') {
+                    Some(define_file) => defines.extend(read_define_file(define_file).iter().map(
+                        |(line_number, define)| {
+                            parse_define(
+                                &arg,
+                                define,
+                                Some(format!("{define_file}:{line_number}").as_str()),
+                            )
+                        },
+                    )),
+                    None => {
+                        let (name, value) = parse_define(&arg, &define, None);
+                        defines.insert(name, value);
+                    }
+                }
+            }
+            "-I" | "--include-path" => {
+                if args.is_empty() {
+                    eprintln!("Expected directory after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                include_paths.push(PathBuf::from(args.pop_front().unwrap()));
+            }
+            "--text-org" | "--data-org" => {
+                if args.is_empty() {
+                    eprintln!("Expected address after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let address = parse_address(&arg, &args.pop_front().unwrap());
+
+                let org = if arg == "--text-org" {
+                    &mut text_org
+                } else {
+                    &mut data_org
+                };
+
+                if org.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                *org = Some(address);
+            }
+            "--rom-size" => {
+                if args.is_empty() {
+                    eprintln!("Expected size after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if rom_size.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                rom_size = Some(parse_rom_size(&arg, &args.pop_front().unwrap()));
+            }
+            "--fill" => {
+                if args.is_empty() {
+                    eprintln!("Expected fill byte after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if fill.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
                     print_help_statement();
                     std::process::exit(1);
                 }
 
-                defines.insert(args.pop_front().unwrap());
+                let value = args.pop_front().unwrap();
+
+                fill = match parse_number(&value).and_then(|fill| u8::try_from(fill).ok()) {
+                    Some(fill) => Some(fill),
+                    None => {
+                        eprintln!("Invalid fill byte '{value}' for {arg} argument! Expected a byte like $FF");
+                        print_help_statement();
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--endian" => {
+                if args.is_empty() {
+                    eprintln!("Expected byte order after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if endian.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let name = args.pop_front().unwrap();
+
+                endian = match Endian::from_name(&name) {
+                    Some(endian) => Some(endian),
+                    None => {
+                        eprintln!("Unknown byte order '{name}'! Expected either big or little");
+                        print_help_statement();
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--checksum" => {
+                if args.is_empty() {
+                    eprintln!("Expected checksum algorithm after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if checksum.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let name = args.pop_front().unwrap();
+
+                checksum = match ChecksumAlgorithm::from_name(&name) {
+                    Some(algorithm) => Some(algorithm),
+                    None => {
+                        eprintln!("Unknown checksum algorithm '{name}'! Expected one of sum, crc16, crc32");
+                        print_help_statement();
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "-m" | "--manifest" => {
+                manifest = true;
+            }
+            "--ignore-case" => {
+                ignore_case = true;
+            }
+            "--no-relax" => {
+                relax = false;
+            }
+            "--fix" => {
+                fix = true;
+            }
+            "--explain-on-error" => {
+                explain_on_error = true;
+            }
+            "--tab-width" => {
+                if args.is_empty() {
+                    eprintln!("Expected number of columns after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if tab_width.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let value = args.pop_front().unwrap();
+
+                tab_width = match value.parse::<u32>() {
+                    Ok(width @ 1..=32) => Some(width),
+                    _ => {
+                        eprintln!("Invalid tab width '{value}'! Expected a number of columns from 1 to 32");
+                        print_help_statement();
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--max-errors" => {
+                if args.is_empty() {
+                    eprintln!("Expected number of errors after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if max_errors.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let value = args.pop_front().unwrap();
+
+                max_errors = match value.parse::<u32>() {
+                    Ok(count) => Some(count),
+                    Err(_) => {
+                        eprintln!("Invalid error limit '{value}'! Expected a number of errors, or 0 for no limit");
+                        print_help_statement();
+                        std::process::exit(1);
+                    }
+                };
             }
             "-v" | "--version" => {
                 println!("SPASM v{}", env!("CARGO_PKG_VERSION"));
                 std::process::exit(0);
             }
+            "--message-format" => {
+                if args.is_empty() {
+                    eprintln!("Expected message format after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if message_format.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let name = args.pop_front().unwrap();
+
+                message_format = match MessageFormat::from_name(&name) {
+                    Some(format) => Some(format),
+                    None => {
+                        eprintln!(
+                            "Unknown message format '{name}'! Expected one of {}",
+                            MessageFormat::NAMES.join(", ")
+                        );
+                        print_help_statement();
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--color" => {
+                if args.is_empty() {
+                    eprintln!("Expected auto, always or never after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if color.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let name = args.pop_front().unwrap();
+
+                color = match ColorChoice::from_name(&name) {
+                    Some(choice) => Some(choice),
+                    None => {
+                        eprintln!(
+                            "Unknown color choice '{name}'! Expected one of {}",
+                            ColorChoice::NAMES.join(", ")
+                        );
+                        print_help_statement();
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--encoding" => {
+                if args.is_empty() {
+                    eprintln!("Expected encoding after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if encoding.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let name = args.pop_front().unwrap();
+
+                encoding = match Encoding::from_name(&name) {
+                    Some(encoding) => Some(encoding),
+                    None => {
+                        eprintln!(
+                            "Unknown encoding '{name}'! Expected one of {}",
+                            Encoding::NAMES.join(", ")
+                        );
+                        print_help_statement();
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--diagnostic-paths" => {
+                if args.is_empty() {
+                    eprintln!("Expected absolute or relative after {arg} argument!");
+                    print_help_statement();
+                    std::process::exit(1);
+                } else if diagnostic_paths.is_some() {
+                    eprintln!("Unexpected duplicate argument {arg}!");
+                    print_help_statement();
+                    std::process::exit(1);
+                }
+
+                let name = args.pop_front().unwrap();
+
+                diagnostic_paths = match DiagnosticPaths::from_name(&name) {
+                    Some(paths) => Some(paths),
+                    None => {
+                        eprintln!(
+                            "Unknown path style '{name}'! Expected one of {}",
+                            DiagnosticPaths::NAMES.join(", ")
+                        );
+                        print_help_statement();
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "-Werror" => {
+                warnings.as_errors = true;
+            }
+            _ if arg.starts_with("-W") => {
+                let (name, enabled) = match arg.strip_prefix("-Wno-") {
+                    Some(name) => (name, false),
+                    None => (&arg[2..], true),
+                };
+
+                let Some(warning) = Warning::from_name(name) else {
+                    eprintln!(
+                        "Unknown warning '{name}'! Expected one of {}",
+                        capabilities().warnings.join(", ")
+                    );
+                    print_help_statement();
+                    std::process::exit(1);
+                };
+
+                if enabled {
+                    warnings.enabled.insert(warning);
+                } else {
+                    warnings.enabled.remove(&warning);
+                }
+            }
             _ => {
                 if arg.starts_with("-") {
                     eprintln!("Unexpected option argument '{arg}'!");
@@ -102,9 +609,11 @@ fn parse_args(mut args: VecDeque<String>) -> AssemblerArguments {
         std::process::exit(1);
     }
 
+    let format = format.unwrap_or(OutputFormat::Bin);
+
     let output_path = match output_path {
         Some(out) => out,
-        None => file_name.replace(".asm", ".bin"),
+        None => file_name.replace(".asm", &format!(".{}", format.writer().extension())),
     };
 
     if output_path == file_name {
@@ -116,8 +625,309 @@ fn parse_args(mut args: VecDeque<String>) -> AssemblerArguments {
         file_name,
         output_path,
         debug,
+        annotate,
+        format,
         defines,
+        include_paths,
+        text_org,
+        data_org,
+        rom_size,
+        fill,
+        endian: endian.unwrap_or_default(),
+        checksum,
+        manifest,
+        ignore_case,
+        tab_width: tab_width.unwrap_or(DEFAULT_TAB_WIDTH),
+        relax,
+        warnings,
+        message_format: message_format.unwrap_or_default(),
+        fix,
+        color: color.unwrap_or_default(),
+        encoding: encoding.unwrap_or_default(),
+        max_errors: max_errors.unwrap_or(DEFAULT_MAX_ERRORS),
+        diagnostic_paths: diagnostic_paths.unwrap_or_default(),
+        explain_on_error,
+    }
+}
+
+/**
+ * Checks an image against its manifest for `spasm verify <image> <manifest>`.
+ * Exits with a non-zero code if anything does not match.
+ */
+fn verify(mut args: VecDeque<String>) -> ! {
+    let (Some(image_path), Some(manifest_path), None) =
+        (args.pop_front(), args.pop_front(), args.pop_front())
+    else {
+        eprintln!("Expected an image and a manifest file! (spasm verify <image> <manifest>)");
+        print_help_statement();
+        std::process::exit(1);
+    };
+
+    let image = fs::read(&image_path).unwrap_or_else(|err| {
+        report_io_error(
+            Code::UnreadableFile,
+            "read image file",
+            Path::new(&image_path),
+            &err,
+        )
+    });
+
+    let manifest = fs::read_to_string(&manifest_path).unwrap_or_else(|err| {
+        report_io_error(
+            Code::UnreadableFile,
+            "read manifest file",
+            Path::new(&manifest_path),
+            &err,
+        )
+    });
+
+    let checks = match verify_manifest(&image, &manifest) {
+        Ok(checks) => checks,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+
+    for check in &checks {
+        println!(
+            "{:<6} offset {:5}  size {:5}  {}",
+            check.name,
+            check.offset,
+            check.size,
+            if check.ok { "OK" } else { "MISMATCH" }
+        );
+    }
+
+    if checks.iter().all(|check| check.ok) {
+        std::process::exit(0);
+    } else {
+        eprintln!("Image '{image_path}' does not match manifest '{manifest_path}'!");
+        std::process::exit(1);
+    }
+}
+
+/**
+ * Renames a label and every reference to it across the given source files
+ */
+fn rename(mut args: VecDeque<String>) -> ! {
+    let (Some(old_name), Some(new_name)) = (args.pop_front(), args.pop_front()) else {
+        eprintln!("Expected the old and new label names! (spasm rename <old_label> <new_label> <files...>)");
+        print_help_statement();
+        std::process::exit(1);
+    };
+
+    if args.is_empty() {
+        eprintln!("Expected at least one source file to rename `{old_name}` in!");
+        print_help_statement();
+        std::process::exit(1);
+    }
+
+    let paths: Vec<_> = args.into_iter().map(PathBuf::from).collect();
+
+    match rename_label_in_files(&old_name, &new_name, &paths) {
+        Ok(renamed) => {
+            for (path, count) in renamed {
+                println!("{}: renamed {count} occurrence(s)", path.display());
+            }
+
+            std::process::exit(0);
+        }
+        Err(diagnostics) => {
+            print_diagnostics(&diagnostics, MessageFormat::Human, false);
+            std::process::exit(exit_code(&diagnostics));
+        }
+    }
+}
+
+/**
+ * Prints the explanation of a diagnostic code for `spasm --explain <code>`
+ */
+fn explain(mut args: VecDeque<String>) -> ! {
+    let (Some(id), None) = (args.pop_front(), args.pop_front()) else {
+        eprintln!("Expected a single code after --explain! (e.g. `spasm --explain E0015`)");
+        print_help_statement();
+        std::process::exit(1);
+    };
+
+    let Some(code) = Code::from_id(&id) else {
+        eprintln!("Unknown code '{id}'! Codes are written like E0015 or W0001");
+        std::process::exit(1);
+    };
+
+    println!("{}: {}", code.id(), code.title());
+    println!();
+    println!("{}", code.explanation());
+
+    std::process::exit(0);
+}
+
+/**
+ * Prints what this build supports for `spasm --capabilities [--json]`
+ */
+fn print_capabilities(args: VecDeque<String>) -> ! {
+    let mut json = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "--capabilities" => (),
+            "--json" => json = true,
+            _ => {
+                eprintln!("Unexpected argument '{arg}' with --capabilities!");
+                print_help_statement();
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let capabilities = capabilities();
+
+    if json {
+        print!("{}", capabilities.to_json());
+    } else {
+        println!(
+            "SPASM v{} (API version {})",
+            capabilities.version, capabilities.api_version
+        );
+        println!("Formats:      {}", capabilities.formats.join(", "));
+        println!("Targets:      {}", capabilities.targets.join(", "));
+        println!("Directives:   {}", capabilities.directives.join(", "));
+        println!("Instructions: {}", capabilities.instructions.join(", "));
+        println!("Checksums:    {}", capabilities.checksums.join(", "));
+        println!("Warnings:     {}", capabilities.warnings.join(", "));
+
+        if capabilities.features.is_empty() {
+            println!("Features:     none");
+        } else {
+            println!("Features:     {}", capabilities.features.join(", "));
+        }
     }
+
+    std::process::exit(0);
+}
+
+/**
+ * Parses an address given on the command line, written like a literal in the source ($C000, %1010 or 49152)
+ */
+fn parse_address(arg: &str, value: &str) -> u16 {
+    match parse_number(value).and_then(|address| u16::try_from(address).ok()) {
+        Some(address) => address,
+        None => {
+            eprintln!("Invalid address '{value}' for {arg} argument! Expected a 16-bit address like $C000");
+            print_help_statement();
+            std::process::exit(1);
+        }
+    }
+}
+
+/**
+ * Parses a define given as NAME or NAME=VALUE, where the value is a 16-bit number like $2580.
+ * `location` is the file and line of a define read from a define file, which errors point at.
+ */
+fn parse_define(arg: &str, define: &str, location: Option<&str>) -> (String, Option<u16>) {
+    let report = |message: String, help: &str| -> ! {
+        let diagnostic =
+            Diagnostic::new(Severity::Error, Code::InvalidToken, message.as_str()).with_help(help);
+
+        match location {
+            Some(location) => diagnostic
+                .with_note(format!("In define file {location}").as_str())
+                .print(),
+            None => diagnostic.print(),
+        }
+
+        std::process::exit(EXIT_ASSEMBLY_ERROR);
+    };
+
+    let (name, value) = match define.split_once('=') {
+        Some((name, value)) => (name.trim(), Some(value)),
+        None => (define, None),
+    };
+
+    // Variables are used in expressions, so they are named like labels
+    if !is_valid_label_name(name) {
+        report(
+            if name.is_empty() {
+                format!("Expected a name before the value of {arg} {define}!")
+            } else {
+                format!("`{name}` is not a valid name for {arg}!")
+            },
+            "Names start with a letter or `_` followed by letters, digits or `_`, such as DEBUG or BAUD_RATE",
+        );
+    }
+
+    let Some(value) = value else {
+        return (name.to_owned(), None);
+    };
+
+    match parse_number(value.trim()).and_then(|value| u16::try_from(value).ok()) {
+        Some(number) => (name.to_owned(), Some(number)),
+        None => report(
+            format!("Invalid value '{}' for {arg} {name}!", value.trim()),
+            "Values are 16-bit numbers, such as 9600 or $2580",
+        ),
+    }
+}
+
+/**
+ * Parses a ROM size in bytes, which can be given in kilobytes with a `K` suffix (e.g. 32K)
+ */
+fn parse_rom_size(arg: &str, value: &str) -> u32 {
+    let size = match value.strip_suffix(['K', 'k']) {
+        Some(kilobytes) => parse_number(kilobytes).and_then(|size| size.checked_mul(1024)),
+        None => parse_number(value),
+    };
+
+    match size {
+        Some(size) if size > 0 && size <= 0x10000 => size,
+        _ => {
+            eprintln!("Invalid ROM size '{value}' for {arg} argument! Expected a size from 1 to 64K bytes");
+            print_help_statement();
+            std::process::exit(1);
+        }
+    }
+}
+
+/**
+ * Parses a number written like a literal in the source ($C000, 0xC000, %1010, 0b1010 or 49152)
+ */
+fn parse_number(value: &str) -> Option<u32> {
+    let parsed = if let Some(hex) = value.strip_prefix('$').or_else(|| value.strip_prefix("0x")) {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(binary) = value.strip_prefix('%').or_else(|| value.strip_prefix("0b")) {
+        u32::from_str_radix(binary, 2)
+    } else {
+        value.parse::<u32>()
+    };
+
+    parsed.ok()
+}
+
+/**
+ * Reads defines from a file with one NAME[=VALUE] per line.
+ * Everything after a `;` or `#` is a comment and blank lines are skipped. Every define comes with its line number.
+ */
+fn read_define_file(define_file: &str) -> Vec<(usize, String)> {
+    let content = fs::read_to_string(define_file).unwrap_or_else(|err| {
+        report_io_error(
+            Code::UnreadableFile,
+            "read define file",
+            Path::new(define_file),
+            &err,
+        )
+    });
+
+    content
+        .lines()
+        .map(|line| match line.find([';', '#']) {
+            Some(comment_start) => &line[..comment_start],
+            None => line,
+        })
+        .map(|line| line.trim())
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| (index + 1, line.to_owned()))
+        .collect()
 }
 
 /**
@@ -125,17 +935,61 @@ fn parse_args(mut args: VecDeque<String>) -> AssemblerArguments {
  */
 fn print_usage() {
     println!("      SPASM - sis16 Assembler");
-    println!("");
+    println!();
     println!("Usage:");
     println!("  spasm --version");
     println!("  spasm --help");
+    println!("  spasm --capabilities [--json]");
+    println!("  spasm --explain <code>");
     println!("  spasm [-o out_file] [options...] file_name");
+    println!("  spasm verify <image> <manifest>");
+    println!("  spasm rename <old_label> <new_label> <files...>");
     println!();
     println!("Options:");
     println!("  -h, --help                    Prints this help dialogue");
     println!("  -o, --output <output_path>    Specifies the output file path");
-    println!("  -d, --debug                   Emits debug information");
+    println!("  -f, --format <format>         Output format: bin (default), ihex, srec, hexdump,");
+    println!("                                sis16-exe");
+    println!("  -d, --debug                   Emits a JSON source map next to the output");
+    println!("  -a, --annotate                Prints each line with the bytes it assembled to");
     println!("  -D, --define <variable_name>  Define a compile time variable");
+    println!("  -D, --define <name>=<value>   Define a compile time variable with a 16-bit value");
+    println!("  -D, --define @<file>          Define every variable listed in a file");
+    println!("  -I, --include-path <dir>      Search <dir> for .include files (can be repeated)");
+    println!("  --text-org <address>          Load address of the .text section (default $0000)");
+    println!(
+        "  --data-org <address>          Load address of the .data section (default after .text)"
+    );
+    println!("  --rom-size <size>             Pads the output to exactly <size> bytes (e.g. 8K or $2000)");
+    println!("  --fill <byte>                 Byte used for gaps and padding (default $FF with --rom-size)");
+    println!("  --endian <big|little>         Byte order of 16-bit words (default little)");
+    println!(
+        "  --checksum <sum|crc16|crc32>  Appends a checksum to the image (or fills in .checksum)"
+    );
+    println!("  -m, --manifest                Emits section checksums for 'spasm verify' next to the output");
+    println!("  --ignore-case                 Accepts instructions and directives in any case (e.g. MOV, .WORD)");
+    println!(
+        "  --encoding <utf8|latin1>      Encoding of the source and included files (default utf8)"
+    );
+    println!("  --tab-width <columns>         Columns between tab stops when showing source in errors (default 4)");
+    println!("  --no-relax                    Errors on branches out of short range instead of making them long");
+    println!("  -W<warning>, -Wno-<warning>   Turns a warning on or off (redundant, unused-label,");
+    println!("                                truncation)");
+    println!(
+        "  -Werror                       Treats warnings as errors, so assembly fails on them"
+    );
+    println!(
+        "  --max-errors <count>          Stops after <count> errors, 0 for no limit (default 20)"
+    );
+    println!(
+        "  --message-format <format>     Prints errors and warnings as human (default) or sarif"
+    );
+    println!("  --fix                         Applies the fixes suggested by errors and warnings to the source");
+    println!("  --explain-on-error            Explains each kind of error after it is first shown in a terminal");
+    println!("  --color <auto|always|never>   Colours errors and warnings (default auto, off if NO_COLOR is set");
+    println!("                                or they are not printed to a terminal)");
+    println!("  --diagnostic-paths <style>    Shows paths in errors as absolute (default) or relative to the");
+    println!("                                working directory");
     println!("  -v, --version                 Print the current version");
     println!();
     println!("Examples:");
@@ -0,0 +1,143 @@
+use crate::{
+    checksum::{crc32, sha256},
+    compile::Assembly,
+    json::{self, Json},
+};
+
+impl Assembly {
+    /**
+     * Render the integrity manifest written by `--manifest`, checked later with `spasm verify`.
+     *
+     * It has the CRC-32 and SHA-256 of the whole binary image and of every contiguous run of a section in it.
+     * Offsets are from the start of the binary image.
+     */
+    pub fn manifest(&self) -> String {
+        let image = self.image();
+        let origin = self.origin();
+
+        let sections: Vec<_> = self
+            .section_runs()
+            .iter()
+            .map(|(section, address, bytes)| {
+                format!(
+                    "    {{ \"section\": {}, \"address\": {}, \"offset\": {}, \"size\": {}, {} }}",
+                    json::string(section.name()),
+                    address,
+                    address - origin,
+                    bytes.len(),
+                    digests(bytes),
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\n  \"version\": 1,\n  \"image\": {{ \"size\": {}, {} }},\n  \"sections\": [\n{}\n  ]\n}}\n",
+            image.len(),
+            digests(&image),
+            sections.join(",\n")
+        )
+    }
+}
+
+fn digests(bytes: &[u8]) -> String {
+    format!(
+        "\"crc32\": \"{:08X}\", \"sha256\": \"{}\"",
+        crc32(bytes),
+        hex_digest(bytes)
+    )
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    sha256(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/**
+ * The result of checking one part of an image against the manifest
+ */
+#[derive(Debug, Clone)]
+pub struct ManifestCheck {
+    // `image` for the whole image, otherwise the name of the section
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+    pub ok: bool,
+}
+
+/**
+ * Check a binary image against the manifest it was assembled with.
+ *
+ * Returns one check for the whole image and one for every section, or an error if the manifest is invalid.
+ */
+pub fn verify_manifest(image: &[u8], manifest: &str) -> Result<Vec<ManifestCheck>, String> {
+    let manifest = json::parse(manifest).map_err(|err| format!("Invalid manifest: {err}"))?;
+
+    if manifest.get("version").and_then(Json::as_u64) != Some(1) {
+        return Err("Unsupported manifest version, expected version 1".to_owned());
+    }
+
+    let entry = |value: &Json, name: String| -> Result<ManifestCheck, String> {
+        let field = |key: &str| {
+            value
+                .get(key)
+                .ok_or_else(|| format!("Manifest entry for `{name}` is missing `{key}`"))
+        };
+
+        let offset = match value.get("offset") {
+            Some(offset) => offset.as_u64().ok_or("Manifest offsets must be numbers")? as usize,
+            None => 0,
+        };
+
+        let size = field("size")?
+            .as_u64()
+            .ok_or("Manifest sizes must be numbers")? as usize;
+
+        let crc = field("crc32")?
+            .as_str()
+            .ok_or("Manifest CRC-32 values must be strings")?;
+        let sha = field("sha256")?
+            .as_str()
+            .ok_or("Manifest SHA-256 values must be strings")?;
+
+        let ok = match image.get(offset..offset + size) {
+            Some(bytes) => {
+                crc.eq_ignore_ascii_case(&format!("{:08X}", crc32(bytes)))
+                    && sha.eq_ignore_ascii_case(&hex_digest(bytes))
+            }
+            None => false,
+        };
+
+        Ok(ManifestCheck {
+            name,
+            offset,
+            size,
+            ok,
+        })
+    };
+
+    let whole_image = manifest.get("image").ok_or("Manifest is missing `image`")?;
+    let mut image_check = entry(whole_image, "image".to_owned())?;
+
+    // Extra bytes at the end of the image are also a mismatch
+    image_check.ok &= image.len() == image_check.size;
+
+    let mut checks = vec![image_check];
+
+    let sections = manifest
+        .get("sections")
+        .and_then(Json::as_array)
+        .ok_or("Manifest is missing `sections`")?;
+
+    for section in sections {
+        let name = section
+            .get("section")
+            .and_then(Json::as_str)
+            .ok_or("Manifest sections must have a `section` name")?;
+
+        checks.push(entry(section, name.to_owned())?);
+    }
+
+    Ok(checks)
+}
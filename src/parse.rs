@@ -1,15 +1,82 @@
-use core::panic;
-use std::{collections::VecDeque, num::IntErrorKind, path::PathBuf};
+use std::{collections::VecDeque, path::Path};
 
 use crate::{
-    report_error,
-    token::{Token, TokenType},
+    capabilities::DIRECTIVES,
+    checksum::ChecksumAlgorithm,
+    code::Code,
+    compile::{Endian, Operand},
+    diagnostic::{self, Diagnostic, Diagnostics, Severity, WarningOptions},
+    expr::Expression,
+    isa::{Mnemonic, OperandKind, IO_PORTS},
+    report_error, report_error_with_note, report_span_error, report_span_error_with_help,
+    report_span_error_with_label,
+    suggest,
+    resolve::Section,
+    table::{self, TableWidth},
+    token::{Span, Token, TokenType},
 };
 
 #[derive(Debug)]
 pub struct Program {
     pub text: Option<TextSection>,
     pub data: Option<DataSection>,
+    // Labels that only reserve space with .res, which is zeroed at startup instead of stored in the image
+    pub bss: Option<DataSection>,
+    // Sections of code declared with .section <name>, in the order they appear
+    pub sections: Vec<NamedSection>,
+    pub regions: Vec<Region>,
+    // Byte order set with .endian, which overrides the command line
+    pub endian: Option<Spanned<Endian>>,
+    // Label where execution starts, set with .entry
+    pub entry: Option<Spanned<String>>,
+    // Labels exported to other files with .global
+    pub globals: Vec<Spanned<String>>,
+    // Labels defined in another file, declared with .extern
+    pub externs: Vec<Spanned<String>>,
+    // Conditions checked with .assert once every label has an address
+    pub assertions: Vec<Assertion>,
+    // Values printed with .print or .info once every label has an address
+    pub messages: Vec<Message>,
+}
+
+/**
+ * An `.assert <condition>[, "<message>"]` that fails the build if its condition is false
+ */
+#[derive(Debug)]
+pub struct Assertion {
+    pub condition: Expression,
+    pub message: Option<Spanned<String>>,
+}
+
+/**
+ * A `.print <values>` (or `.info`) that prints strings and the values of expressions during assembly
+ */
+#[derive(Debug)]
+pub struct Message {
+    pub arguments: Vec<Expression>,
+    pub span: Span,
+}
+
+/**
+ * A section of subroutine labels declared with `.section <name>[, org=<address>]` on a line of its own
+ */
+#[derive(Debug)]
+pub struct NamedSection {
+    pub name: String,
+    // Address set with org=, otherwise the section follows the text section
+    pub origin: Option<u16>,
+    pub text: TextSection,
+    pub span: Span,
+}
+
+/**
+ * A named memory region that labels can be placed in with `.section <name>` after a label
+ */
+#[derive(Debug)]
+pub struct Region {
+    pub name: String,
+    pub address: u16,
+    pub span: Span,
 }
 
 impl Program {
@@ -17,6 +84,15 @@ impl Program {
         Program {
             text: None,
             data: None,
+            bss: None,
+            sections: Vec::new(),
+            regions: Vec::new(),
+            endian: None,
+            entry: None,
+            globals: Vec::new(),
+            externs: Vec::new(),
+            assertions: Vec::new(),
+            messages: Vec::new(),
         }
     }
 
@@ -29,13 +105,7 @@ impl Program {
             return None;
         };
 
-        for label in &data.labels {
-            if label.name == name {
-                return Some(&label);
-            }
-        }
-
-        None
+        data.labels.iter().find(|label| label.name == name)
     }
 
     /**
@@ -47,55 +117,173 @@ impl Program {
             return None;
         };
 
-        for label in &text.labels {
-            if label.name == name {
-                return Some(&label);
-            }
-        }
-
-        None
+        text.labels.iter().find(|label| label.name == name)
     }
 }
 
-trait Parsable {
-    fn parse(path: &PathBuf, lines: &Vec<String>, tokens: &mut VecDeque<Token>) -> Self
+/**
+ * A parsed node along with the location it came from in the source
+ */
+#[derive(Debug)]
+pub struct Spanned<T> {
+    pub span: Span,
+    pub node: T,
+}
+
+pub(crate) trait Parsable {
+    fn parse(path: &Path, lines: &[String], tokens: &mut VecDeque<Token>) -> diagnostic::Result<Self>
     where
         Self: Sized;
 }
 
 #[derive(Debug)]
 pub struct DataSection {
-    labels: Vec<ConstantLabel>,
+    pub labels: Vec<ConstantLabel>,
 }
 
 #[derive(Debug)]
 pub struct ConstantLabel {
-    name: String,
-    constants: Vec<ConstantLabelType>,
+    pub name: String,
+    pub span: Span,
+    pub attributes: LabelAttributes,
+    pub origin: Option<Spanned<u16>>,
+    pub constants: Vec<Spanned<ConstantLabelType>>,
+    // Parts of the label moved by a .org in the middle of it
+    pub continuations: Vec<Continuation>,
+}
+
+/**
+ * The rest of a label after a `.org` in the middle of it, which is placed at the address of the `.org`
+ */
+#[derive(Debug)]
+pub struct Continuation {
+    // Index of the first item placed at the address
+    pub start: usize,
+    pub origin: Spanned<u16>,
+}
+
+/**
+ * The address the item at `index` of a label is moved to by a `.org` in the middle of the label, if any
+ */
+pub fn continuation_at(continuations: &[Continuation], index: usize) -> Option<&Spanned<u16>> {
+    continuations
+        .iter()
+        .find(|continuation| continuation.start == index)
+        .map(|continuation| &continuation.origin)
 }
 
 #[derive(Debug)]
 pub enum ConstantLabelType {
     StringLiteral(String),
     Word(u16),
+    Byte(u8),
+    // Values computed from an expression once every label has an address, such as `.word handler` or `.byte hi(table)`
+    DeferredWord(Expression),
+    DeferredByte(Expression),
+    // Raw bytes spliced in from a file with .incbin
+    Bytes(Vec<u8>),
+    // Bytes reserved with .space or .res, all set to the same value
+    Space { size: u16, value: u8 },
+    // Space for a checksum of the image, filled in once everything is assembled
+    Checksum(ChecksumAlgorithm),
 }
 
-impl Parsable for DataSection {
-    fn parse(path: &PathBuf, lines: &Vec<String>, tokens: &mut VecDeque<Token>) -> DataSection {
+impl DataSection {
+    pub(crate) fn parse(
+        path: &Path,
+        lines: &[String],
+        tokens: &mut VecDeque<Token>,
+        diagnostics: &mut Diagnostics,
+    ) -> diagnostic::Result<DataSection> {
         let mut data = DataSection { labels: Vec::new() };
 
+        // Address set by a .org for the next label
+        let mut origin = None;
+
         // Loop through every label in the section
         while !tokens.is_empty() {
             let first_token = tokens.pop_front().unwrap();
 
-            // Check for end of section or illegal directives
-            if let TokenType::Directive(name) = &first_token.token_type {
-                if name == "data" || name == "text" {
-                    tokens.push_front(first_token);
-                    return data;
-                } else {
-                    report_error(
-                        format!("Illegal directive token `.{}`", name).as_str(),
+            // Check for end of section
+            if matches!(&first_token.token_type, TokenType::Directive(name) if is_section_directive(name)) {
+                tokens.push_front(first_token);
+                break;
+            }
+
+            // After an error, carry on from the next label so the rest of the section is still checked
+            let parsed = (|| -> diagnostic::Result<()> {
+                // Check for illegal directives
+                if let TokenType::Directive(name) = &first_token.token_type {
+                    if name == "org" {
+                        if origin.is_some() {
+                            return report_span_error(
+                                Code::DuplicateDirective,
+                                "Only one .org is allowed before a label!",
+                                path,
+                                lines,
+                                first_token.span(),
+                            );
+                        }
+
+                        let address = parse_origin(path, lines, &first_token, tokens)?;
+
+                        // Anything but a label after the .org is the rest of the label before it
+                        if !tokens.front().is_none_or(is_label_boundary) {
+                            let label = continued_label(path, lines, &mut data.labels, &address)?;
+                            let constants = parse_constant_lines(path, lines, read_tokens_to_label_or_eos(tokens), diagnostics)?;
+
+                            label.continuations.push(Continuation {
+                                start: label.constants.len(),
+                                origin: address,
+                            });
+                            label.constants.extend(constants);
+
+                            return Ok(());
+                        }
+
+                        origin = Some(address);
+                        return Ok(());
+                    } else {
+                        return report_span_error_with_help(
+                            Code::UnknownDirective,
+                            format!("Illegal directive token `.{}`", name).as_str(),
+                            suggest::did_you_mean(name, ".", DIRECTIVES.iter().copied()).as_deref(),
+                            path,
+                            lines,
+                            first_token.span(),
+                        )
+                    }
+                };
+
+                // Start parsing this section as a label
+                let TokenType::Label(label_name) = &first_token.token_type else {
+                    return report_error(
+                        Code::UnexpectedToken,
+                        format!("Unexpected token `{}` in data section.", first_token.value).as_str(),
+                        path,
+                        lines,
+                        first_token.line_number,
+                        first_token.column_start,
+                        first_token.column_end,
+                    )
+                };
+
+                let mut constant_label = ConstantLabel {
+                    name: label_name.clone(),
+                    span: first_token.span(),
+                    attributes: parse_label_attributes(path, lines, &first_token, tokens)?,
+                    origin: origin.take(),
+                    constants: Vec::new(),
+                    continuations: Vec::new(),
+                };
+
+                let constant_tokens = read_tokens_to_label_or_eos(tokens);
+
+                // A label directly followed by another label has no data of its own and shares its address
+                if constant_tokens.is_empty() && !starts_with_label(tokens) {
+                    return report_error(
+                        Code::EmptyLabel,
+                        format!("Label `{}` cannot be empty!", constant_label.name).as_str(),
                         path,
                         lines,
                         first_token.line_number,
@@ -103,221 +291,432 @@ impl Parsable for DataSection {
                         first_token.column_end,
                     )
                 }
-            };
 
-            // Start parsing this section as a label
-            let TokenType::Label(label_name) = first_token.token_type else {
-                report_error(
-                    format!("Unexpected token `{}` in data section.", first_token.value).as_str(),
+                constant_label.constants = parse_constant_lines(path, lines, constant_tokens, diagnostics)?;
+
+                data.labels.push(constant_label);
+
+                // println!("{data:#?}");
+
+                Ok(())
+            })();
+
+            if diagnostics.recover(parsed)?.is_none() {
+                skip_label(tokens);
+            }
+        }
+
+        expect_label_after_origin(path, lines, &origin)?;
+
+        Ok(data)
+    }
+}
+
+/**
+ * Parse the data of a label one line at a time. A line with an error is skipped, so the lines after it are still checked.
+ */
+fn parse_constant_lines(
+    path: &Path,
+    lines: &[String],
+    mut tokens: VecDeque<Token>,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<Vec<Spanned<ConstantLabelType>>> {
+    let mut constants = Vec::new();
+
+    while !tokens.is_empty() {
+        // Every constant is a directive followed by its arguments on the same line
+        let mut constant_line = read_tokens_to_eol(&mut tokens);
+
+        let parsed = (|| -> diagnostic::Result<()> {
+            // `times <count>` assembles the rest of the line that many times
+            let repetitions = parse_times_prefix(path, lines, &mut constant_line)?;
+
+            for _ in 0..repetitions {
+                constants.extend(parse_constant(path, lines, constant_line.clone())?);
+            }
+
+            Ok(())
+        })();
+
+        diagnostics.recover(parsed)?;
+    }
+
+    Ok(constants)
+}
+
+/**
+ * Parse a line of data, which is a directive such as `.word` or `.ascii` followed by its values
+ */
+fn parse_constant(path: &Path, lines: &[String], mut constant_line: VecDeque<Token>) -> diagnostic::Result<Vec<Spanned<ConstantLabelType>>> {
+    let mut constants = Vec::new();
+
+    let directive_token = constant_line.pop_front().unwrap();
+
+    let TokenType::Directive(directive) = &directive_token.token_type else {
+        return report_error(
+            Code::InvalidDirectiveArgument,
+            "First token in a constant must be a directive!",
+            path,
+            lines,
+            directive_token.line_number,
+            directive_token.column_start,
+            directive_token.column_end,
+        )
+    };
+
+    let Some(constant_token) = constant_line.pop_front() else {
+        return report_error(
+            Code::InvalidDirectiveArgument,
+            format!("Expected a value after .{directive} directive!").as_str(),
+            path,
+            lines,
+            directive_token.line_number,
+            directive_token.column_start,
+            directive_token.column_end,
+        )
+    };
+
+    let span = directive_token
+        .span()
+        .to(&constant_line.back().unwrap_or(&constant_token).span());
+
+    match directive.as_str() {
+        "ascii" | "asciz" => {
+            // Assume the next constant is a string
+            let TokenType::AsciiString(string) = &constant_token.token_type else {
+                return report_error(
+                    Code::InvalidDirectiveArgument,
+                    format!("Expected string literal after .{directive} directive!").as_str(),
                     path,
                     lines,
-                    first_token.line_number,
-                    first_token.column_start,
-                    first_token.column_end,
+                    constant_token.line_number,
+                    constant_token.column_start,
+                    constant_token.column_end,
                 )
             };
 
-            let mut constant_label = ConstantLabel {
-                name: label_name,
-                constants: Vec::new(),
+            let mut string = unescape_string(string);
+
+            // .asciz strings are null terminated
+            if directive == "asciz" {
+                string.push('\0');
+            }
+
+            constants.push(Spanned {
+                span,
+                node: ConstantLabelType::StringLiteral(string),
+            });
+
+            expect_end_of_constant(path, lines, &constant_line, directive)?;
+        }
+        "word" => {
+            constant_line.push_front(constant_token);
+
+            // One or more words separated by commas
+            for mut value_tokens in split_tokens_by_commas(path, lines, &mut constant_line)? {
+                if is_expression_value(&value_tokens) {
+                    let expression = Expression::parse(path, lines, &mut value_tokens)?;
+
+                    constants.push(Spanned {
+                        span: expression.span(),
+                        node: ConstantLabelType::DeferredWord(expression),
+                    });
+                    continue;
+                }
+
+                let value_token = value_tokens.pop_front().unwrap();
+                let value = parse_word(path, lines, &value_token, directive)?;
+
+                expect_end_of_value(path, lines, &value_tokens, "word")?;
+
+                constants.push(Spanned {
+                    span: value_token.span(),
+                    node: ConstantLabelType::Word(value),
+                });
+            }
+        }
+        "byte" => {
+            constant_line.push_front(constant_token);
+
+            // One or more bytes separated by commas
+            for mut value_tokens in split_tokens_by_commas(path, lines, &mut constant_line)? {
+                if is_expression_value(&value_tokens) {
+                    let expression = Expression::parse(path, lines, &mut value_tokens)?;
+
+                    constants.push(Spanned {
+                        span: expression.span(),
+                        node: ConstantLabelType::DeferredByte(expression),
+                    });
+                    continue;
+                }
+
+                let value_token = value_tokens.pop_front().unwrap();
+                let value = parse_byte(path, lines, &value_token, directive)?;
+
+                expect_end_of_value(path, lines, &value_tokens, "byte")?;
+
+                constants.push(Spanned {
+                    span: value_token.span(),
+                    node: ConstantLabelType::Byte(value),
+                });
+            }
+        }
+        "space" | "res" => {
+            constant_line.push_front(constant_token);
+
+            // A size in bytes, optionally followed by the value to fill them with
+            let mut values = split_tokens_by_commas(path, lines, &mut constant_line)?;
+
+            let mut size_tokens = values.pop_front().unwrap();
+            let size_token = size_tokens.pop_front().unwrap();
+            let size = parse_word(path, lines, &size_token, directive)?;
+            expect_end_of_value(path, lines, &size_tokens, "size")?;
+
+            let value = match values.pop_front() {
+                Some(mut value_tokens) => {
+                    let value_token = value_tokens.pop_front().unwrap();
+                    let value = parse_byte(path, lines, &value_token, directive)?;
+                    expect_end_of_value(path, lines, &value_tokens, "fill")?;
+                    value
+                }
+                None => 0,
             };
 
-            let mut constant_tokens = read_tokens_to_label_or_eos(tokens);
+            if let Some(mut extra_tokens) = values.pop_front() {
+                let extra_token = extra_tokens.pop_front().unwrap();
 
-            if constant_tokens.len() == 0 {
-                report_error(
-                    format!("Label `{}` cannot be empty!", constant_label.name).as_str(),
+                return report_span_error(
+                    Code::InvalidDirectiveArgument,
+                    format!("Unexpected value `{}`, .{directive} takes a size and an optional fill value!", extra_token.value).as_str(),
                     path,
                     lines,
-                    first_token.line_number,
-                    first_token.column_start,
-                    first_token.column_end,
+                    extra_token.span(),
                 )
             }
 
-            while !constant_tokens.is_empty() {
-                if constant_tokens.len() == 1 {
-                    let token = constant_tokens.front().unwrap();
-
-                    report_error(
-                        "Expected at least 2 tokens in constant.",
-                        path,
-                        lines,
-                        token.line_number,
-                        token.column_start,
-                        token.column_end,
-                    )
-                }
+            constants.push(Spanned {
+                span,
+                node: ConstantLabelType::Space { size, value },
+            });
+        }
+        "incbin" => {
+            let TokenType::AsciiString(file_name) = &constant_token.token_type else {
+                return report_span_error(
+                    Code::InvalidDirectiveArgument,
+                    "Expected file name string after .incbin directive!",
+                    path,
+                    lines,
+                    constant_token.span(),
+                )
+            };
 
-                let directive_token = constant_tokens.pop_front().unwrap();
-                let constant_token = constant_tokens.pop_front().unwrap();
+            constant_line.push_front(constant_token.clone());
+
+            // The file name is optionally followed by an offset and a length in bytes
+            let mut values = split_tokens_by_commas(path, lines, &mut constant_line)?;
+
+            let mut file_tokens = values.pop_front().unwrap();
+            file_tokens.pop_front();
+            expect_end_of_value(path, lines, &file_tokens, "file name")?;
+
+            let mut read_value = |kind: &str| -> diagnostic::Result<Option<Spanned<u16>>> {
+                values
+                    .pop_front()
+                    .map(|mut value_tokens| {
+                        let value_token = value_tokens.pop_front().unwrap();
+                        let value = parse_word(path, lines, &value_token, directive)?;
+                        expect_end_of_value(path, lines, &value_tokens, kind)?;
+
+                        Ok(Spanned {
+                            span: value_token.span(),
+                            node: value,
+                        })
+                    })
+                    .transpose()
+            };
 
-                let TokenType::Directive(directive) = &directive_token.token_type else {
-                    report_error(
-                        "First token in a constant must be a directive!",
-                        path,
-                        lines,
-                        directive_token.line_number,
-                        directive_token.column_start,
-                        directive_token.column_end,
-                    )
-                };
+            let offset = read_value("offset")?;
+            let length = read_value("length")?;
 
-                match directive.as_str() {
-                    "ascii" => {
-                        // Assume the next constant is a string
-                        let TokenType::AsciiString(string) = &constant_token.token_type else {
-                            report_error(
-                                format!("Expected string literal after .ascii directive!").as_str(),
-                                path,
-                                lines,
-                                constant_token.line_number,
-                                constant_token.column_start,
-                                constant_token.column_end,
-                            )
-                        };
+            if let Some(mut extra_tokens) = values.pop_front() {
+                let extra_token = extra_tokens.pop_front().unwrap();
 
-                        constant_label
-                            .constants
-                            .push(ConstantLabelType::StringLiteral(string.clone()))
-                    }
-                    "word" => {
-                        match &constant_token.token_type {
-                            TokenType::Binary(value) => {
-                                // Parse from string value
-                                let bin_value = match u16::from_str_radix(value, 2) {
-                                    Ok(v) => v,
-                                    Err(err) => match err.kind() {
-                                        // Greater than a 16 bit word
-                                        IntErrorKind::PosOverflow =>  report_error(
-                                            "Binary literal is larger than expected 16-bit word! (Max is %1111111111111111)",
-                                            path,
-                                            lines,
-                                            constant_token.line_number,
-                                            constant_token.column_start,
-                                            constant_token.column_end,
-                                        ),
-                                        kind => panic!("Unexpected IntErrorKind {kind:?}")
-                                    }
-                                };
-
-                                // Add constant to current label
-                                constant_label
-                                    .constants
-                                    .push(ConstantLabelType::Word(bin_value))
-                            }
-                            TokenType::Decimal(value) => {
-                                // Parse from string value
-                                let dec_value = match u16::from_str_radix(value, 10) {
-                                    Ok(v) => v,
-                                    Err(err) => match err.kind() {
-                                        // Greater than a 16 bit word
-                                        IntErrorKind::PosOverflow =>  report_error(
-                                            "Decimal literal is larger than expected 16-bit word! (Max is 65535)",
-                                            path,
-                                            lines,
-                                            constant_token.line_number,
-                                            constant_token.column_start,
-                                            constant_token.column_end,
-                                        ),
-                                        kind => panic!("Unexpected IntErrorKind {kind:?}")
-                                    }
-                                };
-
-                                // Add constant to current label
-                                constant_label
-                                    .constants
-                                    .push(ConstantLabelType::Word(dec_value))
-                            }
-                            TokenType::Hex(value) => {
-                                // Parse from string value
-                                let hex_value = match u16::from_str_radix(value, 16) {
-                                    Ok(v) => v,
-                                    Err(err) => match err.kind() {
-                                        // Greater than a 16 bit word
-                                        IntErrorKind::PosOverflow =>  report_error(
-                                            "Hexadecimal literal is larger than expected 16-bit word! (Max is $FFFF)",
-                                            path,
-                                            lines,
-                                            constant_token.line_number,
-                                            constant_token.column_start,
-                                            constant_token.column_end,
-                                        ),
-                                        kind => panic!("Unexpected IntErrorKind {kind:?}")
-                                    }
-                                };
-
-                                // Add constant to current label
-                                constant_label
-                                    .constants
-                                    .push(ConstantLabelType::Word(hex_value))
-                            }
-                            TokenType::Immediate => report_error(
-                                "The .word directive does not require an immediate `#` marker!",
-                                path,
-                                lines,
-                                constant_token.line_number,
-                                constant_token.column_start,
-                                constant_token.column_end,
-                            ),
-                            _ => report_error(
-                                "Expected a number literal after .word directive!",
-                                path,
-                                lines,
-                                constant_token.line_number,
-                                constant_token.column_start,
-                                constant_token.column_end,
-                            ),
-                        }
-                    }
-                    _ => report_error(
-                        format!("Unknown constant directive `.{directive}`!").as_str(),
-                        path,
-                        lines,
-                        directive_token.line_number,
-                        directive_token.column_start,
-                        directive_token.column_end,
-                    ),
-                }
+                return report_span_error(
+                    Code::InvalidDirectiveArgument,
+                    format!("Unexpected value `{}`, .incbin takes a file name, an optional offset and an optional length!", extra_token.value).as_str(),
+                    path,
+                    lines,
+                    extra_token.span(),
+                )
             }
 
-            data.labels.push(constant_label);
+            let bytes = table::read_binary(path, lines, file_name, &constant_token, offset, length)?;
+
+            constants.push(Spanned {
+                span,
+                node: ConstantLabelType::Bytes(bytes),
+            });
+        }
+        "inctable" => {
+            let TokenType::AsciiString(file_name) = &constant_token.token_type else {
+                return report_error(
+                    Code::InvalidDirectiveArgument,
+                    "Expected file name string after .inctable directive!",
+                    path,
+                    lines,
+                    constant_token.line_number,
+                    constant_token.column_start,
+                    constant_token.column_end,
+                )
+            };
+
+            // The table element type follows the file name
+            let width_token = match (constant_line.pop_front(), constant_line.pop_front()) {
+                (
+                    Some(Token { token_type: TokenType::Comma, .. }),
+                    Some(width_token @ Token { token_type: TokenType::Identifier(_), .. }),
+                ) => width_token,
+                _ => return report_error(
+                    Code::InvalidDirectiveArgument,
+                    "Expected `, word` or `, byte` after .inctable file name!",
+                    path,
+                    lines,
+                    span.line_number,
+                    span.column_start,
+                    span.column_end,
+                ),
+            };
+
+            let width = match width_token.value.as_str() {
+                "word" => TableWidth::Word,
+                "byte" => TableWidth::Byte,
+                other => return report_error(
+                    Code::InvalidDirectiveArgument,
+                    format!("Unknown table element type `{other}`! Expected `word` or `byte`").as_str(),
+                    path,
+                    lines,
+                    width_token.line_number,
+                    width_token.column_start,
+                    width_token.column_end,
+                ),
+            };
+
+            expect_end_of_constant(path, lines, &constant_line, directive)?;
 
-            // println!("{data:#?}");
+            let table = table::read_table(path, lines, file_name, &constant_token, width)?;
+
+            constants.extend(
+                table.into_iter().map(|node| Spanned { span, node }),
+            );
         }
+        "checksum" => {
+            let algorithm = match &constant_token.token_type {
+                TokenType::Identifier(name) => ChecksumAlgorithm::from_name(name),
+                _ => None,
+            };
 
-        data
+            let Some(algorithm) = algorithm else {
+                return report_error(
+                    Code::InvalidDirectiveArgument,
+                    "Expected `sum`, `crc16` or `crc32` after .checksum directive!",
+                    path,
+                    lines,
+                    constant_token.line_number,
+                    constant_token.column_start,
+                    constant_token.column_end,
+                )
+            };
+
+            constants.push(Spanned {
+                span,
+                node: ConstantLabelType::Checksum(algorithm),
+            });
+
+            expect_end_of_constant(path, lines, &constant_line, directive)?;
+        }
+        _ => return report_span_error_with_help(
+            Code::UnknownDirective,
+            format!("Unknown constant directive `.{directive}`!").as_str(),
+            suggest::did_you_mean(directive, ".", DIRECTIVES.iter().copied()).as_deref(),
+            path,
+            lines,
+            directive_token.span(),
+        ),
     }
+
+    Ok(constants)
 }
 
 #[derive(Debug)]
 pub struct TextSection {
-    labels: Vec<SubroutineLabel>,
+    pub labels: Vec<SubroutineLabel>,
 }
 
 #[derive(Debug)]
 pub struct SubroutineLabel {
-    name: String,
-    instructions: Vec<Instruction>,
+    pub name: String,
+    pub span: Span,
+    pub attributes: LabelAttributes,
+    pub origin: Option<Spanned<u16>>,
+    pub contents: Vec<Spanned<SubroutineItem>>,
+    // Parts of the label moved by a .org in the middle of it
+    pub continuations: Vec<Continuation>,
+}
+
+/**
+ * A line of a subroutine, either an instruction or data placed between its instructions
+ */
+#[derive(Debug)]
+pub enum SubroutineItem {
+    Instruction {
+        instruction: Instruction,
+        // Operands filled in once labels are placed
+        deferred: Vec<DeferredOperand>,
+    },
+    Constant(ConstantLabelType),
 }
 
 #[rustfmt::skip]
 #[derive(Debug)]
+#[allow(dead_code)]
 pub enum InstructionArgumentType {
     Immediate(u16),       // Immediate Value - #$FFFF     ; Uses the immediate value as the argument
     MemoryAddress(u16),         // Memory Address - $FFFF       ; Uses the 8-bit value at this memory address as the argument
     MemoryAddressIndirect(u16), // Memory Address - ($FFFF)     ; Uses the little endian 16-bit word at this memory address as the argument
+    RegisterIndirect(Register), // Register - (%ebx)            ; Uses the value at the memory address held in this register as the argument
+    Indexed(IndexedAddress),    // Indexed - $8000(%ecx)        ; Uses the value at the memory address plus the value of the register as the argument
+    IndexedLabel(String, Register), // Indexed - table(%ecx)    ; Uses the value at the address of the label plus the value of the register as the argument
     LabelAddress(String),       // Label Name - boot_loader     ; Uses the rom address of the constant as the argument
     LabelValue(String),         // Label Name - [boot_loader]   ; Uses the immediate value of this constant as the argument
     Register(Register),         // Register - %eax              ; Uses this register as the argument
 }
 
+impl InstructionArgumentType {
+    /**
+     * The kind of operand the argument is in the ISA spec, or `None` if no instruction takes it
+     */
+    fn kind(&self) -> Option<OperandKind> {
+        let kind = match self {
+            InstructionArgumentType::Immediate(_) => OperandKind::Immediate,
+            InstructionArgumentType::MemoryAddress(_) => OperandKind::MemoryAddress,
+            InstructionArgumentType::MemoryAddressIndirect(_) => OperandKind::MemoryAddressIndirect,
+            InstructionArgumentType::RegisterIndirect(_) => OperandKind::RegisterIndirect,
+            InstructionArgumentType::Indexed(_) | InstructionArgumentType::IndexedLabel(..) => OperandKind::Indexed,
+            InstructionArgumentType::LabelAddress(_) => OperandKind::Label,
+            InstructionArgumentType::LabelValue(_) => return None,
+            InstructionArgumentType::Register(_) => OperandKind::Register,
+        };
+
+        Some(kind)
+    }
+}
+
 impl Parsable for InstructionArgumentType {
     fn parse(
-        path: &PathBuf,
-        lines: &Vec<String>,
+        path: &Path,
+        lines: &[String],
         tokens: &mut VecDeque<Token>,
-    ) -> InstructionArgumentType {
+    ) -> diagnostic::Result<InstructionArgumentType> {
         assert!(
             !tokens.is_empty(),
             "Vec passed to InstructionArgumentType parser should contain at least one token"
@@ -325,15 +724,26 @@ impl Parsable for InstructionArgumentType {
 
         let first_token = tokens.pop_front().unwrap();
 
-        match &first_token.token_type {
+        Ok(match &first_token.token_type {
             TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_) => {
-                let value = first_token.parse_u16(path, lines);
+                let value = first_token.parse_u16(path, lines)?;
+
+                // `$8000(%ecx)` adds an index register to the address
+                if tokens.front().is_some_and(|token| token.token_type == TokenType::OpenParenthesis) {
+                    let open_token = tokens.pop_front().unwrap();
+
+                    return Ok(InstructionArgumentType::Indexed(IndexedAddress {
+                        base: value,
+                        index: parse_index_register(path, lines, &open_token, tokens)?,
+                    }));
+                }
 
                 // There should not be any more tokens after a memory literal
                 if !tokens.is_empty() {
                     let illegal_token = tokens.pop_front().unwrap();
 
-                    report_error(
+                    return report_error(
+                        Code::UnexpectedToken,
                         format!(
                             "Unexpected token `{}` after number literal!",
                             illegal_token.value
@@ -352,7 +762,8 @@ impl Parsable for InstructionArgumentType {
             TokenType::Immediate => {
                 // Make sure that there is a number after the immediate specifier
                 let Some(number_token) = tokens.pop_front() else {
-                    report_error(
+                    return report_error(
+                        Code::UnexpectedToken,
                         "Expected number literal after immediate specifier `#`!",
                         path,
                         lines,
@@ -364,13 +775,14 @@ impl Parsable for InstructionArgumentType {
 
                 match &number_token.token_type {
                     TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_) => {
-                        let value = number_token.parse_u16(path, lines);
+                        let value = number_token.parse_u16(path, lines)?;
 
                         // There should not be any more tokens after an immediate value
                         if !tokens.is_empty() {
                             let illegal_token = tokens.pop_front().unwrap();
 
-                            report_error(
+                            return report_error(
+                                Code::UnexpectedToken,
                                 format!(
                                     "Unexpected token `{}` after immediate number literal!",
                                     illegal_token.value
@@ -386,7 +798,8 @@ impl Parsable for InstructionArgumentType {
 
                         InstructionArgumentType::Immediate(value)
                     }
-                    _ => report_error(
+                    _ => return report_error(
+                        Code::UnexpectedToken,
                         format!(
                             "Unexpected token `{}` after immediate specifier!",
                             number_token.value
@@ -401,10 +814,11 @@ impl Parsable for InstructionArgumentType {
                 }
             }
             TokenType::OpenParenthesis => {
-                // Make sure that there is a number after the opening paren
+                // Make sure that there is a number or register after the opening paren
                 let Some(address_token) = tokens.pop_front() else {
-                    report_error(
-                        "Expected memory address after opening parenthesis `(`!",
+                    return report_error(
+                        Code::UnexpectedToken,
+                        "Expected memory address or register after opening parenthesis `(`!",
                         path,
                         lines,
                         first_token.line_number,
@@ -413,11 +827,61 @@ impl Parsable for InstructionArgumentType {
                     )
                 };
 
-                let address = match &address_token.token_type {
+                // `($8000, %ecx)` and `(table, %ecx)` add an index register to the address
+                if tokens.front().is_some_and(|token| token.token_type == TokenType::Comma) {
+                    let comma_token = tokens.pop_front().unwrap();
+                    let index = parse_index_register(path, lines, &comma_token, tokens)?;
+
+                    return Ok(match &address_token.token_type {
+                        TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_) => {
+                            InstructionArgumentType::Indexed(IndexedAddress {
+                                base: address_token.parse_u16(path, lines)?,
+                                index,
+                            })
+                        }
+                        TokenType::Identifier(label) => InstructionArgumentType::IndexedLabel(label.clone(), index),
+                        _ => return report_span_error(
+                            Code::UnexpectedToken,
+                            format!("Unexpected token `{}`, expected the base address of an indexed address!", address_token.value).as_str(),
+                            path,
+                            lines,
+                            address_token.span(),
+                        ),
+                    });
+                }
+
+                let argument = match &address_token.token_type {
                     TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_) => {
-                        address_token.parse_u16(path, lines)
+                        InstructionArgumentType::MemoryAddressIndirect(address_token.parse_u16(path, lines)?)
+                    }
+                    TokenType::Register(name) => {
+                        let register = parse_register_name(path, lines, name, address_token.span())?;
+
+                        // Addresses are 16 bits, so only the 16-bit registers can point to memory
+                        if register.width() != 16 {
+                            return report_span_error(
+                                Code::OperandSizeMismatch,
+                                format!("Register `%{}` holds {} bits and cannot hold a memory address!", register.name(), register.width()).as_str(),
+                                path,
+                                lines,
+                                address_token.span(),
+                            )
+                        }
+
+                        if !register.can_address() {
+                            return report_span_error(
+                                Code::InvalidRegister,
+                                format!("Register `%{}` cannot be used as a pointer!", register.name()).as_str(),
+                                path,
+                                lines,
+                                address_token.span(),
+                            )
+                        }
+
+                        InstructionArgumentType::RegisterIndirect(register)
                     }
-                    _ => report_error(
+                    _ => return report_error(
+                        Code::UnexpectedToken,
                         format!(
                             "Unexpected token `{}` after opening parenthesis!",
                             address_token.value
@@ -434,18 +898,31 @@ impl Parsable for InstructionArgumentType {
                 /* Validate the closing parens */
 
                 let Some(close_token) = tokens.pop_front() else {
-                    report_error(
+                    return Diagnostic::at(
+                        Severity::Error,
+                        Code::UnexpectedToken,
                         "Expected closing parenthesis after memory address!",
+                        None,
                         path,
                         lines,
-                        address_token.line_number,
-                        address_token.column_start,
-                        address_token.column_end,
+                        address_token.span(),
+                    )
+                    .with_fix(
+                        "Add the missing closing parenthesis `)`",
+                        path,
+                        lines,
+                        Span {
+                            column_start: address_token.column_end,
+                            ..address_token.span()
+                        },
+                        ")",
                     )
+                    .raise()
                 };
 
                 let TokenType::CloseParenthesis = close_token.token_type else {
-                    report_error(
+                    return report_error(
+                        Code::UnexpectedToken,
                         format!(
                             "Unexpected token `{}` after memory address! Expected closing parenthesis!",
                             close_token.value
@@ -463,7 +940,8 @@ impl Parsable for InstructionArgumentType {
                 if !tokens.is_empty() {
                     let illegal_token = tokens.pop_front().unwrap();
 
-                    report_error(
+                    return report_error(
+                        Code::UnexpectedToken,
                         format!(
                             "Unexpected token `{}` after indirect memory address!",
                             illegal_token.value
@@ -477,13 +955,24 @@ impl Parsable for InstructionArgumentType {
                     )
                 }
 
-                InstructionArgumentType::MemoryAddressIndirect(address)
+                argument
             }
             TokenType::Identifier(value) => {
+                // `table(%ecx)` adds an index register to the address of the label
+                if tokens.front().is_some_and(|token| token.token_type == TokenType::OpenParenthesis) {
+                    let open_token = tokens.pop_front().unwrap();
+
+                    return Ok(InstructionArgumentType::IndexedLabel(
+                        value.clone(),
+                        parse_index_register(path, lines, &open_token, tokens)?,
+                    ));
+                }
+
                 if !tokens.is_empty() {
                     let illegal_token = tokens.pop_front().unwrap();
 
-                    report_error(
+                    return report_error(
+                        Code::UnexpectedToken,
                         format!(
                             "Unexpected token `{}` after label identifier!",
                             illegal_token.value
@@ -502,7 +991,8 @@ impl Parsable for InstructionArgumentType {
             TokenType::OpenBracket => {
                 // Make sure that there is a label name after the bracket
                 let Some(identifier_token) = tokens.pop_front() else {
-                    report_error(
+                    return report_error(
+                        Code::UnexpectedToken,
                         "Expected label identifier after opening bracket `[`!",
                         path,
                         lines,
@@ -514,7 +1004,8 @@ impl Parsable for InstructionArgumentType {
 
                 let identifier_name = match &identifier_token.token_type {
                     TokenType::Identifier(value) => value,
-                    _ => report_error(
+                    _ => return report_error(
+                        Code::UnexpectedToken,
                         format!(
                             "Unexpected token `{}` after opening bracket! Expected label identifier!",
                             identifier_token.value
@@ -531,18 +1022,31 @@ impl Parsable for InstructionArgumentType {
                 /* Validate the closing brackets */
 
                 let Some(close_token) = tokens.pop_front() else {
-                    report_error(
+                    return Diagnostic::at(
+                        Severity::Error,
+                        Code::UnexpectedToken,
                         "Expected closing bracket after label identifier!",
+                        None,
                         path,
                         lines,
-                        identifier_token.line_number,
-                        identifier_token.column_start,
-                        identifier_token.column_end,
+                        identifier_token.span(),
+                    )
+                    .with_fix(
+                        "Add the missing closing bracket `]`",
+                        path,
+                        lines,
+                        Span {
+                            column_start: identifier_token.column_end,
+                            ..identifier_token.span()
+                        },
+                        "]",
                     )
+                    .raise()
                 };
 
                 let TokenType::CloseBracket = close_token.token_type else {
-                    report_error(
+                    return report_error(
+                        Code::UnexpectedToken,
                         format!(
                             "Unexpected token `{}` after label identifier! Expected closing bracket!",
                             close_token.value
@@ -560,7 +1064,8 @@ impl Parsable for InstructionArgumentType {
                 if !tokens.is_empty() {
                     let illegal_token = tokens.pop_front().unwrap();
 
-                    report_error(
+                    return report_error(
+                        Code::UnexpectedToken,
                         format!(
                             "Unexpected token `{}` after label dereference!",
                             illegal_token.value
@@ -580,7 +1085,8 @@ impl Parsable for InstructionArgumentType {
                 if !tokens.is_empty() {
                     let illegal_token = tokens.pop_front().unwrap();
 
-                    report_error(
+                    return report_error(
+                        Code::UnexpectedToken,
                         format!(
                             "Unexpected token `{}` after register name!",
                             illegal_token.value
@@ -595,24 +1101,13 @@ impl Parsable for InstructionArgumentType {
                 }
 
                 // Make sure the register name is valid
-                let Some(register) = Register::from_name(name) else {
-                    report_error(
-                        format!(
-                            "Register name `{name}` is invalid!"
-                        )
-                        .as_str(),
-                        path,
-                        lines,
-                        first_token.line_number,
-                        first_token.column_start,
-                        first_token.column_end,
-                    )
-                };
+                let register = parse_register_name(path, lines, name, first_token.span())?;
 
                 InstructionArgumentType::Register(register)
             }
             // TODO - Add more specific error messages for each token
-            _ => report_error(
+            _ => return report_error(
+                Code::UnexpectedToken,
                 format!("Unexpected token `{}` in argument list!", first_token.value).as_str(),
                 path,
                 lines,
@@ -620,37 +1115,203 @@ impl Parsable for InstructionArgumentType {
                 first_token.column_start,
                 first_token.column_end,
             ),
-        }
+        })
     }
 }
 
-type InstructionArguments = VecDeque<InstructionArgumentType>;
-
-impl Parsable for InstructionArguments {
-    fn parse(
-        path: &PathBuf,
-        lines: &Vec<String>,
-        argument_tokens: &mut VecDeque<Token>,
-    ) -> InstructionArguments {
-        let mut arguments = InstructionArguments::new();
+/**
+ * A memory address offset by the value of an index register, such as `$8000(%ecx)`
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexedAddress {
+    pub base: u16,
+    pub index: Register,
+}
 
-        let mut args = split_tokens_by_commas(path, lines, argument_tokens);
+/**
+ * The register with the name written after `%`, suggesting the closest register if there is none
+ */
+fn parse_register_name(path: &Path, lines: &[String], name: &str, span: Span) -> diagnostic::Result<Register> {
+    let Some(register) = Register::from_name(name) else {
+        let names = Register::all().iter().map(|register| register.name());
+
+        return report_span_error_with_help(
+            Code::InvalidRegister,
+            format!("Register name `{name}` is invalid!").as_str(),
+            suggest::did_you_mean(name, "%", names).as_deref(),
+            path,
+            lines,
+            span,
+        )
+    };
+
+    Ok(register)
+}
 
-        while !args.is_empty() {
-            let mut arg = args.pop_front().unwrap();
+/**
+ * Parse the `%reg)` that follows the base of an indexed address
+ */
+fn parse_index_register(path: &Path, lines: &[String], previous_token: &Token, tokens: &mut VecDeque<Token>) -> diagnostic::Result<Register> {
+    let Some(register_token) = tokens.pop_front() else {
+        return report_span_error(
+            Code::UnexpectedToken,
+            format!("Expected an index register after `{}`! (e.g. `$8000(%ecx)`)", previous_token.value).as_str(),
+            path,
+            lines,
+            previous_token.span(),
+        )
+    };
+
+    let TokenType::Register(name) = &register_token.token_type else {
+        return report_span_error(
+            Code::UnexpectedToken,
+            format!("Unexpected token `{}`, expected an index register!", register_token.value).as_str(),
+            path,
+            lines,
+            register_token.span(),
+        )
+    };
+
+    let register = parse_register_name(path, lines, name, register_token.span())?;
+
+    if !register.can_address() {
+        return report_span_error(
+            Code::InvalidRegister,
+            format!("Register `%{}` cannot be used as an index register!", register.name()).as_str(),
+            path,
+            lines,
+            register_token.span(),
+        )
+    }
 
-            arguments.push_back(InstructionArgumentType::parse(path, lines, &mut arg))
-        }
+    match tokens.pop_front() {
+        Some(Token { token_type: TokenType::CloseParenthesis, .. }) => {}
+        Some(token) => return report_span_error(
+            Code::UnexpectedToken,
+            format!("Unexpected token `{}` after index register! Expected closing parenthesis!", token.value).as_str(),
+            path,
+            lines,
+            token.span(),
+        ),
+        None => return Diagnostic::at(
+            Severity::Error,
+            Code::UnexpectedToken,
+            "Expected closing parenthesis after index register!",
+            None,
+            path,
+            lines,
+            register_token.span(),
+        )
+        .with_fix(
+            "Add the missing closing parenthesis `)`",
+            path,
+            lines,
+            Span {
+                column_start: register_token.column_end,
+                ..register_token.span()
+            },
+            ")",
+        )
+        .raise(),
+    }
 
-        arguments
+    // There should not be any more tokens after an indexed address
+    if let Some(illegal_token) = tokens.pop_front() {
+        return report_span_error(
+            Code::UnexpectedToken,
+            format!("Unexpected token `{}` after indexed address!", illegal_token.value).as_str(),
+            path,
+            lines,
+            illegal_token.span(),
+        )
     }
+
+    Ok(register)
 }
 
+type InstructionArguments = VecDeque<InstructionArgumentType>;
+
+/**
+ * An operand whose value is only known once every label has an address, such as `#lo(message)`
+ */
 #[derive(Debug)]
-pub enum Register {
-    /* 8-bit */
-    AX,
-    BX,
+pub struct DeferredOperand {
+    // Position of the operand in encoding order
+    pub index: usize,
+    pub value: Expression,
+}
+
+/**
+ * Parse the arguments of an instruction.
+ *
+ * Immediates that are expressions rather than a single number are parsed as `#0` and returned
+ * separately, so they can be filled in once labels are placed. Label operands and the labels of
+ * indexed addresses are returned the same way, as the address of the label.
+ */
+fn parse_instruction_arguments(
+    path: &Path,
+    lines: &[String],
+    argument_tokens: &mut VecDeque<Token>,
+) -> diagnostic::Result<(InstructionArguments, Vec<DeferredOperand>, Vec<Span>)> {
+    let mut arguments = InstructionArguments::new();
+    let mut deferred = Vec::new();
+    let mut spans = Vec::new();
+
+    let mut args = split_tokens_by_commas(path, lines, argument_tokens)?;
+
+    while !args.is_empty() {
+        let mut arg = args.pop_front().unwrap();
+
+        spans.push(arg.front().unwrap().span().to(&arg.back().unwrap().span()));
+
+        let is_deferred = arg.front().unwrap().token_type == TokenType::Immediate
+            && is_expression_value(&arg.range(1..).cloned().collect());
+
+        if is_deferred {
+            arg.pop_front();
+
+            deferred.push(DeferredOperand {
+                index: arguments.len(),
+                value: Expression::parse(path, lines, &mut arg)?,
+            });
+
+            arguments.push_back(InstructionArgumentType::Immediate(0));
+            continue;
+        }
+
+        let span = arg.front().unwrap().span();
+        let argument = InstructionArgumentType::parse(path, lines, &mut arg)?;
+
+        if let InstructionArgumentType::LabelAddress(name) = &argument {
+            deferred.push(DeferredOperand {
+                index: arguments.len(),
+                value: Expression::Identifier(name.clone(), span),
+            });
+        }
+
+        // The base of an indexed label is filled in the same way
+        if let InstructionArgumentType::IndexedLabel(name, index) = argument {
+            deferred.push(DeferredOperand {
+                index: arguments.len(),
+                value: Expression::Identifier(name, span),
+            });
+
+            arguments.push_back(InstructionArgumentType::Indexed(IndexedAddress { base: 0, index }));
+            continue;
+        }
+
+        arguments.push_back(argument)
+    }
+
+    Ok((arguments, deferred, spans))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Register {
+    /* 8-bit */
+    AX,
+    BX,
     CX,
     DX,
     EX,
@@ -660,10 +1321,14 @@ pub enum Register {
     ECX,
     EDX,
     EEX,
+    /* Special */
+    SP,
+    PC,
+    FLAGS,
 }
 
 impl Register {
-    fn from_name(name: &String) -> Option<Register> {
+    pub fn from_name(name: &str) -> Option<Register> {
         let reg = match name.to_lowercase().as_str() {
             "ax" => Register::AX,
             "bx" => Register::BX,
@@ -675,29 +1340,131 @@ impl Register {
             "ecx" => Register::ECX,
             "edx" => Register::EDX,
             "eex" => Register::EEX,
+            "sp" => Register::SP,
+            "pc" => Register::PC,
+            "flags" => Register::FLAGS,
             _ => return None,
         };
 
         Some(reg)
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Register::AX => "ax",
+            Register::BX => "bx",
+            Register::CX => "cx",
+            Register::DX => "dx",
+            Register::EX => "ex",
+            Register::EAX => "eax",
+            Register::EBX => "ebx",
+            Register::ECX => "ecx",
+            Register::EDX => "edx",
+            Register::EEX => "eex",
+            Register::SP => "sp",
+            Register::PC => "pc",
+            Register::FLAGS => "flags",
+        }
+    }
 }
 
-impl Parsable for TextSection {
-    fn parse(path: &PathBuf, lines: &Vec<String>, tokens: &mut VecDeque<Token>) -> TextSection {
+impl TextSection {
+    pub(crate) fn parse(
+        path: &Path,
+        lines: &[String],
+        tokens: &mut VecDeque<Token>,
+        diagnostics: &mut Diagnostics,
+    ) -> diagnostic::Result<TextSection> {
         let mut text = TextSection { labels: Vec::new() };
 
+        // Address set by a .org for the next label
+        let mut origin = None;
+
         // Loop through every label in the section
         while !tokens.is_empty() {
             let first_token = tokens.pop_front().unwrap();
 
-            // Check for end of section or illegal directives
-            if let TokenType::Directive(name) = &first_token.token_type {
-                if name == "data" || name == "text" {
-                    tokens.push_front(first_token);
-                    return text;
-                } else {
-                    report_error(
-                        format!("Illegal directive token `.{}`", name).as_str(),
+            // Check for end of section
+            if matches!(&first_token.token_type, TokenType::Directive(name) if is_section_directive(name)) {
+                tokens.push_front(first_token);
+                break;
+            }
+
+            // After an error, carry on from the next label so the rest of the section is still checked
+            let parsed = (|| -> diagnostic::Result<()> {
+                // Check for illegal directives
+                if let TokenType::Directive(name) = &first_token.token_type {
+                    if name == "org" {
+                        if origin.is_some() {
+                            return report_span_error(
+                                Code::DuplicateDirective,
+                                "Only one .org is allowed before a label!",
+                                path,
+                                lines,
+                                first_token.span(),
+                            );
+                        }
+
+                        let address = parse_origin(path, lines, &first_token, tokens)?;
+
+                        // Anything but a label after the .org is the rest of the label before it
+                        if !tokens.front().is_none_or(is_label_boundary) {
+                            let label = continued_label(path, lines, &mut text.labels, &address)?;
+                            let contents = parse_subroutine_lines(path, lines, read_tokens_to_label_or_eos(tokens), diagnostics)?;
+
+                            label.continuations.push(Continuation {
+                                start: label.contents.len(),
+                                origin: address,
+                            });
+                            label.contents.extend(contents);
+
+                            return Ok(());
+                        }
+
+                        origin = Some(address);
+                        return Ok(());
+                    } else {
+                        return report_span_error_with_help(
+                            Code::UnknownDirective,
+                            format!("Illegal directive token `.{}`", name).as_str(),
+                            suggest::did_you_mean(name, ".", DIRECTIVES.iter().copied()).as_deref(),
+                            path,
+                            lines,
+                            first_token.span(),
+                        )
+                    }
+                };
+
+                // Start parsing this section as a label
+                let TokenType::Label(label_name) = &first_token.token_type else {
+                    return report_error(
+                        Code::UnexpectedToken,
+                        format!("Unexpected token `{}` in text section.", first_token.value).as_str(),
+                        path,
+                        lines,
+                        first_token.line_number,
+                        first_token.column_start,
+                        first_token.column_end,
+                    )
+                };
+
+                let mut subroutine_label = SubroutineLabel {
+                    name: label_name.clone(),
+                    span: first_token.span(),
+                    attributes: parse_label_attributes(path, lines, &first_token, tokens)?,
+                    origin: origin.take(),
+                    contents: Vec::new(),
+                    continuations: Vec::new(),
+                };
+
+                // Read all the tokens in this label
+                let subroutine_tokens = read_tokens_to_label_or_eos(tokens);
+
+                // Subroutine labels need to have instructions in them, unless they share the address of the label after them
+                if subroutine_tokens.is_empty() && !starts_with_label(tokens) {
+                    return report_error(
+                        Code::EmptyLabel,
+                        format!("Label `{}` cannot be empty!", subroutine_label.name).as_str(),
                         path,
                         lines,
                         first_token.line_number,
@@ -705,83 +1472,214 @@ impl Parsable for TextSection {
                         first_token.column_end,
                     )
                 }
-            };
 
-            // Start parsing this section as a label
-            let TokenType::Label(label_name) = first_token.token_type else {
-                report_error(
-                    format!("Unexpected token `{}` in text section.", first_token.value).as_str(),
-                    path,
-                    lines,
-                    first_token.line_number,
-                    first_token.column_start,
-                    first_token.column_end,
-                )
-            };
+                subroutine_label.contents = parse_subroutine_lines(path, lines, subroutine_tokens, diagnostics)?;
 
-            let mut subroutine_label = SubroutineLabel {
-                name: label_name,
-                instructions: Vec::new(),
-            };
+                text.labels.push(subroutine_label);
 
-            // Read all the tokens in this label
-            let mut subroutine_tokens = read_tokens_to_label_or_eos(tokens);
+                Ok(())
+            })();
 
-            // Subroutine labels need to have instructions in them
-            if subroutine_tokens.len() == 0 {
-                report_error(
-                    format!("Label `{}` cannot be empty!", subroutine_label.name).as_str(),
-                    path,
-                    lines,
-                    first_token.line_number,
-                    first_token.column_start,
-                    first_token.column_end,
-                )
+            if diagnostics.recover(parsed)?.is_none() {
+                skip_label(tokens);
             }
+        }
 
-            // Read tokens one line at a time until we reach the end of the subroutine
-            while !subroutine_tokens.is_empty() {
-                let mut line = read_tokens_to_eol(&mut subroutine_tokens);
+        expect_label_after_origin(path, lines, &origin)?;
 
-                // Grab the line details for error reporting later
-                let line_number = line.front().unwrap().line_number;
-                let col_start = line.front().unwrap().column_start;
-                let col_end = line.back().unwrap().column_end;
+        Ok(text)
+    }
+}
 
-                let first_line_token = line.pop_front().unwrap();
+/**
+ * Parse the tokens of a subroutine one line at a time. A line with an error is skipped, so the lines after it are
+ * still checked.
+ */
+fn parse_subroutine_lines(
+    path: &Path,
+    lines: &[String],
+    mut tokens: VecDeque<Token>,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<Vec<Spanned<SubroutineItem>>> {
+    let mut contents = Vec::new();
 
-                // Make sure first token is an instruction
-                let TokenType::Instruction(instruction_mnemonic) = &first_line_token.token_type else {
-                    report_error(
-                       "Lines inside a subroutine must start with an instruction",
-                        path,
-                        lines,
-                        first_line_token.line_number,
-                        first_line_token.column_start,
-                        first_line_token.column_end,
-                    )
-                };
+    while !tokens.is_empty() {
+        let mut line = read_tokens_to_eol(&mut tokens);
+
+        let parsed = (|| -> diagnostic::Result<()> {
+            // `times <count>` assembles the rest of the line that many times
+            let repetitions = parse_times_prefix(path, lines, &mut line)?;
+
+            for _ in 0..repetitions {
+                contents.extend(parse_subroutine_line(path, lines, line.clone())?);
+            }
+
+            Ok(())
+        })();
+
+        diagnostics.recover(parsed)?;
+    }
+
+    Ok(contents)
+}
+
+/**
+ * Parse a line of a subroutine, which is either an instruction or a data directive
+ */
+fn parse_subroutine_line(path: &Path, lines: &[String], mut line: VecDeque<Token>) -> diagnostic::Result<Vec<Spanned<SubroutineItem>>> {
+    // Grab the line details for error reporting later
+    let line_number = line.front().unwrap().line_number;
+    let col_start = line.front().unwrap().column_start;
+    let col_end = line.back().unwrap().column_end;
+
+    // Data can be placed between instructions, it is assembled in place
+    if let TokenType::Directive(directive) = &line.front().unwrap().token_type {
+        if directive == "checksum" {
+            return report_span_error(
+                Code::MisplacedDirective,
+                "The .checksum directive can only be used in the .data section!",
+                path,
+                lines,
+                line.front().unwrap().span(),
+            )
+        }
+
+        return Ok(parse_constant(path, lines, line)?
+            .into_iter()
+            .map(|constant| Spanned {
+                span: constant.span,
+                node: SubroutineItem::Constant(constant.node),
+            })
+            .collect());
+    }
+
+    let first_line_token = line.pop_front().unwrap();
+
+    // Make sure first token is an instruction
+    let TokenType::Instruction(instruction_mnemonic) = &first_line_token.token_type else {
+        return report_error(
+        Code::MisplacedDirective,
+        "Lines inside a subroutine must start with an instruction or a data directive",
+            path,
+            lines,
+            first_line_token.line_number,
+            first_line_token.column_start,
+            first_line_token.column_end,
+        )
+    };
+
+    let (mut instruction_arguments, deferred, argument_spans) = parse_instruction_arguments(path, lines, &mut line)?;
+
+    // Unknown instructions are reported when the instruction is parsed
+    if let Some(mnemonic) = Mnemonic::from_name(&split_size_suffix(instruction_mnemonic).0) {
+        let span = Span {
+            line_number,
+            column_start: col_start,
+            column_end: col_end,
+        };
+
+        label_operands_as_addresses(mnemonic, &mut instruction_arguments);
+        check_overloads(mnemonic, &instruction_arguments, &argument_spans, path, lines, span)?;
+    }
+
+    let instruction = Instruction::parse(
+        instruction_mnemonic,
+        &mut instruction_arguments,
+        &argument_spans,
+        path,
+        lines,
+        line_number,
+        col_start,
+        col_end,
+    )?;
+
+    // Values computed from labels are checked against the size once they are known
+    if instruction.immediate_size() == 1 {
+        for (index, operand) in instruction.operands().iter().enumerate() {
+            let Operand::Immediate(value) = operand else {
+                continue;
+            };
 
-                let mut instruction_arguments = InstructionArguments::parse(path, lines, &mut line);
+            if *value > 0xFF && !deferred.iter().any(|operand| operand.index == index) {
+                let target = match instruction.operands().first() {
+                    Some(Operand::Register(register)) => format!("the 8-bit register `%{}`", register.name()),
+                    _ => "a byte".to_owned(),
+                };
 
-                let instruction = Instruction::parse(
-                    instruction_mnemonic,
-                    &mut instruction_arguments,
+                return report_error(
+                    Code::ValueOutOfRange,
+                    format!("Immediate value {value} does not fit in {target}, expected 0 to 255!").as_str(),
                     path,
                     lines,
                     line_number,
                     col_start,
                     col_end,
-                );
-
-                subroutine_label.instructions.push(instruction);
+                )
             }
-
-            text.labels.push(subroutine_label);
         }
+    }
+
+    Ok(vec![Spanned {
+        span: Span {
+            line_number,
+            column_start: col_start,
+            column_end: col_end,
+        },
+        node: SubroutineItem::Instruction { instruction, deferred },
+    }])
+}
+
+/**
+ * Number of times a line should be assembled, removing its `times <count>` prefix if it has one
+ */
+fn parse_times_prefix(path: &Path, lines: &[String], line: &mut VecDeque<Token>) -> diagnostic::Result<u16> {
+    if !matches!(&line.front().unwrap().token_type, TokenType::Instruction(name) if name == "times") {
+        return Ok(1);
+    }
+
+    let times_token = line.pop_front().unwrap();
 
-        text
+    let count = match line.pop_front() {
+        Some(count_token @ Token { token_type: TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_), .. }) => {
+            count_token.parse_u16(path, lines)?
+        }
+        _ => return report_span_error(
+            Code::InvalidDirectiveArgument,
+            "Expected a repeat count after `times`! (e.g. `times 16 nop`)",
+            path,
+            lines,
+            times_token.span(),
+        ),
+    };
+
+    let Some(first_token) = line.front_mut() else {
+        return report_span_error(
+            Code::InvalidDirectiveArgument,
+            format!("Expected an instruction or data directive after `times {count}`!").as_str(),
+            path,
+            lines,
+            times_token.span(),
+        )
+    };
+
+    // The instruction is not the first word of the line, so it was read as an identifier
+    if let TokenType::Identifier(name) = &first_token.token_type {
+        first_token.token_type = TokenType::Instruction(name.clone());
     }
+
+    Ok(count)
+}
+
+/**
+ * Target of a conditional branch.
+ *
+ * A short branch is encoded as an 8-bit displacement from the end of the branch, a long branch as the
+ * 16-bit address of the label like `jmp`.
+ */
+#[derive(Debug)]
+pub struct Branch {
+    pub label: String,
+    pub long: bool,
 }
 
 #[rustfmt::skip]
@@ -797,12 +1695,60 @@ pub enum Instruction {
     mov_RegisterToRegister(Register, Register),     // mov %eax, %ebx       ; Copy value in %ebx to %eax
     mov_ImmediateToMemory8(u16, u8),                // mov $F354, #69       ; Copy 8 bit immediate #69 to mem address $F354
     mov_ImmediateToMemory16(u16, u16),              // mov $F354, #420      ; Copy 16 bit immediate #420 to mem addresses $F354-F355
+    mov_RegisterToRegisterIndirect(Register, Register), // mov (%ebx), %eax ; Copy value in %eax to the mem address held in %ebx
+    mov_RegisterIndirectToRegister(Register, Register), // mov %eax, (%ebx) ; Copy value at the mem address held in %ebx to %eax
+    mov_ImmediateToRegisterIndirect8(Register, u8),     // mov.b (%ebx), #69  ; Copy 8 bit immediate #69 to the mem address held in %ebx
+    mov_ImmediateToRegisterIndirect16(Register, u16),   // mov (%ebx), #420   ; Copy 16 bit immediate #420 to the mem address held in %ebx
+    mov_RegisterToIndexed(IndexedAddress, Register),    // mov $8000(%ecx), %eax ; Copy value in %eax to mem address $8000 plus %ecx
+    mov_IndexedToRegister(Register, IndexedAddress),    // mov %eax, $8000(%ecx) ; Copy value at mem address $8000 plus %ecx to %eax
     /* add - accumulator */
     add_RegisterToAccumulator(Register),            // add %ebx             ; Add the value of %ebx to the accumulator register
     add_ImmediateToAccumulator(u16),                // add #2               ; Add 2 to the accumulator register
     /* add - to register */
     add_RegisterToRegister(Register, Register),     // add %ebx, %ecx       ; Add the value of %ecx to the value in %ebx
     add_ImmediateToRegister(Register, u16),         // add %ebx, #2         ; Add 2 to the value in %ebx
+    /* sub - accumulator */
+    sub_RegisterFromAccumulator(Register),          // sub %ebx             ; Subtract the value of %ebx from the accumulator register
+    sub_ImmediateFromAccumulator(u16),              // sub #2               ; Subtract 2 from the accumulator register
+    /* sub - from register */
+    sub_RegisterFromRegister(Register, Register),   // sub %ebx, %ecx       ; Subtract the value of %ecx from the value in %ebx
+    sub_ImmediateFromRegister(Register, u16),       // sub %ebx, #2         ; Subtract 2 from the value in %ebx
+    /* mul/div - accumulator */
+    mul_Accumulator(Register),                      // mul %ebx             ; Multiply the accumulator by the value of %ebx
+    mul_AccumulatorImmediate(u16),                  // mul #3               ; Multiply the accumulator by 3
+    div_Accumulator(Register),                      // div %ebx             ; Divide the accumulator by the value of %ebx
+    div_AccumulatorImmediate(u16),                  // div #3               ; Divide the accumulator by 3
+    /* mul/div - register */
+    mul_Register(Register, Register),               // mul %ebx, %ecx       ; Multiply the value in %ebx by the value of %ecx
+    mul_RegisterImmediate(Register, u16),           // mul %ebx, #3         ; Multiply the value in %ebx by 3
+    div_Register(Register, Register),               // div %ebx, %ecx       ; Divide the value in %ebx by the value of %ecx
+    div_RegisterImmediate(Register, u16),           // div %ebx, #3         ; Divide the value in %ebx by 3
+    /* bitwise - accumulator */
+    and_Accumulator(Register),                      // and %ebx             ; AND the accumulator with the value of %ebx
+    and_AccumulatorImmediate(u16),                  // and #$0F             ; AND the accumulator with $0F
+    or_Accumulator(Register),                       // or %ebx              ; OR the accumulator with the value of %ebx
+    or_AccumulatorImmediate(u16),                   // or #$80              ; OR the accumulator with $80
+    xor_Accumulator(Register),                      // xor %ebx             ; XOR the accumulator with the value of %ebx
+    xor_AccumulatorImmediate(u16),                  // xor #$FF             ; XOR the accumulator with $FF
+    not_Accumulator,                                // not                  ; Invert every bit of the accumulator
+    /* bitwise - register */
+    and_Register(Register, Register),               // and %ebx, %ecx       ; AND the value in %ebx with the value of %ecx
+    and_RegisterImmediate(Register, u16),           // and %ebx, #$0F       ; AND the value in %ebx with $0F
+    or_Register(Register, Register),                // or %ebx, %ecx        ; OR the value in %ebx with the value of %ecx
+    or_RegisterImmediate(Register, u16),            // or %ebx, #$80        ; OR the value in %ebx with $80
+    xor_Register(Register, Register),               // xor %ebx, %ecx       ; XOR the value in %ebx with the value of %ecx
+    xor_RegisterImmediate(Register, u16),           // xor %ebx, #$FF       ; XOR the value in %ebx with $FF
+    not_Register(Register),                         // not %ebx             ; Invert every bit of the value in %ebx
+    /* shifts - by immediate */
+    shl_Immediate(Register, u16),                   // shl %ebx, #4         ; Shift the value in %ebx left by 4 bits
+    shr_Immediate(Register, u16),                   // shr %ebx, #4         ; Shift the value in %ebx right by 4 bits
+    rol_Immediate(Register, u16),                   // rol %ebx, #4         ; Rotate the value in %ebx left by 4 bits
+    ror_Immediate(Register, u16),                   // ror %ebx, #4         ; Rotate the value in %ebx right by 4 bits
+    /* shifts - by register */
+    shl_Register(Register, Register),               // shl %ebx, %ecx       ; Shift the value in %ebx left by the value of %ecx
+    shr_Register(Register, Register),               // shr %ebx, %ecx       ; Shift the value in %ebx right by the value of %ecx
+    rol_Register(Register, Register),               // rol %ebx, %ecx       ; Rotate the value in %ebx left by the value of %ecx
+    ror_Register(Register, Register),               // ror %ebx, %ecx       ; Rotate the value in %ebx right by the value of %ecx
     /* inc/dec - accumulator */
     inc_Accumulator,                                // inc                  ; Increment the accumulator
     dec_Accumulator,                                // dec                  ; Decrement the accumulator
@@ -813,9 +1759,25 @@ pub enum Instruction {
     jmp_Immediate(u16),                             // jmp #$F354           ; Jump to memory address #$F354
     jmp_Register(Register),                         // jmp %ebx             ; Jump to memory address stored in %ebx
     jmp_Memory(u16),                                // jmp $F354            ; Jump to memory address stored in address $F354
-    jmp_Label(SubroutineLabel),                     // jmp boot_loader      ; Jump to subroutine boot_loader but don't push pc onto the stack
-    jsr(SubroutineLabel),                           // jsr boot_loader      ; Push current pc onto stack and jump to subroutine boot_loader
+    jmp_Label(String),                              // jmp boot_loader      ; Jump to subroutine boot_loader but don't push pc onto the stack
+    jsr_Immediate(u16),                             // jsr #$F354           ; Push current pc onto stack and jump to memory address #$F354
+    jsr_Register(Register),                         // jsr %ebx             ; Push current pc onto stack and jump to memory address stored in %ebx
+    jsr_Memory(u16),                                // jsr $F354            ; Push current pc onto stack and jump to memory address stored in address $F354
+    jsr_MemoryIndirect(u16),                        // jsr ($FFFE)          ; Push current pc onto stack and jump to the 16-bit vector stored at $FFFE
+    jsr_Label(String),                              // jsr boot_loader      ; Push current pc onto stack and jump to subroutine boot_loader
     ret,                                            // ret                  ; Pop return address off stack and jump back
+    /* cmp */
+    cmp_Accumulator(Register),                      // cmp %ebx             ; Compare the accumulator with the value of %ebx
+    cmp_AccumulatorImmediate(u16),                  // cmp #2               ; Compare the accumulator with 2
+    cmp_Register(Register, Register),               // cmp %ebx, %ecx       ; Compare the value in %ebx with the value of %ecx
+    cmp_RegisterImmediate(Register, u16),           // cmp %ebx, #2         ; Compare the value in %ebx with 2
+    /* conditional branches */
+    beq(Branch),                                    // beq boot_loader      ; Branch to boot_loader if the last comparison was equal
+    bne(Branch),                                    // bne boot_loader      ; Branch to boot_loader if the last comparison was not equal
+    blt(Branch),                                    // blt boot_loader      ; Branch to boot_loader if the first value was less (signed)
+    bge(Branch),                                    // bge boot_loader      ; Branch to boot_loader if the first value was greater or equal (signed)
+    bcs(Branch),                                    // bcs boot_loader      ; Branch to boot_loader if the carry flag is set (unsigned less)
+    bcc(Branch),                                    // bcc boot_loader      ; Branch to boot_loader if the carry flag is clear (unsigned greater or equal)
     /* syscalls */
     syscall,                                        // syscall              ; Jump to the syscall handler
     ssc(u16),                                       // ssc #$00FF           ; Sets the syscall handler register to the value #$00FF
@@ -825,24 +1787,106 @@ pub enum Instruction {
     push_Register(Register),                        // push %ebx            ; Pushes the value in %ebx onto the stack
     pop_Memory(u16),                                // pop $420             ; Pops the top value on the stack into mem address $420
     pop_Register(Register),                         // pop %ebx             ; Pops the top value on the stack into %ebx
+    pusha,                                          // pusha                ; Pushes %eax, %ebx, %ecx, %edx and %eex onto the stack
+    popa,                                           // popa                 ; Pops %eex, %edx, %ecx, %ebx and %eax off the stack
+    /* exchange */
+    xchg_Registers(Register, Register),             // xchg %ebx, %ecx      ; Swaps the values in %ebx and %ecx
+    xchg_RegisterMemory(Register, u16),             // xchg %ebx, $420      ; Swaps the value in %ebx with the value at mem address $420
+    xchg_MemoryRegister(u16, Register),             // xchg $420, %ebx      ; Swaps the value at mem address $420 with the value in %ebx
+    /* control */
+    hlt,                                            // hlt                  ; Stops the CPU
+    brk,                                            // brk                  ; Stops at a breakpoint so a debugger can take over
+    /* port I/O */
+    in_Immediate(Register, u16),                    // in %ax, #2           ; Reads a byte from I/O port 2 into %ax
+    in_Register(Register, Register),                // in %ax, %ebx         ; Reads a byte from the I/O port numbered by %ebx into %ax
+    out_Immediate(u16, Register),                   // out #2, %ax          ; Writes the byte in %ax to I/O port 2
+    out_Register(Register, Register),               // out %ebx, %ax        ; Writes the byte in %ax to the I/O port numbered by %ebx
+    /* interrupts */
+    cli,                                            // cli                  ; Disables interrupts
+    sei,                                            // sei                  ; Enables interrupts
+    rti,                                            // rti                  ; Pops the flags and return address pushed by an interrupt and jumps back
 }
 
 impl Instruction {
+    #[allow(clippy::too_many_arguments)]
     fn parse(
-        instruction_mnemonic: &String,
+        instruction_mnemonic: &str,
         instruction_arguments: &mut InstructionArguments,
-        path: &PathBuf,
-        lines: &Vec<String>,
+        argument_spans: &[Span],
+        path: &Path,
+        lines: &[String],
         line_number: u32,
         col_start: u32,
         col_end: u32,
-    ) -> Instruction {
+    ) -> diagnostic::Result<Instruction> {
         let num_args = instruction_arguments.len();
 
-        match instruction_mnemonic.as_str() {
+        let (name, size) = split_size_suffix(instruction_mnemonic);
+        let instruction_mnemonic = &name;
+
+        if Mnemonic::from_name(instruction_mnemonic).is_none() {
+            let names = Mnemonic::all().iter().map(|mnemonic| mnemonic.name);
+
+            return report_span_error_with_help(
+                Code::UnknownInstruction,
+                format!("Unknown instruction `{instruction_mnemonic}`!").as_str(),
+                suggest::did_you_mean(instruction_mnemonic, "", names).as_deref(),
+                path,
+                lines,
+                Span {
+                    line_number,
+                    column_start: col_start,
+                    column_end: col_end,
+                },
+            )
+        }
+
+        if let Some(size) = size {
+            if instruction_mnemonic != "mov" {
+                return report_error(
+                    Code::InvalidOperand,
+                    format!("The `.{}` size suffix can only be used on `mov`!", size.suffix()).as_str(),
+                    path,
+                    lines,
+                    line_number,
+                    col_start,
+                    col_end,
+                )
+            }
+
+            // Register operands carry their own width, so a size suffix has to agree with the registers being moved
+            for argument in instruction_arguments.iter() {
+                if let InstructionArgumentType::Register(register) = argument {
+                    if register.width() != size.bits() {
+                        return report_error(
+                            Code::OperandSizeMismatch,
+                            format!(
+                                "`{instruction_mnemonic}.{}` moves {} bits, but `%{}` holds {} bits!",
+                                size.suffix(),
+                                size.bits(),
+                                register.name(),
+                                register.width()
+                            )
+                            .as_str(),
+                            path,
+                            lines,
+                            line_number,
+                            col_start,
+                            col_end,
+                        )
+                    }
+                }
+            }
+        }
+
+        check_special_registers(instruction_mnemonic, instruction_arguments, path, lines, line_number, col_start, col_end)?;
+        check_register_widths(instruction_mnemonic, instruction_arguments, argument_spans, path, lines)?;
+
+        Ok(match instruction_mnemonic.as_str() {
             "nop" => {
                 if num_args != 0 {
-                    report_error(
+                    return report_error(
+                        Code::WrongArgumentCount,
                         format!("`{instruction_mnemonic}` instruction expects 0 arguments, but got {num_args}").as_str(),
                         path,
                         lines,
@@ -856,7 +1900,8 @@ impl Instruction {
             }
             "mov" => {
                 if num_args != 2 {
-                    report_error(
+                    return report_error(
+                        Code::WrongArgumentCount,
                         format!("`{instruction_mnemonic}` instruction expects 2 arguments, but got {num_args}").as_str(),
                         path,
                         lines,
@@ -884,18 +1929,121 @@ impl Instruction {
                         InstructionArgumentType::Register(register),
                         InstructionArgumentType::Immediate(immediate), 
                     ) => Instruction::mov_ImmediateToRegister(register, immediate),
-                    // TODO - Emit warning if registers are the same
                     (
                         InstructionArgumentType::Register(dest_register),
                         InstructionArgumentType::Register(src_register), 
                     ) => Instruction::mov_RegisterToRegister(dest_register, src_register),
-                    // TODO - Implement 8 bit immediate parsing
+                    // Stores are 16-bit unless `mov.b` asks for a single byte
+                    (
+                        InstructionArgumentType::MemoryAddress(address),
+                        InstructionArgumentType::Immediate(immediate),
+                    ) if size == Some(OperandSize::Byte) => {
+                        if immediate > 0xFF {
+                            return report_error(
+                                Code::ValueOutOfRange,
+                                format!("Immediate value {immediate} does not fit in the byte stored by `mov.b`, expected 0 to 255!").as_str(),
+                                path,
+                                lines,
+                                line_number,
+                                col_start,
+                                col_end,
+                            )
+                        }
+
+                        Instruction::mov_ImmediateToMemory8(address, immediate as u8)
+                    }
                     (
                         InstructionArgumentType::MemoryAddress(address),
                         InstructionArgumentType::Immediate(immediate_16), 
                     ) => Instruction::mov_ImmediateToMemory16(address, immediate_16),
-                    _ => report_error(
-                        format!("Could not find valid overload of `{instruction_mnemonic}` instruction for supplied argument types").as_str(),
+                    (
+                        InstructionArgumentType::RegisterIndirect(pointer),
+                        InstructionArgumentType::Register(register),
+                    ) => Instruction::mov_RegisterToRegisterIndirect(pointer, register),
+                    (
+                        InstructionArgumentType::Register(register),
+                        InstructionArgumentType::RegisterIndirect(pointer),
+                    ) => Instruction::mov_RegisterIndirectToRegister(register, pointer),
+                    (
+                        InstructionArgumentType::RegisterIndirect(pointer),
+                        InstructionArgumentType::Immediate(immediate),
+                    ) if size == Some(OperandSize::Byte) => {
+                        if immediate > 0xFF {
+                            return report_error(
+                                Code::ValueOutOfRange,
+                                format!("Immediate value {immediate} does not fit in the byte stored by `mov.b`, expected 0 to 255!").as_str(),
+                                path,
+                                lines,
+                                line_number,
+                                col_start,
+                                col_end,
+                            )
+                        }
+
+                        Instruction::mov_ImmediateToRegisterIndirect8(pointer, immediate as u8)
+                    }
+                    (
+                        InstructionArgumentType::RegisterIndirect(pointer),
+                        InstructionArgumentType::Immediate(immediate),
+                    ) => Instruction::mov_ImmediateToRegisterIndirect16(pointer, immediate),
+                    (
+                        InstructionArgumentType::Indexed(address),
+                        InstructionArgumentType::Register(register),
+                    ) => Instruction::mov_RegisterToIndexed(address, register),
+                    (
+                        InstructionArgumentType::Register(register),
+                        InstructionArgumentType::Indexed(address),
+                    ) => Instruction::mov_IndexedToRegister(register, address),
+                    _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
+                }
+            }
+            "add" => match parse_arithmetic_operands(instruction_mnemonic, instruction_arguments, path, lines, line_number, col_start, col_end)? {
+                ArithmeticOperands::Register(register) => Instruction::add_RegisterToAccumulator(register),
+                ArithmeticOperands::Immediate(immediate) => Instruction::add_ImmediateToAccumulator(immediate),
+                ArithmeticOperands::RegisterRegister(dest_register, src_register) => Instruction::add_RegisterToRegister(dest_register, src_register),
+                ArithmeticOperands::RegisterImmediate(register, immediate) => Instruction::add_ImmediateToRegister(register, immediate),
+            },
+            "sub" => match parse_arithmetic_operands(instruction_mnemonic, instruction_arguments, path, lines, line_number, col_start, col_end)? {
+                ArithmeticOperands::Register(register) => Instruction::sub_RegisterFromAccumulator(register),
+                ArithmeticOperands::Immediate(immediate) => Instruction::sub_ImmediateFromAccumulator(immediate),
+                ArithmeticOperands::RegisterRegister(dest_register, src_register) => Instruction::sub_RegisterFromRegister(dest_register, src_register),
+                ArithmeticOperands::RegisterImmediate(register, immediate) => Instruction::sub_ImmediateFromRegister(register, immediate),
+            },
+            "mul" => match parse_arithmetic_operands(instruction_mnemonic, instruction_arguments, path, lines, line_number, col_start, col_end)? {
+                ArithmeticOperands::Register(register) => Instruction::mul_Accumulator(register),
+                ArithmeticOperands::Immediate(immediate) => Instruction::mul_AccumulatorImmediate(immediate),
+                ArithmeticOperands::RegisterRegister(dest_register, src_register) => Instruction::mul_Register(dest_register, src_register),
+                ArithmeticOperands::RegisterImmediate(register, immediate) => Instruction::mul_RegisterImmediate(register, immediate),
+            },
+            "div" => match parse_arithmetic_operands(instruction_mnemonic, instruction_arguments, path, lines, line_number, col_start, col_end)? {
+                ArithmeticOperands::Register(register) => Instruction::div_Accumulator(register),
+                ArithmeticOperands::Immediate(immediate) => Instruction::div_AccumulatorImmediate(immediate),
+                ArithmeticOperands::RegisterRegister(dest_register, src_register) => Instruction::div_Register(dest_register, src_register),
+                ArithmeticOperands::RegisterImmediate(register, immediate) => Instruction::div_RegisterImmediate(register, immediate),
+            },
+            "and" => match parse_arithmetic_operands(instruction_mnemonic, instruction_arguments, path, lines, line_number, col_start, col_end)? {
+                ArithmeticOperands::Register(register) => Instruction::and_Accumulator(register),
+                ArithmeticOperands::Immediate(immediate) => Instruction::and_AccumulatorImmediate(immediate),
+                ArithmeticOperands::RegisterRegister(dest_register, src_register) => Instruction::and_Register(dest_register, src_register),
+                ArithmeticOperands::RegisterImmediate(register, immediate) => Instruction::and_RegisterImmediate(register, immediate),
+            },
+            "or" => match parse_arithmetic_operands(instruction_mnemonic, instruction_arguments, path, lines, line_number, col_start, col_end)? {
+                ArithmeticOperands::Register(register) => Instruction::or_Accumulator(register),
+                ArithmeticOperands::Immediate(immediate) => Instruction::or_AccumulatorImmediate(immediate),
+                ArithmeticOperands::RegisterRegister(dest_register, src_register) => Instruction::or_Register(dest_register, src_register),
+                ArithmeticOperands::RegisterImmediate(register, immediate) => Instruction::or_RegisterImmediate(register, immediate),
+            },
+            "xor" => match parse_arithmetic_operands(instruction_mnemonic, instruction_arguments, path, lines, line_number, col_start, col_end)? {
+                ArithmeticOperands::Register(register) => Instruction::xor_Accumulator(register),
+                ArithmeticOperands::Immediate(immediate) => Instruction::xor_AccumulatorImmediate(immediate),
+                ArithmeticOperands::RegisterRegister(dest_register, src_register) => Instruction::xor_Register(dest_register, src_register),
+                ArithmeticOperands::RegisterImmediate(register, immediate) => Instruction::xor_RegisterImmediate(register, immediate),
+            },
+            "not" => {
+                if num_args > 1 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 or 1 arguments, but got {num_args}").as_str(),
                         path,
                         lines,
                         line_number,
@@ -903,11 +2051,34 @@ impl Instruction {
                         col_end,
                     )
                 }
+
+                match instruction_arguments.pop_front() {
+                    None => Instruction::not_Accumulator,
+                    Some(InstructionArgumentType::Register(register)) => Instruction::not_Register(register),
+                    _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
+                }
             }
-            "add" => {
-                if num_args < 1 || num_args > 2 {
-                    report_error(
-                        format!("`{instruction_mnemonic}` instruction expects 1 or 2 arguments, but got {num_args}").as_str(),
+            "shl" => match parse_shift_operands(instruction_mnemonic, instruction_arguments, argument_spans, path, lines, line_number, col_start, col_end)? {
+                ShiftOperands::Immediate(register, count) => Instruction::shl_Immediate(register, count),
+                ShiftOperands::Register(register, count_register) => Instruction::shl_Register(register, count_register),
+            },
+            "shr" => match parse_shift_operands(instruction_mnemonic, instruction_arguments, argument_spans, path, lines, line_number, col_start, col_end)? {
+                ShiftOperands::Immediate(register, count) => Instruction::shr_Immediate(register, count),
+                ShiftOperands::Register(register, count_register) => Instruction::shr_Register(register, count_register),
+            },
+            "rol" => match parse_shift_operands(instruction_mnemonic, instruction_arguments, argument_spans, path, lines, line_number, col_start, col_end)? {
+                ShiftOperands::Immediate(register, count) => Instruction::rol_Immediate(register, count),
+                ShiftOperands::Register(register, count_register) => Instruction::rol_Register(register, count_register),
+            },
+            "ror" => match parse_shift_operands(instruction_mnemonic, instruction_arguments, argument_spans, path, lines, line_number, col_start, col_end)? {
+                ShiftOperands::Immediate(register, count) => Instruction::ror_Immediate(register, count),
+                ShiftOperands::Register(register, count_register) => Instruction::ror_Register(register, count_register),
+            },
+            "inc" => {
+                if num_args > 1 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 or 1 arguments, but got {num_args}").as_str(),
                         path,
                         lines,
                         line_number,
@@ -916,117 +2087,1851 @@ impl Instruction {
                     )
                 }
 
-                if num_args == 1 {
-                    let arg = instruction_arguments.pop_front().unwrap();
-    
-                    match arg {
-                        InstructionArgumentType::Register(register) => Instruction::add_RegisterToAccumulator(register),
-                        InstructionArgumentType::Immediate(immediate) => Instruction::add_ImmediateToAccumulator(immediate),
-                        _ => report_error(
-                            format!("Could not find valid overload of `{instruction_mnemonic}` instruction for supplied argument types").as_str(),
-                            path,
-                            lines,
-                            line_number,
-                            col_start,
-                            col_end,
-                        )
-                    }
-                } else {
-                    let (arg1, arg2) = (
-                        instruction_arguments.pop_front().unwrap(),
-                        instruction_arguments.pop_front().unwrap(),
-                    );
-
-                    match (arg1, arg2) {
-                        (
-                            InstructionArgumentType::Register(dest_register), 
-                            InstructionArgumentType::Register(src_register)
-                        ) => Instruction::add_RegisterToRegister(dest_register, src_register),
-                        (
-                            InstructionArgumentType::Register(register),
-                            InstructionArgumentType::Immediate(immediate), 
-                        ) => Instruction::add_ImmediateToRegister(register, immediate),
-                        _ => report_error(
-                            format!("Could not find valid overload of `{instruction_mnemonic}` instruction for supplied argument types").as_str(),
-                            path,
-                            lines,
-                            line_number,
-                            col_start,
-                                col_end,
-                        )
-                    }
+                match instruction_arguments.pop_front() {
+                    None => Instruction::inc_Accumulator,
+                    Some(InstructionArgumentType::Register(register)) => Instruction::inc_Register(register),
+                    _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
                 }
-
             }
-            _ => todo!("Instruction `{instruction_mnemonic}` not implemented"),
-        }
-    }
-}
-
-pub fn build_program(path: &PathBuf, lines: &Vec<String>, tokens: &mut VecDeque<Token>) -> Program {
-    let mut ast = Program::new();
-
-    while !tokens.is_empty() {
-        let token = tokens.pop_front().unwrap();
-
-        let TokenType::Directive(name) = token.token_type else {
-            report_error(
-                format!("Unexpected token `{}`. Program should start with either .data or .text section directive!", token.value).as_str(),
-                path,
-                lines,
-                token.line_number,
-                token.column_start,
-                token.column_end,
-            )
-        };
-
-        match name.as_str() {
-            "data" => {
-                if ast.data.is_none() {
-                    ast.data = Some(DataSection::parse(path, lines, tokens));
-                } else {
-                    report_error(
-                        "Duplicate section '.data'",
+            "dec" => {
+                if num_args > 1 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 or 1 arguments, but got {num_args}").as_str(),
                         path,
                         lines,
-                        token.line_number,
-                        token.column_start,
-                        token.column_end,
+                        line_number,
+                        col_start,
+                        col_end,
                     )
                 }
+
+                match instruction_arguments.pop_front() {
+                    None => Instruction::dec_Accumulator,
+                    Some(InstructionArgumentType::Register(register)) => Instruction::dec_Register(register),
+                    _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
+                }
             }
-            "text" => {
-                if ast.text.is_none() {
-                    ast.text = Some(TextSection::parse(path, lines, tokens));
-                } else {
-                    report_error(
-                        "Duplicate section '.text'",
+            "jmp" => {
+                if num_args != 1 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 1 argument, but got {num_args}").as_str(),
                         path,
                         lines,
-                        token.line_number,
-                        token.column_start,
-                        token.column_end,
+                        line_number,
+                        col_start,
+                        col_end,
                     )
                 }
+
+                match instruction_arguments.pop_front().unwrap() {
+                    InstructionArgumentType::Immediate(address) => Instruction::jmp_Immediate(address),
+                    InstructionArgumentType::Register(register) => Instruction::jmp_Register(register),
+                    InstructionArgumentType::MemoryAddress(address) => Instruction::jmp_Memory(address),
+                    InstructionArgumentType::LabelAddress(label) => Instruction::jmp_Label(label),
+                    _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
+                }
             }
-            _ => report_error(
-                "Expected program to start with either .data or .text section!",
-                path,
-                lines,
-                token.line_number,
+            "jsr" => {
+                if num_args != 1 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 1 argument, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                match instruction_arguments.pop_front().unwrap() {
+                    InstructionArgumentType::Immediate(address) => Instruction::jsr_Immediate(address),
+                    InstructionArgumentType::Register(register) => Instruction::jsr_Register(register),
+                    InstructionArgumentType::MemoryAddress(address) => Instruction::jsr_Memory(address),
+                    InstructionArgumentType::MemoryAddressIndirect(address) => Instruction::jsr_MemoryIndirect(address),
+                    InstructionArgumentType::LabelAddress(label) => Instruction::jsr_Label(label),
+                    _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
+                }
+            }
+            "ret" => {
+                if num_args != 0 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                Instruction::ret
+            }
+            "cmp" => match parse_arithmetic_operands(instruction_mnemonic, instruction_arguments, path, lines, line_number, col_start, col_end)? {
+                ArithmeticOperands::Register(register) => Instruction::cmp_Accumulator(register),
+                ArithmeticOperands::Immediate(immediate) => Instruction::cmp_AccumulatorImmediate(immediate),
+                ArithmeticOperands::RegisterRegister(dest_register, src_register) => Instruction::cmp_Register(dest_register, src_register),
+                ArithmeticOperands::RegisterImmediate(register, immediate) => Instruction::cmp_RegisterImmediate(register, immediate),
+            },
+            "beq" | "bne" | "blt" | "bge" | "bcs" | "bcc" => {
+                if num_args != 1 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 1 argument, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                let InstructionArgumentType::LabelAddress(label) = instruction_arguments.pop_front().unwrap() else {
+                    return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
+                };
+
+                // Branches start out short and are made long during layout if their target is too far away
+                let branch = Branch { label, long: false };
+
+                match instruction_mnemonic.as_str() {
+                    "beq" => Instruction::beq(branch),
+                    "bne" => Instruction::bne(branch),
+                    "blt" => Instruction::blt(branch),
+                    "bge" => Instruction::bge(branch),
+                    "bcs" => Instruction::bcs(branch),
+                    _ => Instruction::bcc(branch),
+                }
+            }
+            "syscall" => {
+                if num_args != 0 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                Instruction::syscall
+            }
+            "ssc" => {
+                if num_args != 1 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 1 argument, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                match instruction_arguments.pop_front().unwrap() {
+                    InstructionArgumentType::Immediate(address) => Instruction::ssc(address),
+                    _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end),
+                }
+            }
+            "push" => {
+                if num_args != 1 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 1 argument, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                match instruction_arguments.pop_front().unwrap() {
+                    InstructionArgumentType::Immediate(immediate) => Instruction::push_Immediate(immediate),
+                    InstructionArgumentType::MemoryAddress(address) => Instruction::push_Memory(address),
+                    InstructionArgumentType::Register(register) => Instruction::push_Register(register),
+                    _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
+                }
+            }
+            "pop" => {
+                if num_args != 1 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 1 argument, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                match instruction_arguments.pop_front().unwrap() {
+                    InstructionArgumentType::MemoryAddress(address) => Instruction::pop_Memory(address),
+                    InstructionArgumentType::Register(register) => Instruction::pop_Register(register),
+                    _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
+                }
+            }
+            "pusha" => {
+                if num_args != 0 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                Instruction::pusha
+            }
+            "popa" => {
+                if num_args != 0 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                Instruction::popa
+            }
+            "xchg" => {
+                if num_args != 2 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 2 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                let (arg1, arg2) = (
+                    instruction_arguments.pop_front().unwrap(),
+                    instruction_arguments.pop_front().unwrap(),
+                );
+
+                match (arg1, arg2) {
+                    (
+                        InstructionArgumentType::Register(first),
+                        InstructionArgumentType::Register(second),
+                    ) => Instruction::xchg_Registers(first, second),
+                    (
+                        InstructionArgumentType::Register(register),
+                        InstructionArgumentType::MemoryAddress(address),
+                    ) => Instruction::xchg_RegisterMemory(register, address),
+                    (
+                        InstructionArgumentType::MemoryAddress(address),
+                        InstructionArgumentType::Register(register),
+                    ) => Instruction::xchg_MemoryRegister(address, register),
+                    _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
+                }
+            }
+            "hlt" => {
+                if num_args != 0 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                Instruction::hlt
+            }
+            "brk" => {
+                if num_args != 0 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                Instruction::brk
+            }
+            "in" => {
+                if num_args != 2 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 2 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                let data = (instruction_arguments.pop_front().unwrap(), argument_spans[0]);
+                let port = (instruction_arguments.pop_front().unwrap(), argument_spans[1]);
+
+                match parse_port_operands(instruction_mnemonic, (port, data), path, lines, line_number, col_start, col_end)? {
+                    PortOperands::Immediate(port, register) => Instruction::in_Immediate(register, port),
+                    PortOperands::Register(port, register) => Instruction::in_Register(register, port),
+                }
+            }
+            "out" => {
+                if num_args != 2 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 2 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                let port = (instruction_arguments.pop_front().unwrap(), argument_spans[0]);
+                let data = (instruction_arguments.pop_front().unwrap(), argument_spans[1]);
+
+                match parse_port_operands(instruction_mnemonic, (port, data), path, lines, line_number, col_start, col_end)? {
+                    PortOperands::Immediate(port, register) => Instruction::out_Immediate(port, register),
+                    PortOperands::Register(port, register) => Instruction::out_Register(port, register),
+                }
+            }
+            "cli" => {
+                if num_args != 0 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                Instruction::cli
+            }
+            "sei" => {
+                if num_args != 0 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                Instruction::sei
+            }
+            "rti" => {
+                if num_args != 0 {
+                    return report_error(
+                        Code::WrongArgumentCount,
+                        format!("`{instruction_mnemonic}` instruction expects 0 arguments, but got {num_args}").as_str(),
+                        path,
+                        lines,
+                        line_number,
+                        col_start,
+                        col_end,
+                    )
+                }
+
+                Instruction::rti
+            }
+            _ => unreachable!("Instruction `{instruction_mnemonic}` is listed in the ISA but not parsed"),
+        })
+    }
+}
+
+/**
+ * Size of the data an instruction moves, chosen with a `.b` or `.w` suffix on its mnemonic
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandSize {
+    Byte,
+    Word,
+}
+
+impl OperandSize {
+    pub fn bits(&self) -> u8 {
+        match self {
+            OperandSize::Byte => 8,
+            OperandSize::Word => 16,
+        }
+    }
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            OperandSize::Byte => "b",
+            OperandSize::Word => "w",
+        }
+    }
+}
+
+/**
+ * Read a label operand as the memory at the label where the instruction takes an address but not a label, such as
+ * `mov %eax, counter`. Jumps, calls and branches keep the label as their target.
+ *
+ * The address of the label is filled in like any other label operand, once labels are placed.
+ */
+fn label_operands_as_addresses(mnemonic: &Mnemonic, arguments: &mut InstructionArguments) {
+    let count = arguments.len();
+
+    for (position, argument) in arguments.iter_mut().enumerate() {
+        if !matches!(argument, InstructionArgumentType::LabelAddress(_)) {
+            continue;
+        }
+
+        let accepts = |kind: OperandKind| {
+            mnemonic
+                .signatures
+                .iter()
+                .any(|signature| signature.len() == count && signature[position] == kind)
+        };
+
+        if !accepts(OperandKind::Label) && accepts(OperandKind::MemoryAddress) {
+            *argument = InstructionArgumentType::MemoryAddress(0);
+        }
+    }
+}
+
+/**
+ * Report arguments that match none of the forms of an instruction with as many operands.
+ *
+ * The error points at the first argument that differs from the closest form and lists every form from the ISA spec.
+ * Argument counts that no form takes are left to the instruction to report.
+ */
+fn check_overloads(
+    mnemonic: &Mnemonic,
+    arguments: &InstructionArguments,
+    spans: &[Span],
+    path: &Path,
+    lines: &[String],
+    span: Span,
+) -> diagnostic::Result<()> {
+    let kinds: Vec<_> = arguments.iter().map(|argument| argument.kind()).collect();
+
+    let candidates: Vec<_> = mnemonic
+        .signatures
+        .iter()
+        .filter(|signature| signature.len() == kinds.len())
+        .collect();
+
+    let matching = |signature: &Vec<OperandKind>| {
+        signature
+            .iter()
+            .zip(&kinds)
+            .take_while(|(expected, kind)| Some(**expected) == **kind)
+            .count()
+    };
+
+    // The closest form matches the most arguments from the start
+    let Some(position) = candidates.iter().map(|signature| matching(signature)).max() else {
+        return Ok(());
+    };
+
+    if position == kinds.len() {
+        return Ok(());
+    }
+
+    let mut expected: Vec<&str> = Vec::new();
+
+    for signature in candidates.iter().filter(|signature| matching(signature) == position) {
+        let name = signature[position].name();
+
+        if !expected.contains(&name) {
+            expected.push(name);
+        }
+    }
+
+    let given = match kinds[position] {
+        Some(kind) => kind.name(),
+        None => "label value",
+    };
+
+    let article = |name: &str| {
+        if name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+            format!("an {name}")
+        } else {
+            format!("a {name}")
+        }
+    };
+
+    let mut expected: Vec<_> = expected.iter().map(|name| article(name)).collect();
+
+    // Written as a list, such as `a register, an address or an immediate`
+    let last = expected.pop().unwrap();
+    let expected = if expected.is_empty() {
+        last
+    } else {
+        format!("{} or {last}", expected.join(", "))
+    };
+    let forms: Vec<_> = mnemonic.forms().iter().map(|form| format!("`{form}`")).collect();
+
+    let error = format!(
+        "`{}` does not accept {} as operand {}, expected {}!",
+        mnemonic.name,
+        article(given),
+        position + 1,
+        expected
+    );
+    let note = format!("`{}` accepts {}", mnemonic.name, forms.join(", "));
+    let span = spans.get(position).copied().unwrap_or(span);
+
+    report_error_with_note(
+        Code::InvalidOperand,
+        error.as_str(),
+        Some(note.as_str()),
+        path,
+        lines,
+        span.line_number,
+        span.column_start,
+        span.column_end,
+    )
+}
+
+/**
+ * Report operands that match none of the forms of an instruction, listing the forms from the ISA spec
+ */
+fn report_invalid_overload<T>(
+    instruction_mnemonic: &str,
+    path: &Path,
+    lines: &[String],
+    line_number: u32,
+    col_start: u32,
+    col_end: u32,
+) -> diagnostic::Result<T> {
+    let note = Mnemonic::from_name(instruction_mnemonic).map(|mnemonic| {
+        let forms: Vec<_> = mnemonic.forms().iter().map(|form| format!("`{form}`")).collect();
+
+        format!("`{instruction_mnemonic}` accepts {}", forms.join(", "))
+    });
+
+    report_error_with_note(
+        Code::InvalidOperand,
+        format!("Could not find valid overload of `{instruction_mnemonic}` instruction for supplied argument types").as_str(),
+        note.as_deref(),
+        path,
+        lines,
+        line_number,
+        col_start,
+        col_end,
+    )
+}
+
+/**
+ * Report special registers used by instructions that cannot access them
+ *
+ * `%pc` can only be read, the first operand of `mov` and the operands of `pop` and `xchg` are written to
+ */
+fn check_special_registers(
+    mnemonic: &str,
+    arguments: &InstructionArguments,
+    path: &Path,
+    lines: &[String],
+    line_number: u32,
+    col_start: u32,
+    col_end: u32,
+) -> diagnostic::Result<()> {
+    for (position, argument) in arguments.iter().enumerate() {
+        let InstructionArgumentType::Register(register) = argument else {
+            continue;
+        };
+
+        let is_written = (mnemonic == "mov" && position == 0) || mnemonic == "pop" || mnemonic == "xchg";
+
+        if *register == Register::PC && is_written {
+            return report_error(
+                Code::InvalidRegister,
+                "`%pc` is read-only, use `jmp` to change the program counter!",
+                path,
+                lines,
+                line_number,
+                col_start,
+                col_end,
+            )
+        }
+
+        let Some(allowed) = register.allowed_mnemonics() else {
+            continue;
+        };
+
+        if !allowed.contains(&mnemonic) {
+            let names: Vec<_> = allowed.iter().map(|name| format!("`{name}`")).collect();
+
+            return report_error(
+                Code::InvalidRegister,
+                format!(
+                    "`%{}` cannot be used by `{mnemonic}`, it is only accepted by {}!",
+                    register.name(),
+                    names.join(", ")
+                )
+                .as_str(),
+                path,
+                lines,
+                line_number,
+                col_start,
+                col_end,
+            )
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * Report registers of different widths used together, and 8-bit registers where an address is expected
+ *
+ * The count of a shift and the port of `in` and `out` are not moved into the other register, so they can be any width.
+ */
+fn check_register_widths(mnemonic: &str, arguments: &InstructionArguments, spans: &[Span], path: &Path, lines: &[String]) -> diagnostic::Result<()> {
+    let registers: Vec<_> = arguments
+        .iter()
+        .zip(spans)
+        .filter_map(|(argument, span)| match argument {
+            InstructionArgumentType::Register(register) => Some((register, *span)),
+            _ => None,
+        })
+        .collect();
+
+    if matches!(mnemonic, "jmp" | "jsr") {
+        if let Some((register, span)) = registers.iter().find(|(register, _)| register.width() != 16) {
+            return report_span_error(
+                Code::OperandSizeMismatch,
+                format!("`{mnemonic}` needs a 16-bit address, but `%{}` holds {} bits!", register.name(), register.width()).as_str(),
+                path,
+                lines,
+                *span,
+            )
+        }
+    }
+
+    if matches!(mnemonic, "shl" | "shr" | "rol" | "ror" | "in" | "out") {
+        return Ok(());
+    }
+
+    if let [(first, _), (second, span)] = registers.as_slice() {
+        if first.width() != second.width() {
+            return report_span_error(
+                Code::OperandSizeMismatch,
+                format!(
+                    "`{mnemonic}` cannot combine the {}-bit `%{}` with the {}-bit `%{}`, both registers must be the same width!",
+                    first.width(),
+                    first.name(),
+                    second.width(),
+                    second.name()
+                )
+                .as_str(),
+                path,
+                lines,
+                *span,
+            )
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * Split a mnemonic such as `mov.b` into its name and the size given by its suffix
+ */
+fn split_size_suffix(mnemonic: &str) -> (String, Option<OperandSize>) {
+    match mnemonic.rsplit_once('.') {
+        Some((name, "b")) => (name.to_owned(), Some(OperandSize::Byte)),
+        Some((name, "w")) => (name.to_owned(), Some(OperandSize::Word)),
+        _ => (mnemonic.to_owned(), None),
+    }
+}
+
+/**
+ * Operands of an arithmetic instruction, which works on the accumulator when it is given a single operand
+ */
+enum ArithmeticOperands {
+    Register(Register),
+    Immediate(u16),
+    RegisterRegister(Register, Register),
+    RegisterImmediate(Register, u16),
+}
+
+/**
+ * Match the arguments of an arithmetic instruction such as `add` against its overloads
+ */
+fn parse_arithmetic_operands(
+    instruction_mnemonic: &String,
+    instruction_arguments: &mut InstructionArguments,
+    path: &Path,
+    lines: &[String],
+    line_number: u32,
+    col_start: u32,
+    col_end: u32,
+) -> diagnostic::Result<ArithmeticOperands> {
+    let num_args = instruction_arguments.len();
+
+    if !(1..=2).contains(&num_args) {
+        return report_error(
+            Code::WrongArgumentCount,
+            format!("`{instruction_mnemonic}` instruction expects 1 or 2 arguments, but got {num_args}").as_str(),
+            path,
+            lines,
+            line_number,
+            col_start,
+            col_end,
+        )
+    }
+
+    Ok(match (instruction_arguments.pop_front().unwrap(), instruction_arguments.pop_front()) {
+        (InstructionArgumentType::Register(register), None) => ArithmeticOperands::Register(register),
+        (InstructionArgumentType::Immediate(immediate), None) => ArithmeticOperands::Immediate(immediate),
+        (InstructionArgumentType::Register(dest_register), Some(InstructionArgumentType::Register(src_register))) => {
+            ArithmeticOperands::RegisterRegister(dest_register, src_register)
+        }
+        (InstructionArgumentType::Register(register), Some(InstructionArgumentType::Immediate(immediate))) => {
+            ArithmeticOperands::RegisterImmediate(register, immediate)
+        }
+        _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end),
+    })
+}
+
+/**
+ * Operands of a shift or rotate, a register and the number of bits to move it by
+ */
+enum ShiftOperands {
+    Immediate(Register, u16),
+    Register(Register, Register),
+}
+
+/**
+ * Match the arguments of a shift or rotate such as `shl` against its overloads
+ *
+ * An immediate count must be less than the width of the register, so `shl %eax, #16` is an error.
+ */
+#[allow(clippy::too_many_arguments)]
+fn parse_shift_operands(
+    instruction_mnemonic: &String,
+    instruction_arguments: &mut InstructionArguments,
+    argument_spans: &[Span],
+    path: &Path,
+    lines: &[String],
+    line_number: u32,
+    col_start: u32,
+    col_end: u32,
+) -> diagnostic::Result<ShiftOperands> {
+    let num_args = instruction_arguments.len();
+
+    if num_args != 2 {
+        return report_error(
+            Code::WrongArgumentCount,
+            format!("`{instruction_mnemonic}` instruction expects 2 arguments, but got {num_args}").as_str(),
+            path,
+            lines,
+            line_number,
+            col_start,
+            col_end,
+        )
+    }
+
+    Ok(match (instruction_arguments.pop_front().unwrap(), instruction_arguments.pop_front().unwrap()) {
+        (InstructionArgumentType::Register(register), InstructionArgumentType::Immediate(count)) => {
+            let width = register.width() as u16;
+
+            if count >= width {
+                return report_span_error(
+                    Code::ValueOutOfRange,
+                    format!(
+                        "`{instruction_mnemonic}` count of {count} is out of range for the {width}-bit register `%{}`, expected 0 to {}!",
+                        register.name(),
+                        width - 1
+                    )
+                    .as_str(),
+                    path,
+                    lines,
+                    argument_spans[1],
+                )
+            }
+
+            ShiftOperands::Immediate(register, count)
+        }
+        (InstructionArgumentType::Register(register), InstructionArgumentType::Register(count_register)) => {
+            ShiftOperands::Register(register, count_register)
+        }
+        _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end),
+    })
+}
+
+/**
+ * Operands of `in` and `out`, the port and the 8-bit register that is transferred
+ */
+enum PortOperands {
+    Immediate(u16, Register),
+    Register(Register, Register),
+}
+
+/**
+ * Match the port and data arguments of `in` or `out` against their overloads
+ *
+ * Ports are 8-bit registers, so the data has to be an 8-bit register and an immediate port must exist.
+ */
+fn parse_port_operands(
+    instruction_mnemonic: &str,
+    ((port, port_span), (data, data_span)): ((InstructionArgumentType, Span), (InstructionArgumentType, Span)),
+    path: &Path,
+    lines: &[String],
+    line_number: u32,
+    col_start: u32,
+    col_end: u32,
+) -> diagnostic::Result<PortOperands> {
+    let InstructionArgumentType::Register(register) = data else {
+        return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end)
+    };
+
+    if register.width() != 8 {
+        return report_span_error(
+            Code::OperandSizeMismatch,
+            format!("`{instruction_mnemonic}` transfers 8 bits, but `%{}` holds {} bits!", register.name(), register.width()).as_str(),
+            path,
+            lines,
+            data_span,
+        )
+    }
+
+    Ok(match port {
+        InstructionArgumentType::Immediate(port) => {
+            if port >= IO_PORTS {
+                return report_span_error(
+                    Code::ValueOutOfRange,
+                    format!("I/O port {port} is out of range, expected 0 to {}!", IO_PORTS - 1).as_str(),
+                    path,
+                    lines,
+                    port_span,
+                )
+            }
+
+            PortOperands::Immediate(port, register)
+        }
+        InstructionArgumentType::Register(port_register) => PortOperands::Register(port_register, register),
+        _ => return report_invalid_overload(instruction_mnemonic, path, lines, line_number, col_start, col_end),
+    })
+}
+
+pub fn build_program(
+    path: &Path,
+    lines: &[String],
+    tokens: &mut VecDeque<Token>,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<Program> {
+    let mut ast = Program::new();
+
+    // Assertions and messages can be anywhere in the file, they are only evaluated after labels are placed
+    for line in take_directive_lines(tokens, DEFERRED_DIRECTIVES) {
+        let parsed = match &line[0].token_type {
+            TokenType::Directive(name) if name == "assert" => parse_assertion(path, lines, line).map(|assertion| ast.assertions.push(assertion)),
+            _ => parse_message(path, lines, line).map(|message| ast.messages.push(message)),
+        };
+
+        diagnostics.recover(parsed)?;
+    }
+
+    while !tokens.is_empty() {
+        let token = tokens.pop_front().unwrap();
+
+        let TokenType::Directive(name) = token.token_type.clone() else {
+            return report_error(
+                Code::MisplacedDirective,
+                format!("Unexpected token `{}`. Program should start with either .data or .text section directive!", token.value).as_str(),
+                path,
+                lines,
+                token.line_number,
                 token.column_start,
                 token.column_end,
+            )
+        };
+
+        match name.as_str() {
+            "data" => {
+                if ast.data.is_none() {
+                    ast.data = Some(DataSection::parse(path, lines, tokens, diagnostics)?);
+                } else {
+                    return report_error(
+                        Code::DuplicateDirective,
+                        "Duplicate section '.data'",
+                        path,
+                        lines,
+                        token.line_number,
+                        token.column_start,
+                        token.column_end,
+                    )
+                }
+            }
+            "bss" => {
+                if ast.bss.is_none() {
+                    ast.bss = Some(parse_bss(path, lines, tokens, diagnostics)?);
+                } else {
+                    return report_span_error(Code::DuplicateDirective, "Duplicate section '.bss'", path, lines, token.span())
+                }
+            }
+            "text" => {
+                if ast.text.is_none() {
+                    ast.text = Some(TextSection::parse(path, lines, tokens, diagnostics)?);
+                } else {
+                    return report_error(
+                        Code::DuplicateDirective,
+                        "Duplicate section '.text'",
+                        path,
+                        lines,
+                        token.line_number,
+                        token.column_start,
+                        token.column_end,
+                    )
+                }
+            }
+            "section" => {
+                let (name, origin, span) = parse_section_header(path, lines, &token, tokens)?;
+
+                if let Some(existing) = ast.sections.iter().find(|existing| existing.name == name) {
+                    return report_span_error_with_label(
+                        Code::DuplicateDirective,
+                        format!("Duplicate section '.section {name}'").as_str(),
+                        path,
+                        lines,
+                        span,
+                        "first declared here",
+                        existing.span,
+                    )
+                }
+
+                ast.sections.push(NamedSection {
+                    name,
+                    origin,
+                    text: TextSection::parse(path, lines, tokens, diagnostics)?,
+                    span,
+                });
+            }
+            "region" => {
+                let region = parse_region(path, lines, &token, tokens)?;
+
+                if let Some(existing) = ast.regions.iter().find(|existing| existing.name == region.name) {
+                    return report_span_error_with_label(
+                        Code::DuplicateDirective,
+                        format!("Duplicate region `{}`", region.name).as_str(),
+                        path,
+                        lines,
+                        region.span,
+                        "first declared here",
+                        existing.span,
+                    )
+                }
+
+                ast.regions.push(region);
+            }
+            "endian" => {
+                let endian = parse_endian(path, lines, &token, tokens)?;
+
+                if let Some(existing) = &ast.endian {
+                    return report_span_error_with_label(
+                        Code::DuplicateDirective,
+                        "Duplicate .endian directive, a program can only have one byte order!",
+                        path,
+                        lines,
+                        endian.span,
+                        "byte order first set here",
+                        existing.span,
+                    )
+                }
+
+                ast.endian = Some(endian);
+            }
+            "entry" => {
+                let entry = parse_entry(path, lines, &token, tokens)?;
+
+                if let Some(existing) = &ast.entry {
+                    return report_span_error_with_label(
+                        Code::DuplicateDirective,
+                        "Duplicate .entry directive, a program can only have one entry point!",
+                        path,
+                        lines,
+                        entry.span,
+                        "entry point first set here",
+                        existing.span,
+                    )
+                }
+
+                ast.entry = Some(entry);
+            }
+            "global" | "extern" => {
+                for symbol in parse_symbol_list(path, lines, &token, tokens)? {
+                    let (same, other) = if name == "global" {
+                        (&ast.globals, &ast.externs)
+                    } else {
+                        (&ast.externs, &ast.globals)
+                    };
+
+                    if let Some(existing) = same.iter().find(|existing| existing.node == symbol.node) {
+                        return report_span_error_with_label(
+                            Code::DuplicateDirective,
+                            format!("Label `{}` was already marked .{name}", symbol.node).as_str(),
+                            path,
+                            lines,
+                            symbol.span,
+                            "first marked here",
+                            existing.span,
+                        )
+                    }
+
+                    if let Some(existing) = other.iter().find(|existing| existing.node == symbol.node) {
+                        let other_name = if name == "global" { "extern" } else { "global" };
+
+                        return report_span_error_with_label(
+                            Code::SymbolConflict,
+                            format!("Label `{}` cannot be both .global and .extern!", symbol.node).as_str(),
+                            path,
+                            lines,
+                            symbol.span,
+                            format!("marked .{other_name} here").as_str(),
+                            existing.span,
+                        )
+                    }
+
+                    if name == "global" {
+                        ast.globals.push(symbol);
+                    } else {
+                        ast.externs.push(symbol);
+                    }
+                }
+            }
+            _ => return report_span_error_with_help(
+                Code::MisplacedDirective,
+                "Expected program to start with either .data or .text section!",
+                match &token.token_type {
+                    TokenType::Directive(name) => suggest::did_you_mean(name, ".", DIRECTIVES.iter().copied()),
+                    _ => None,
+                }
+                .as_deref(),
+                path,
+                lines,
+                token.span(),
+            ),
+        }
+    }
+
+    // The image only has room for a single checksum
+    if let Some(data) = &ast.data {
+        if let Some(duplicate) = data
+            .labels
+            .iter()
+            .flat_map(|label| &label.constants)
+            .filter(|constant| matches!(constant.node, ConstantLabelType::Checksum(_)))
+            .nth(1)
+        {
+            return report_span_error(
+                Code::DuplicateDirective,
+                "Duplicate .checksum directive, a program can only have one checksum!",
+                path,
+                lines,
+                duplicate.span,
+            )
+        }
+    }
+
+    Ok(ast)
+}
+
+/**
+ * Parse the labels of a .bss section, which can only reserve zeroed bytes
+ */
+fn parse_bss(
+    path: &Path,
+    lines: &[String],
+    tokens: &mut VecDeque<Token>,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<DataSection> {
+    let bss = DataSection::parse(path, lines, tokens, diagnostics)?;
+
+    let initialized = bss
+        .labels
+        .iter()
+        .flat_map(|label| &label.constants)
+        .find(|constant| !matches!(constant.node, ConstantLabelType::Space { value: 0, .. }));
+
+    if let Some(constant) = initialized {
+        return report_span_error(
+            Code::MisplacedDirective,
+            "Only .res and .space without a fill value can be used in .bss, since it has no bytes in the image!",
+            path,
+            lines,
+            constant.span,
+        )
+    }
+
+    Ok(bss)
+}
+
+/**
+ * Number of bytes a single label assembles to, `tokens` should start with the label itself
+ */
+pub fn label_size(path: &Path, lines: &[String], tokens: &mut VecDeque<Token>, section: Section) -> diagnostic::Result<u32> {
+    // Errors in the label are reported when the whole program is parsed
+    let diagnostics = &mut Diagnostics::new(0, WarningOptions::default());
+
+    Ok(match section {
+        Section::Text | Section::Named(_) => TextSection::parse(path, lines, tokens, diagnostics)?
+            .labels
+            .iter()
+            .flat_map(|label| &label.contents)
+            .map(|item| u32::from(item.node.size()))
+            .sum(),
+        Section::Data | Section::Bss => DataSection::parse(path, lines, tokens, diagnostics)?
+            .labels
+            .iter()
+            .flat_map(|label| &label.constants)
+            .map(|constant| u32::from(constant.node.size()))
+            .sum(),
+    })
+}
+
+/**
+ * If a single label has a conditional branch, which can be made long once the program is laid out.
+ * `tokens` should start with the label itself.
+ */
+pub fn label_has_branch(path: &Path, lines: &[String], tokens: &mut VecDeque<Token>, section: Section) -> diagnostic::Result<bool> {
+    // Errors in the label are reported when the whole program is parsed
+    let diagnostics = &mut Diagnostics::new(0, WarningOptions::default());
+
+    Ok(match section {
+        Section::Text | Section::Named(_) => TextSection::parse(path, lines, tokens, diagnostics)?
+            .labels
+            .iter()
+            .flat_map(|label| &label.contents)
+            .any(|item| matches!(&item.node, SubroutineItem::Instruction { instruction, .. } if instruction.branch().is_some())),
+        Section::Data | Section::Bss => false,
+    })
+}
+
+/**
+ * Number of values in the data of a single label, `tokens` should start with the label itself.
+ *
+ * Instructions are not counted, only data directives.
+ */
+pub fn label_length(path: &Path, lines: &[String], tokens: &mut VecDeque<Token>, section: Section) -> diagnostic::Result<u32> {
+    // Errors in the label are reported when the whole program is parsed
+    let diagnostics = &mut Diagnostics::new(0, WarningOptions::default());
+
+    Ok(match section {
+        Section::Text | Section::Named(_) => TextSection::parse(path, lines, tokens, diagnostics)?
+            .labels
+            .iter()
+            .flat_map(|label| &label.contents)
+            .filter_map(|item| match &item.node {
+                SubroutineItem::Constant(constant) => Some(u32::from(constant.length())),
+                SubroutineItem::Instruction { .. } => None,
+            })
+            .sum(),
+        Section::Data | Section::Bss => DataSection::parse(path, lines, tokens, diagnostics)?
+            .labels
+            .iter()
+            .flat_map(|label| &label.constants)
+            .map(|constant| u32::from(constant.node.length()))
+            .sum(),
+    })
+}
+
+/**
+ * Parse a single 16-bit number literal given to a directive like .word
+ */
+fn parse_word(path: &Path, lines: &[String], token: &Token, directive: &str) -> diagnostic::Result<u16> {
+    match &token.token_type {
+        TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_) => token.parse_u16(path, lines),
+        TokenType::Immediate => Diagnostic::at(
+            Severity::Error,
+            Code::MisplacedDirective,
+            format!("The .{directive} directive does not require an immediate `#` marker!").as_str(),
+            None,
+            path,
+            lines,
+            token.span(),
+        )
+        .with_fix("Remove the `#`", path, lines, token.span(), "")
+        .raise(),
+        _ => report_span_error(
+            Code::InvalidDirectiveArgument,
+            format!("Expected a number literal after .{directive} directive!").as_str(),
+            path,
+            lines,
+            token.span(),
+        ),
+    }
+}
+
+/**
+ * Checks if a value is an expression to compute once labels are placed, rather than a single literal
+ */
+fn is_expression_value(value_tokens: &VecDeque<Token>) -> bool {
+    match value_tokens.front().map(|token| &token.token_type) {
+        Some(TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_) | TokenType::Char(_)) => {
+            value_tokens.len() > 1
+        }
+        // A stray `#` is reported by the literal parsers
+        Some(TokenType::Immediate) | None => false,
+        Some(_) => true,
+    }
+}
+
+/**
+ * Every literal in a list of .byte or .word values is a single token
+ */
+fn expect_end_of_value(path: &Path, lines: &[String], value_tokens: &VecDeque<Token>, kind: &str) -> diagnostic::Result<()> {
+    if let Some(illegal_token) = value_tokens.front() {
+        return report_span_error(
+            Code::UnexpectedToken,
+            format!("Unexpected token `{}` after {kind} value! Expected `,`", illegal_token.value).as_str(),
+            path,
+            lines,
+            illegal_token.span(),
+        )
+    }
+
+    Ok(())
+}
+
+/**
+ * Parse a single byte given to a directive like .byte, which can be a number or a character literal
+ */
+fn parse_byte(path: &Path, lines: &[String], token: &Token, directive: &str) -> diagnostic::Result<u8> {
+    Ok(match &token.token_type {
+        TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_) => {
+            match u8::try_from(token.parse_u16(path, lines)?) {
+                Ok(value) => value,
+                Err(_) => return report_span_error(
+                    Code::ValueOutOfRange,
+                    format!("Literal `{}` is larger than expected 8-bit byte! (Max is $FF)", token.value).as_str(),
+                    path,
+                    lines,
+                    token.span(),
+                ),
+            }
+        }
+        TokenType::Char(contents) => {
+            let character = unescape_string(contents);
+            let mut chars = character.chars();
+
+            match (chars.next(), chars.next()) {
+                (Some(character), None) if character.is_ascii() => character as u8,
+                _ => return report_span_error(
+                    Code::InvalidToken,
+                    "Character literal must contain exactly one ascii character!",
+                    path,
+                    lines,
+                    token.span(),
+                ),
+            }
+        }
+        TokenType::Immediate => return Diagnostic::at(
+            Severity::Error,
+            Code::MisplacedDirective,
+            format!("The .{directive} directive does not require an immediate `#` marker!").as_str(),
+            None,
+            path,
+            lines,
+            token.span(),
+        )
+        .with_fix("Remove the `#`", path, lines, token.span(), "")
+        .raise(),
+        _ => return report_span_error(
+            Code::InvalidDirectiveArgument,
+            format!("Expected a number or character literal after .{directive} directive!").as_str(),
+            path,
+            lines,
+            token.span(),
+        ),
+    })
+}
+
+/**
+ * Checks if a directive separates the labels of a section
+ */
+pub fn is_section_directive(name: &str) -> bool {
+    matches!(name, "text" | "data" | "bss" | "section" | "region" | "endian" | "entry" | "global" | "extern")
+}
+
+/**
+ * Checks if a token ends the contents of the label before it
+ */
+pub fn is_label_boundary(token: &Token) -> bool {
+    match &token.token_type {
+        TokenType::Directive(name) => is_section_directive(name) || name == "org",
+        TokenType::Label(_) => true,
+        _ => false,
+    }
+}
+
+/**
+ * Checks if the next token declares a label
+ */
+fn starts_with_label(tokens: &VecDeque<Token>) -> bool {
+    matches!(tokens.front(), Some(Token { token_type: TokenType::Label(_), .. }))
+}
+
+/**
+ * Read the rest of the line after a directive token, along with the span of the whole directive
+ */
+fn read_directive_arguments(directive_token: &Token, tokens: &mut VecDeque<Token>) -> (VecDeque<Token>, Span) {
+    let mut line = VecDeque::new();
+
+    while tokens
+        .front()
+        .is_some_and(|token| token.line_number == directive_token.line_number)
+    {
+        line.push_back(tokens.pop_front().unwrap());
+    }
+
+    let span = directive_token.span().to(&line.back().unwrap_or(directive_token).span());
+
+    (line, span)
+}
+
+/**
+ * Parse the label name of a `.entry <label>` directive after its directive token
+ */
+fn parse_entry(path: &Path, lines: &[String], directive_token: &Token, tokens: &mut VecDeque<Token>) -> diagnostic::Result<Spanned<String>> {
+    let (mut line, span) = read_directive_arguments(directive_token, tokens);
+
+    Ok(match (line.pop_front(), line.pop_front()) {
+        (
+            Some(Token {
+                token_type: TokenType::Identifier(name),
+                ..
+            }),
+            None,
+        ) => Spanned { span, node: name },
+        _ => return report_span_error(
+            Code::InvalidDirectiveArgument,
+            "Expected a label name after .entry directive! (e.g. `.entry main`)",
+            path,
+            lines,
+            span,
+        ),
+    })
+}
+
+/**
+ * Directives that are evaluated after every label has an address instead of where they appear
+ */
+pub(crate) const DEFERRED_DIRECTIVES: &[&str] = &["assert", "print", "info"];
+
+/**
+ * Remove every line starting with one of the given directives from the tokens, returning the removed lines
+ */
+pub(crate) fn take_directive_lines(tokens: &mut VecDeque<Token>, directives: &[&str]) -> Vec<VecDeque<Token>> {
+    let mut taken = Vec::new();
+    let mut kept = VecDeque::new();
+
+    while !tokens.is_empty() {
+        let line = read_tokens_to_eol(tokens);
+
+        if matches!(&line[0].token_type, TokenType::Directive(name) if directives.contains(&name.as_str())) {
+            taken.push(line);
+        } else {
+            kept.extend(line);
+        }
+    }
+
+    *tokens = kept;
+    taken
+}
+
+/**
+ * Parse an `.assert` line, starting with its directive token
+ */
+fn parse_assertion(path: &Path, lines: &[String], mut line: VecDeque<Token>) -> diagnostic::Result<Assertion> {
+    let directive_token = line.pop_front().unwrap();
+    let span = match line.back() {
+        Some(last) => directive_token.span().to(&last.span()),
+        None => directive_token.span(),
+    };
+
+    if line.is_empty() {
+        return report_span_error(
+            Code::InvalidDirectiveArgument,
+            "Expected a condition after .assert directive! (e.g. `.assert sizeof(main) < 256, \"main is too big\"`)",
+            path,
+            lines,
+            span,
+        )
+    }
+
+    let condition = Expression::parse_prefix(path, lines, &mut line)?;
+
+    let message = match line.pop_front() {
+        None => None,
+        Some(Token {
+            token_type: TokenType::Comma,
+            ..
+        }) => Some(parse_assertion_message(path, lines, span, line)?),
+        Some(token) => return report_span_error(
+            Code::InvalidDirectiveArgument,
+            format!("Unexpected token `{}` after .assert condition! Expected `,` and a message string", token.value).as_str(),
+            path,
+            lines,
+            token.span(),
+        ),
+    };
+
+    Ok(Assertion { condition, message })
+}
+
+/**
+ * Parse a `.print` or `.info` line, starting with its directive token
+ */
+fn parse_message(path: &Path, lines: &[String], mut line: VecDeque<Token>) -> diagnostic::Result<Message> {
+    let directive_token = line.pop_front().unwrap();
+    let span = match line.back() {
+        Some(last) => directive_token.span().to(&last.span()),
+        None => directive_token.span(),
+    };
+
+    if line.is_empty() {
+        return report_span_error(
+            Code::InvalidDirectiveArgument,
+            format!("Expected values to print after {} directive! (e.g. `{} \"size:\", sizeof(table)`)", directive_token.value, directive_token.value).as_str(),
+            path,
+            lines,
+            span,
+        )
+    }
+
+    let mut arguments = Vec::new();
+
+    loop {
+        arguments.push(Expression::parse_prefix(path, lines, &mut line)?);
+
+        match line.pop_front() {
+            None => break,
+            Some(
+                comma_token @ Token {
+                    token_type: TokenType::Comma,
+                    ..
+                },
+            ) => {
+                if line.is_empty() {
+                    return report_span_error(Code::UnexpectedToken, "Unexpected argument separator `,`!", path, lines, comma_token.span())
+                }
+            }
+            Some(token) => return report_span_error(
+                Code::InvalidDirectiveArgument,
+                format!("Unexpected token `{}` in {} directive! Values are separated by `,`", token.value, directive_token.value).as_str(),
+                path,
+                lines,
+                token.span(),
+            ),
+        }
+    }
+
+    Ok(Message { arguments, span })
+}
+
+/**
+ * The message string that follows the comma after an `.assert` condition
+ */
+fn parse_assertion_message(path: &Path, lines: &[String], span: Span, mut line: VecDeque<Token>) -> diagnostic::Result<Spanned<String>> {
+    Ok(match (line.pop_front(), line.pop_front()) {
+        (
+            Some(
+                message_token @ Token {
+                    token_type: TokenType::AsciiString(_),
+                    ..
+                },
+            ),
+            None,
+        ) => {
+            let TokenType::AsciiString(message) = &message_token.token_type else {
+                unreachable!()
+            };
+
+            Spanned {
+                span: message_token.span(),
+                node: message.clone(),
+            }
+        }
+        (Some(Token { token_type: TokenType::AsciiString(_), .. }), Some(token)) => return report_span_error(
+            Code::InvalidDirectiveArgument,
+            format!("Unexpected token `{}` after .assert message!", token.value).as_str(),
+            path,
+            lines,
+            token.span(),
+        ),
+        (Some(token), _) => return report_span_error(
+            Code::InvalidDirectiveArgument,
+            format!("Expected a message string after the .assert condition, found `{}`!", token.value).as_str(),
+            path,
+            lines,
+            token.span(),
+        ),
+        (None, _) => return report_span_error(Code::InvalidDirectiveArgument, "Expected a message string after `,` in .assert directive!", path, lines, span),
+    })
+}
+
+/**
+ * Parse the label names of a `.global` or `.extern` directive after its directive token
+ */
+fn parse_symbol_list(path: &Path, lines: &[String], directive_token: &Token, tokens: &mut VecDeque<Token>) -> diagnostic::Result<Vec<Spanned<String>>> {
+    let (mut line, span) = read_directive_arguments(directive_token, tokens);
+
+    if line.is_empty() {
+        return report_span_error(
+            Code::InvalidDirectiveArgument,
+            format!("Expected label names after {} directive! (e.g. `{} main`)", directive_token.value, directive_token.value).as_str(),
+            path,
+            lines,
+            span,
+        )
+    }
+
+    split_tokens_by_commas(path, lines, &mut line)?
+        .into_iter()
+        .map(|mut name_tokens| match (name_tokens.pop_front(), name_tokens.pop_front()) {
+            (
+                Some(
+                    name_token @ Token {
+                        token_type: TokenType::Identifier(_),
+                        ..
+                    },
+                ),
+                None,
+            ) => Ok(Spanned {
+                span: name_token.span(),
+                node: name_token.value,
+            }),
+            (Some(_), Some(extra_token)) => report_span_error(
+                Code::UnexpectedToken,
+                format!("Unexpected token `{}` after label name! Expected `,`", extra_token.value).as_str(),
+                path,
+                lines,
+                extra_token.span(),
+            ),
+            (Some(token), None) => report_span_error(
+                Code::InvalidDirectiveArgument,
+                format!("Expected a label name after {} directive, but found `{}`!", directive_token.value, token.value).as_str(),
+                path,
+                lines,
+                token.span(),
+            ),
+            (None, _) => unreachable!(),
+        })
+        .collect()
+}
+
+/**
+ * Parse a `.region <name>, <address>` declaration after its directive token
+ */
+fn parse_region(path: &Path, lines: &[String], directive_token: &Token, tokens: &mut VecDeque<Token>) -> diagnostic::Result<Region> {
+    let (mut line, span) = read_directive_arguments(directive_token, tokens);
+
+    Ok(match (line.pop_front(), line.pop_front(), line.pop_front(), line.pop_front()) {
+        (
+            Some(Token { token_type: TokenType::Identifier(name), .. }),
+            Some(Token { token_type: TokenType::Comma, .. }),
+            Some(address_token @ Token { token_type: TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_), .. }),
+            None,
+        ) => Region {
+            name,
+            address: address_token.parse_u16(path, lines)?,
+            span,
+        },
+        _ => return report_error(
+            Code::InvalidDirectiveArgument,
+            "Expected region name and address after .region directive! (e.g. `.region rom_high, $C000`)",
+            path,
+            lines,
+            span.line_number,
+            span.column_start,
+            span.column_end,
+        ),
+    })
+}
+
+/**
+ * Parse the name and optional address of a `.section <name>[, org=<address>]` directive after its directive token
+ */
+fn parse_section_header(
+    path: &Path,
+    lines: &[String],
+    directive_token: &Token,
+    tokens: &mut VecDeque<Token>,
+) -> diagnostic::Result<(String, Option<u16>, Span)> {
+    let (mut line, span) = read_directive_arguments(directive_token, tokens);
+
+    let Some(Token { token_type: TokenType::Identifier(name), .. }) = line.pop_front() else {
+        return report_span_error(
+            Code::InvalidDirectiveArgument,
+            "Expected section name after .section directive! (e.g. `.section vectors, org=$FFF0`)",
+            path,
+            lines,
+            span,
+        )
+    };
+
+    if matches!(name.as_str(), "text" | "data" | "bss") {
+        return report_span_error(
+            Code::MisplacedDirective,
+            format!("Section name `{name}` is reserved, use the .{name} directive instead!").as_str(),
+            path,
+            lines,
+            span,
+        )
+    }
+
+    let origin = match (line.pop_front(), line.pop_front(), line.pop_front(), line.pop_front(), line.pop_front()) {
+        (None, ..) => None,
+        (
+            Some(Token { token_type: TokenType::Comma, .. }),
+            Some(Token { token_type: TokenType::Identifier(key), .. }),
+            Some(Token { token_type: TokenType::Operator(operator), .. }),
+            Some(address_token @ Token { token_type: TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_), .. }),
+            None,
+        ) if key == "org" && operator == "=" => Some(address_token.parse_u16(path, lines)?),
+        _ => return report_span_error(
+            Code::InvalidDirectiveArgument,
+            "Expected `org=<address>` after section name! (e.g. `.section vectors, org=$FFF0`)",
+            path,
+            lines,
+            span,
+        ),
+    };
+
+    Ok((name, origin, span))
+}
+
+/**
+ * Parse the byte order of a `.endian big|little` directive after its directive token
+ */
+fn parse_endian(path: &Path, lines: &[String], directive_token: &Token, tokens: &mut VecDeque<Token>) -> diagnostic::Result<Spanned<Endian>> {
+    let (mut line, span) = read_directive_arguments(directive_token, tokens);
+
+    Ok(match (line.pop_front(), line.pop_front()) {
+        (
+            Some(Token {
+                token_type: TokenType::Identifier(name),
+                ..
+            }),
+            None,
+        ) if Endian::from_name(&name).is_some() => Spanned {
+            span,
+            node: Endian::from_name(&name).unwrap(),
+        },
+        _ => return report_span_error(
+            Code::InvalidDirectiveArgument,
+            "Expected `big` or `little` after .endian directive!",
+            path,
+            lines,
+            span,
+        ),
+    })
+}
+
+/**
+ * Parse the address of a `.org <address>` directive after its directive token
+ */
+fn parse_origin(path: &Path, lines: &[String], directive_token: &Token, tokens: &mut VecDeque<Token>) -> diagnostic::Result<Spanned<u16>> {
+    let (mut line, span) = read_directive_arguments(directive_token, tokens);
+
+    Ok(match (line.pop_front(), line.pop_front()) {
+        (
+            Some(address_token @ Token { token_type: TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_), .. }),
+            None,
+        ) => Spanned {
+            span,
+            node: address_token.parse_u16(path, lines)?,
+        },
+        _ => return report_span_error(
+            Code::InvalidDirectiveArgument,
+            "Expected an address after .org directive! (e.g. `.org $C000`)",
+            path,
+            lines,
+            span,
+        ),
+    })
+}
+
+/**
+ * The label a `.org` in the middle of continues, which is the last one parsed
+ */
+fn continued_label<'a, T>(path: &Path, lines: &[String], labels: &'a mut [T], origin: &Spanned<u16>) -> diagnostic::Result<&'a mut T> {
+    match labels.last_mut() {
+        Some(label) => Ok(label),
+        None => report_span_error(
+            Code::MisplacedDirective,
+            "Expected a label after .org directive!",
+            path,
+            lines,
+            origin.span,
+        ),
+    }
+}
+
+/**
+ * Skip the rest of a label after an error in it, along with the parts of it moved by a `.org`
+ */
+fn skip_label(tokens: &mut VecDeque<Token>) {
+    loop {
+        read_tokens_to_label_or_eos(tokens);
+
+        let Some(directive) = tokens.front().filter(|token| token.token_type == TokenType::Directive("org".to_owned())) else {
+            return;
+        };
+
+        // A .org followed by a label places that label instead
+        let line_number = directive.line_number;
+        let continues = tokens
+            .iter()
+            .find(|token| token.line_number != line_number)
+            .is_some_and(|token| !is_label_boundary(token));
+
+        if !continues {
+            return;
+        }
+
+        while tokens.front().is_some_and(|token| token.line_number == line_number) {
+            tokens.pop_front();
+        }
+    }
+}
+
+/**
+ * A .org only applies to the label that follows it, so it cannot end a section
+ */
+fn expect_label_after_origin(path: &Path, lines: &[String], origin: &Option<Spanned<u16>>) -> diagnostic::Result<()> {
+    if let Some(origin) = origin {
+        return report_span_error(
+            Code::MisplacedDirective,
+            "Expected a label after .org directive!",
+            path,
+            lines,
+            origin.span,
+        )
+    }
+
+    Ok(())
+}
+
+/**
+ * Attributes that follow a label on the same line
+ */
+#[derive(Debug, Default)]
+pub struct LabelAttributes {
+    // Region set with `.section <region>`
+    pub placement: Option<Spanned<String>>,
+    // Most bytes the label may assemble to, set with `.size <bytes>`
+    pub size: Option<Spanned<u16>>,
+}
+
+/**
+ * Read the `.section <region>` and `.size <bytes>` attributes that follow a label on the same line
+ */
+fn parse_label_attributes(
+    path: &Path,
+    lines: &[String],
+    label_token: &Token,
+    tokens: &mut VecDeque<Token>,
+) -> diagnostic::Result<LabelAttributes> {
+    let mut attributes = LabelAttributes::default();
+
+    while let Some(Token {
+        token_type: TokenType::Directive(name),
+        line_number,
+        ..
+    }) = tokens.front()
+    {
+        if *line_number != label_token.line_number || !matches!(name.as_str(), "section" | "size") {
+            break;
+        }
+
+        let directive_token = tokens.pop_front().unwrap();
+        let name = directive_token.value[1..].to_owned();
+
+        let Some(value_token) =
+            tokens.pop_front_if(|token| token.line_number == label_token.line_number)
+        else {
+            return report_span_error(
+                Code::InvalidDirectiveArgument,
+                format!("Expected a value after .{name} label attribute!").as_str(),
+                path,
+                lines,
+                directive_token.span(),
+            )
+        };
+
+        let span = directive_token.span().to(&value_token.span());
+
+        let duplicate = match (name.as_str(), &value_token.token_type) {
+            ("section", TokenType::Identifier(region)) => attributes
+                .placement
+                .replace(Spanned {
+                    span,
+                    node: region.clone(),
+                })
+                .is_some(),
+            ("section", _) => return report_span_error(
+                Code::InvalidDirectiveArgument,
+                "Expected region name after .section directive!",
+                path,
+                lines,
+                span,
+            ),
+            ("size", TokenType::Binary(_) | TokenType::Decimal(_) | TokenType::Hex(_)) => {
+                attributes
+                    .size
+                    .replace(Spanned {
+                        span,
+                        node: value_token.parse_u16(path, lines)?,
+                    })
+                    .is_some()
+            }
+            _ => return report_span_error(
+                Code::InvalidDirectiveArgument,
+                "Expected number of bytes after .size directive!",
+                path,
+                lines,
+                span,
             ),
+        };
+
+        if duplicate {
+            return report_span_error(
+                Code::DuplicateDirective,
+                format!("Duplicate .{name} attribute on label!").as_str(),
+                path,
+                lines,
+                span,
+            )
         }
     }
 
-    ast
+    Ok(attributes)
 }
 
 /**
  * Read tokens to the end of the line for parsing
  */
-fn read_tokens_to_eol(tokens: &mut VecDeque<Token>) -> VecDeque<Token> {
+pub(crate) fn read_tokens_to_eol(tokens: &mut VecDeque<Token>) -> VecDeque<Token> {
     let mut line = VecDeque::new();
 
     if tokens.is_empty() {
@@ -1055,17 +3960,7 @@ fn read_tokens_to_label_or_eos(tokens: &mut VecDeque<Token>) -> VecDeque<Token>
         return result;
     }
 
-    while !tokens.is_empty()
-        && !match &tokens.front().unwrap().token_type {
-            TokenType::Directive(name) => match name.as_str() {
-                "text" => true,
-                "data" => true,
-                _ => false,
-            },
-            TokenType::Label(_) => true,
-            _ => false,
-        }
-    {
+    while !tokens.is_empty() && !is_label_boundary(tokens.front().unwrap()) {
         result.push_back(tokens.pop_front().unwrap());
     }
 
@@ -1076,18 +3971,21 @@ fn read_tokens_to_label_or_eos(tokens: &mut VecDeque<Token>) -> VecDeque<Token>
  * Split a token vector by the commas
  */
 fn split_tokens_by_commas(
-    path: &PathBuf,
-    lines: &Vec<String>,
+    path: &Path,
+    lines: &[String],
     tokens: &mut VecDeque<Token>,
-) -> VecDeque<VecDeque<Token>> {
+) -> diagnostic::Result<VecDeque<VecDeque<Token>>> {
     let mut result = VecDeque::new();
 
     if tokens.is_empty() {
-        return result;
+        return Ok(result);
     }
 
     let mut current_argument = VecDeque::new();
 
+    // Commas inside parentheses separate the parts of an argument, such as `($8000, %ecx)`
+    let mut depth = 0;
+
     // Loop through the tokens, and if we reach a comma,
     // push the current argument into result list,
     // else push it into the current argument
@@ -1095,11 +3993,20 @@ fn split_tokens_by_commas(
         let token = tokens.pop_front().unwrap();
 
         match &token.token_type {
-            TokenType::Comma => {
+            TokenType::OpenParenthesis => {
+                depth += 1;
+                current_argument.push_back(token);
+            }
+            TokenType::CloseParenthesis => {
+                depth -= 1;
+                current_argument.push_back(token);
+            }
+            TokenType::Comma if depth <= 0 => {
                 // Make sure there are not 2 commas in a row,
                 // a comma before the first argument, or a comma at the end of a line
                 if current_argument.is_empty() || tokens.is_empty() {
-                    report_error(
+                    return report_error(
+                        Code::UnexpectedToken,
                         "Unexpected argument separator `,`!",
                         path,
                         lines,
@@ -1123,5 +4030,50 @@ fn split_tokens_by_commas(
         result.push_back(current_argument);
     }
 
+    Ok(result)
+}
+
+/**
+ * Make sure nothing follows the value of a constant directive
+ */
+fn expect_end_of_constant(path: &Path, lines: &[String], constant_line: &VecDeque<Token>, directive: &str) -> diagnostic::Result<()> {
+    if let Some(illegal_token) = constant_line.front() {
+        return report_error(
+            Code::InvalidDirectiveArgument,
+            format!("Unexpected token `{}` after .{directive} value!", illegal_token.value).as_str(),
+            path,
+            lines,
+            illegal_token.line_number,
+            illegal_token.column_start,
+            illegal_token.column_end,
+        )
+    }
+
+    Ok(())
+}
+
+/**
+ * Replace the escape sequences in a string literal with the characters they represent
+ */
+fn unescape_string(string: &str) -> String {
+    let mut result = String::new();
+    let mut chars = string.chars();
+
+    while let Some(character) = chars.next() {
+        if character != '\\' {
+            result.push(character);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('0') => result.push('\0'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+
     result
 }
@@ -0,0 +1,486 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+};
+
+use crate::{
+    code::Code,
+    diagnostic,
+    expr::{Context, Expression},
+    parse::{self, Parsable},
+    report_span_error, report_span_error_with_help,
+    resolve::Section,
+    token::{Span, Token, TokenType},
+};
+
+/**
+ * State of a single `.if` block while its lines are being read
+ */
+struct Condition {
+    span: Span,
+    // If the lines in the current branch are being assembled
+    active: bool,
+    // If any branch of this block has been assembled yet
+    taken: bool,
+    seen_else: bool,
+}
+
+struct Preprocessor<'a> {
+    path: &'a Path,
+    lines: &'a [String],
+    defines: &'a HashMap<String, Option<u16>>,
+    relax: bool,
+    output: VecDeque<Token>,
+    section: Option<Section>,
+    // Names used in the conditions of `.if`, which are gone once the program is built
+    referenced: HashSet<String>,
+}
+
+impl Context for Preprocessor<'_> {
+    fn is_defined(&self, name: &str) -> bool {
+        self.defines.contains_key(name)
+    }
+
+    fn value_of(&self, name: &str) -> Option<u16> {
+        self.defines.get(name).copied().flatten()
+    }
+
+    fn size_of(&self, label: &str, span: Span) -> diagnostic::Result<Option<u32>> {
+        let Some((mut label_tokens, section)) = self.label_tokens(label) else {
+            return Ok(None);
+        };
+
+        // Short branches are only made long once the program is laid out, after `.if` has already used the size
+        if self.relax
+            && parse::label_has_branch(
+                self.path,
+                self.lines,
+                &mut label_tokens.clone(),
+                section.clone(),
+            )?
+        {
+            return report_span_error_with_help(
+                Code::InvalidExpression,
+                format!("The size of {} is not known in `.if`, its branches can still grow!", source_name(label)).as_str(),
+                Some("Check the size with `.assert` instead, or assemble with `--no-relax` to keep every branch short"),
+                self.path,
+                self.lines,
+                span,
+            );
+        }
+
+        Ok(Some(parse::label_size(
+            self.path,
+            self.lines,
+            &mut label_tokens,
+            section,
+        )?))
+    }
+
+    fn length_of(&self, label: &str) -> diagnostic::Result<Option<u32>> {
+        let Some((mut label_tokens, section)) = self.label_tokens(label) else {
+            return Ok(None);
+        };
+
+        Ok(Some(parse::label_length(
+            self.path,
+            self.lines,
+            &mut label_tokens,
+            section,
+        )?))
+    }
+
+    fn section(&self) -> Option<Section> {
+        self.section.clone()
+    }
+
+    fn names(&self) -> Vec<&str> {
+        let labels = self
+            .output
+            .iter()
+            .filter_map(|token| match &token.token_type {
+                TokenType::Label(name) => Some(name.as_str()),
+                _ => None,
+            });
+
+        labels
+            .chain(self.defines.keys().map(String::as_str))
+            .collect()
+    }
+}
+
+impl Preprocessor<'_> {
+    /**
+     * The tokens of a label declared before the current line, along with the section it is in
+     */
+    fn label_tokens(&self, label: &str) -> Option<(VecDeque<Token>, Section)> {
+        let start = self
+            .output
+            .iter()
+            .position(|token| token.token_type == TokenType::Label(label.to_owned()))?;
+
+        // Find the section the label was declared in
+        let section = (0..start)
+            .rev()
+            .find_map(|index| section_started_by(&self.output, index))?;
+
+        // Attributes on the line of the label, such as `.section <region>`, are part of it
+        let label_line = self.output[start].line_number;
+
+        let mut label_tokens: VecDeque<_> = self
+            .output
+            .range(start..)
+            .enumerate()
+            .take_while(|(i, token)| {
+                *i == 0 || token.line_number == label_line || !parse::is_label_boundary(token)
+            })
+            .map(|(_, token)| token.clone())
+            .collect();
+
+        // Assertions and messages are only evaluated after assembly and take up no space
+        parse::take_directive_lines(&mut label_tokens, parse::DEFERRED_DIRECTIVES);
+
+        Some((label_tokens, section))
+    }
+}
+
+/**
+ * The section started by the token at `index`, if it is a section directive.
+ *
+ * `.section <name>` only starts a section on a line of its own, after a label it places the label in a region.
+ */
+fn section_started_by(tokens: &VecDeque<Token>, index: usize) -> Option<Section> {
+    let TokenType::Directive(name) = &tokens[index].token_type else {
+        return None;
+    };
+
+    match name.as_str() {
+        "text" => Some(Section::Text),
+        "data" => Some(Section::Data),
+        "bss" => Some(Section::Bss),
+        "section" if index == 0 || tokens[index - 1].line_number != tokens[index].line_number => {
+            let name = tokens.get(index + 1)?.value.clone();
+
+            Some(Section::Named(name))
+        }
+        _ => None,
+    }
+}
+
+/**
+ * Run the conditional assembly directives (`.if`, `.ifdef`, `.ifndef`, `.else` and `.endif`)
+ * and return only the tokens that should be assembled, along with the names used by the conditions
+ *
+ * With `relax` on, branches can grow after this runs, so `sizeof` is an error on labels that have them.
+ */
+pub fn preprocess(
+    path: &Path,
+    lines: &[String],
+    tokens: &mut VecDeque<Token>,
+    defines: &HashMap<String, Option<u16>>,
+    relax: bool,
+) -> diagnostic::Result<(VecDeque<Token>, HashSet<String>)> {
+    let mut preprocessor = Preprocessor {
+        path,
+        lines,
+        defines,
+        relax,
+        output: VecDeque::new(),
+        section: None,
+        referenced: HashSet::new(),
+    };
+
+    let mut conditions: Vec<Condition> = Vec::new();
+
+    while !tokens.is_empty() {
+        let mut line = parse::read_tokens_to_eol(tokens);
+
+        let active = conditions.iter().all(|condition| condition.active);
+
+        let directive = match &line.front().unwrap().token_type {
+            TokenType::Directive(name) => name.clone(),
+            _ => String::new(),
+        };
+
+        match directive.as_str() {
+            "if" => {
+                let directive_token = line.pop_front().unwrap();
+
+                if line.is_empty() {
+                    return report_span_error(
+                        Code::InvalidDirectiveArgument,
+                        "Expected a condition after .if directive!",
+                        path,
+                        lines,
+                        directive_token.span(),
+                    );
+                }
+
+                // Conditions inside of skipped blocks are never evaluated
+                let result = active && {
+                    let expression = Expression::parse(path, lines, &mut line)?;
+                    let value = expression.evaluate_number(path, lines, &preprocessor)?;
+
+                    preprocessor
+                        .referenced
+                        .extend(expression.identifiers().into_iter().map(str::to_owned));
+
+                    value != 0
+                };
+
+                conditions.push(Condition {
+                    span: directive_token.span(),
+                    active: result,
+                    taken: result,
+                    seen_else: false,
+                });
+            }
+            "ifdef" | "ifndef" => {
+                let directive_token = line.pop_front().unwrap();
+
+                let name = match (line.pop_front(), line.pop_front()) {
+                    (
+                        Some(Token {
+                            token_type: TokenType::Identifier(name),
+                            ..
+                        }),
+                        None,
+                    ) => name,
+                    _ => return report_span_error(
+                        Code::InvalidDirectiveArgument,
+                        format!("Expected a single define name after .{directive} directive! (e.g. `.{directive} DEBUG`)").as_str(),
+                        path,
+                        lines,
+                        directive_token.span(),
+                    ),
+                };
+
+                let result = active && preprocessor.is_defined(&name) == (directive == "ifdef");
+
+                conditions.push(Condition {
+                    span: directive_token.span(),
+                    active: result,
+                    taken: result,
+                    seen_else: false,
+                });
+            }
+            "else" => {
+                let directive_token = line.pop_front().unwrap();
+                expect_end_of_line(path, lines, &line, &directive)?;
+
+                let Some(condition) = conditions.last_mut() else {
+                    return report_span_error(
+                        Code::UnbalancedBlock,
+                        "Unexpected .else directive without a matching .if!",
+                        path,
+                        lines,
+                        directive_token.span(),
+                    );
+                };
+
+                if condition.seen_else {
+                    return report_span_error(
+                        Code::DuplicateDirective,
+                        "Duplicate .else directive in .if block!",
+                        path,
+                        lines,
+                        directive_token.span(),
+                    );
+                }
+
+                condition.seen_else = true;
+                condition.active = !condition.taken;
+                condition.taken = true;
+            }
+            "endif" => {
+                let directive_token = line.pop_front().unwrap();
+                expect_end_of_line(path, lines, &line, &directive)?;
+
+                if conditions.pop().is_none() {
+                    return report_span_error(
+                        Code::UnbalancedBlock,
+                        "Unexpected .endif directive without a matching .if!",
+                        path,
+                        lines,
+                        directive_token.span(),
+                    );
+                }
+            }
+            _ if active => {
+                let start = preprocessor.output.len();
+                preprocessor.output.extend(line);
+
+                if let Some(section) = section_started_by(&preprocessor.output, start) {
+                    preprocessor.section = Some(section);
+                }
+            }
+            // Skip lines in blocks whose condition is false
+            _ => (),
+        }
+    }
+
+    if let Some(condition) = conditions.last() {
+        return report_span_error(
+            Code::UnbalancedBlock,
+            "Expected .endif directive to close .if block!",
+            path,
+            lines,
+            condition.span,
+        );
+    }
+
+    Ok((preprocessor.output, preprocessor.referenced))
+}
+
+/**
+ * Give local and numeric labels names that are unique in the program.
+ *
+ * Local labels (`.name:`) and the references to them are renamed to `<label>.name`, where `<label>` is the
+ * closest label before them in the same section, so every label can have its own `.loop` or `.done`.
+ * Numeric labels (`1:`) and anonymous labels (`:`) can be declared any number of times, so they are numbered
+ * in the order they are declared (`1@0`, `1@1`, ...) and `1f`/`:+` and `1b`/`:-` are renamed to the next and
+ * previous one.
+ */
+pub fn scope_local_labels(
+    path: &Path,
+    lines: &[String],
+    tokens: &mut VecDeque<Token>,
+) -> diagnostic::Result<()> {
+    let mut parent: Option<String> = None;
+
+    let is_numeric = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_ascii_digit());
+    let is_reusable = |name: &str| name == ":" || is_numeric(name);
+
+    // How many times each numeric label is declared in total, and so far
+    let mut totals: HashMap<String, usize> = HashMap::new();
+    let mut declared: HashMap<String, usize> = HashMap::new();
+
+    for token in tokens.iter() {
+        if let TokenType::Label(name) = &token.token_type {
+            if is_reusable(name) {
+                *totals.entry(name.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    for index in 0..tokens.len() {
+        if section_started_by(tokens, index).is_some() {
+            parent = None;
+        }
+
+        let span = tokens[index].span();
+        let is_declaration = matches!(tokens[index].token_type, TokenType::Label(_));
+
+        let (TokenType::Label(name) | TokenType::Identifier(name)) = &mut tokens[index].token_type
+        else {
+            continue;
+        };
+
+        if is_declaration && is_reusable(name) {
+            let count = declared.entry(name.clone()).or_default();
+            *name = format!("{name}@{count}");
+            *count += 1;
+
+            continue;
+        }
+
+        // `1f` and `:+` refer forwards, `1b` and `:-` backwards
+        let reference = match name.split_at_checked(name.len().saturating_sub(1)) {
+            Some((":", "+")) => Some((":", true, "anonymous label `:`".to_owned())),
+            Some((":", "-")) => Some((":", false, "anonymous label `:`".to_owned())),
+            Some((number, "f")) if is_numeric(number) => {
+                Some((number, true, format!("numeric label `{number}:`")))
+            }
+            Some((number, "b")) if is_numeric(number) => {
+                Some((number, false, format!("numeric label `{number}:`")))
+            }
+            _ => None,
+        };
+
+        if let Some((label, forwards, description)) = reference {
+            let count = declared.get(label).copied().unwrap_or(0);
+            let total = totals.get(label).copied().unwrap_or(0);
+
+            let target = if forwards {
+                (count < total).then_some(count)
+            } else {
+                count.checked_sub(1)
+            };
+
+            let Some(target) = target else {
+                return report_span_error(
+                    Code::UnknownLabel,
+                    format!(
+                        "There is no {description} {} this reference!",
+                        if forwards { "after" } else { "before" }
+                    )
+                    .as_str(),
+                    path,
+                    lines,
+                    span,
+                );
+            };
+
+            *name = format!("{label}@{target}");
+
+            continue;
+        }
+
+        let Some(local) = name.strip_prefix('.') else {
+            if is_declaration {
+                parent = Some(name.clone());
+            }
+
+            continue;
+        };
+
+        let Some(parent) = &parent else {
+            return report_span_error(
+                Code::MisplacedDirective,
+                format!("Local label `.{local}` is not inside of a label it can belong to!")
+                    .as_str(),
+                path,
+                lines,
+                span,
+            );
+        };
+
+        *name = format!("{parent}.{local}");
+    }
+
+    Ok(())
+}
+
+/**
+ * A label name as it is written in the source, in backticks for messages. Local labels are renamed to
+ * `parent.local` by `scope_local_labels` and are shown as `.local` in `parent`.
+ */
+pub(crate) fn source_name(name: &str) -> String {
+    match name.split_once('.') {
+        Some((parent, local)) => format!("`.{local}` in `{parent}`"),
+        None => format!("`{name}`"),
+    }
+}
+
+fn expect_end_of_line(
+    path: &Path,
+    lines: &[String],
+    line: &VecDeque<Token>,
+    directive: &str,
+) -> diagnostic::Result<()> {
+    if let Some(illegal_token) = line.front() {
+        return report_span_error(
+            Code::InvalidDirectiveArgument,
+            format!(
+                "Unexpected token `{}` after .{directive} directive!",
+                illegal_token.value
+            )
+            .as_str(),
+            path,
+            lines,
+            illegal_token.span(),
+        );
+    }
+
+    Ok(())
+}
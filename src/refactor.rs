@@ -0,0 +1,261 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+
+use crate::{
+    code::Code,
+    cst::{SyntaxKind, SyntaxTree},
+    diagnostic::{Diagnostic, Severity},
+    io_error,
+    token::{Span, TokenType},
+};
+
+/**
+ * A place in a syntax tree where a label is declared or referenced
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LabelReference {
+    pub span: Span,
+    pub declaration: bool,
+    // Position of the token in its line of the tree
+    line: usize,
+    token: usize,
+}
+
+/**
+ * Find every declaration of and reference to a label.
+ *
+ * Labels are referenced by `.entry <label>`, `.global` and `.extern` lists, `.word` and `.byte` values, immediate, label and indexed operands, `.assert` and `.print` expressions, `sizeof(<label>)` and `lengthof(<label>)`. Other identifiers that happen
+ * to share the name, such as region names, are not references.
+ */
+pub fn find_label_references(tree: &SyntaxTree, name: &str) -> Vec<LabelReference> {
+    let mut references = Vec::new();
+
+    for (line_index, line) in tree.lines.iter().enumerate() {
+        // Indices of the tokens the parser sees, so patterns can skip over whitespace
+        let significant: Vec<_> = line
+            .tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, token)| !token.kind.is_trivia())
+            .map(|(index, _)| index)
+            .collect();
+
+        let kind = |position: usize| match &line.tokens[significant[position]].kind {
+            SyntaxKind::Token(token_type) => token_type,
+            _ => unreachable!(),
+        };
+
+        // Every identifier in a .global or .extern line is a label name
+        let is_symbol_list = !significant.is_empty()
+            && matches!(kind(0), TokenType::Directive(directive) if directive == "global" || directive == "extern");
+
+        // Identifiers in .assert and .print expressions refer to label addresses, unless they name a function
+        let is_deferred = !significant.is_empty()
+            && matches!(kind(0), TokenType::Directive(directive) if matches!(directive.as_str(), "assert" | "print" | "info"));
+
+        // Identifiers in .word and .byte values and in immediates are computed from label addresses
+        let value_position = (0..significant.len()).find(|&position| match kind(position) {
+            TokenType::Directive(directive) => directive == "word" || directive == "byte",
+            token_type => token_type == &TokenType::Immediate,
+        });
+
+        // A lone identifier as an instruction operand is the address of a label, as in `jmp main`
+        let is_instruction =
+            !significant.is_empty() && matches!(kind(0), TokenType::Instruction(_));
+
+        for (position, &index) in significant.iter().enumerate() {
+            let declaration = match kind(position) {
+                TokenType::Label(label) if label == name => true,
+                TokenType::Identifier(identifier) if identifier == name && position > 0 => {
+                    let is_entry = matches!(kind(position - 1), TokenType::Directive(directive) if directive == "entry");
+
+                    let is_sizeof = position > 1
+                        && kind(position - 1) == &TokenType::OpenParenthesis
+                        && matches!(kind(position - 2), TokenType::Identifier(function) if function == "sizeof" || function == "lengthof");
+
+                    let is_call = position + 1 < significant.len()
+                        && kind(position + 1) == &TokenType::OpenParenthesis;
+                    let is_operand = is_deferred && !is_call;
+                    let is_value = value_position.is_some_and(|start| position > start) && !is_call;
+
+                    // The base of an indexed address is one too, as in `table(%ecx)` or `(table, %ecx)`
+                    let is_indexed = position + 2 < significant.len()
+                        && kind(position + 1) == &TokenType::OpenParenthesis
+                        && matches!(kind(position + 2), TokenType::Register(_));
+
+                    let is_target = is_instruction
+                        && matches!(
+                            kind(position - 1),
+                            TokenType::Instruction(_)
+                                | TokenType::Comma
+                                | TokenType::OpenParenthesis
+                        )
+                        && (position + 1 == significant.len()
+                            || kind(position + 1) == &TokenType::Comma
+                            || is_indexed);
+
+                    if !is_entry
+                        && !is_sizeof
+                        && !is_symbol_list
+                        && !is_operand
+                        && !is_value
+                        && !is_target
+                    {
+                        continue;
+                    }
+
+                    false
+                }
+                _ => continue,
+            };
+
+            references.push(LabelReference {
+                span: line.tokens[index].span,
+                declaration,
+                line: line_index,
+                token: index,
+            });
+        }
+    }
+
+    references
+}
+
+/**
+ * Checks if a name can be used for a label
+ */
+pub fn is_valid_label_name(name: &str) -> bool {
+    let re = Regex::new(r"^[a-zA-Z_][a-zA-Z0-9_]*$").unwrap();
+    re.is_match(name)
+}
+
+/**
+ * Rename a label in a syntax tree, returning how many tokens were changed
+ */
+pub fn rename_label(tree: &mut SyntaxTree, old_name: &str, new_name: &str) -> usize {
+    let references = find_label_references(tree, old_name);
+
+    for reference in &references {
+        let token = &mut tree.lines[reference.line].tokens[reference.token];
+
+        if reference.declaration {
+            token.kind = SyntaxKind::Token(TokenType::Label(new_name.to_owned()));
+            token.text = format!("{new_name}:");
+        } else {
+            token.kind = SyntaxKind::Token(TokenType::Identifier(new_name.to_owned()));
+            token.text = new_name.to_owned();
+        }
+    }
+
+    references.len()
+}
+
+/**
+ * Rename a label across every file of a project and write the changed files back.
+ *
+ * Labels are global, so the new name must not already be declared in any of the files.
+ * Returns the number of occurrences renamed in each file that changed, or why nothing was renamed. Files are only
+ * written once all of them are checked, so only a file that cannot be written leaves the ones after it unchanged.
+ */
+pub fn rename_label_in_files(
+    old_name: &str,
+    new_name: &str,
+    paths: &[PathBuf],
+) -> Result<Vec<(PathBuf, usize)>, Vec<Diagnostic>> {
+    if !is_valid_label_name(new_name) {
+        return Err(vec![Diagnostic::new(
+            Severity::Error,
+            Code::InvalidLabelName,
+            format!("`{new_name}` is not a valid label name! Labels start with a letter or `_` followed by letters, digits or `_`").as_str(),
+        )]);
+    }
+
+    if old_name == new_name {
+        return Err(vec![Diagnostic::new(
+            Severity::Error,
+            Code::UnknownLabel,
+            format!("Label `{old_name}` already has that name!").as_str(),
+        )]);
+    }
+
+    let mut files = Vec::new();
+
+    for path in paths {
+        let source = fs::read_to_string(path).map_err(|err| {
+            vec![io_error(
+                Code::UnreadableFile,
+                "read source file",
+                path,
+                &err,
+            )]
+        })?;
+
+        let tree = SyntaxTree::parse(path, &source)?;
+
+        files.push((path, source, tree));
+    }
+
+    let declared = files.iter().any(|(_, _, tree)| {
+        find_label_references(tree, old_name)
+            .iter()
+            .any(|reference| reference.declaration)
+    });
+
+    if !declared {
+        return Err(vec![Diagnostic::new(
+            Severity::Error,
+            Code::UnknownLabel,
+            format!("Label `{old_name}` is not declared in any of the given files!").as_str(),
+        )]);
+    }
+
+    // Renaming onto an existing label would merge two symbols
+    for (path, source, tree) in &files {
+        if let Some(existing) = find_label_references(tree, new_name)
+            .iter()
+            .find(|reference| reference.declaration)
+        {
+            let lines: Vec<_> = source.lines().map(|string| string.to_owned()).collect();
+
+            return Err(vec![Diagnostic::at(
+                Severity::Error,
+                Code::DuplicateLabel,
+                format!("Cannot rename `{old_name}` to `{new_name}`, a label named `{new_name}` is already declared here!").as_str(),
+                None,
+                path,
+                &lines,
+                existing.span,
+            )]);
+        }
+    }
+
+    let mut renamed = Vec::new();
+
+    for (path, _, mut tree) in files {
+        let count = rename_label(&mut tree, old_name, new_name);
+
+        if count == 0 {
+            continue;
+        }
+
+        write_source(path, tree.text())?;
+        renamed.push((path.clone(), count));
+    }
+
+    Ok(renamed)
+}
+
+fn write_source(path: &Path, source: String) -> Result<(), Vec<Diagnostic>> {
+    fs::write(path, source).map_err(|err| {
+        vec![io_error(
+            Code::UnwritableFile,
+            "write source file",
+            path,
+            &err,
+        )]
+    })
+}
@@ -0,0 +1,1093 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    code::Code,
+    diagnostic::{self, Diagnostics, Warning},
+    expr::{Context, Expression, Value},
+    parse::{self, ConstantLabelType, Continuation, Program, Spanned, SubroutineItem},
+    preprocess, report_error_with_note, report_span_error, report_span_error_with_help,
+    report_span_error_with_label, suggest,
+    token::Span,
+};
+
+/**
+ * The section a symbol was defined in
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Section {
+    Text,
+    Data,
+    // Zero-initialized RAM that takes up no space in the image
+    Bss,
+    // Code in a section declared with `.section <name>`
+    Named(String),
+}
+
+impl Section {
+    pub fn name(&self) -> &str {
+        match self {
+            Section::Text => "text",
+            Section::Data => "data",
+            Section::Bss => "bss",
+            Section::Named(name) => name,
+        }
+    }
+
+    /**
+     * Checks if the section holds subroutine labels, which is the text section and every named section
+     */
+    pub fn is_code(&self) -> bool {
+        matches!(self, Section::Text | Section::Named(_))
+    }
+}
+
+/**
+ * A label along with the final address it was placed at
+ */
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u16,
+    pub section: Section,
+    pub span: Span,
+    // Number of bytes the label assembles to
+    pub size: u32,
+    // Exported to other files with .global
+    pub global: bool,
+}
+
+/**
+ * Where every section and label of a program ends up in memory
+ */
+#[derive(Debug)]
+pub struct Layout {
+    pub symbols: Vec<Symbol>,
+    // Address of the .entry label
+    pub entry: Option<u16>,
+    // Labels declared with .extern, which are defined in another file
+    pub externs: Vec<String>,
+}
+
+impl Layout {
+    pub fn find_symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /**
+     * Names of the labels written in the source, leaving out the ones made up for numeric and anonymous labels
+     */
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.symbols
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .filter(|name| !name.contains('@'))
+    }
+}
+
+/**
+ * Assign an address to every label in the program.
+ *
+ * The text section is placed at `text_org` and the data section at `data_org`, or directly after
+ * the text section if it is not given. Sections declared with `.section <name>` follow the text section, or start at their
+ * `org` if they have one. Labels placed with `.section <region>` are laid out one after another from the address of their region instead.
+ */
+pub fn resolve_labels(
+    path: &Path,
+    lines: &[String],
+    program: &Program,
+    text_org: u16,
+    data_org: Option<u16>,
+) -> diagnostic::Result<Layout> {
+    let mut symbols = Vec::new();
+    let mut ranges = Vec::new();
+
+    // Next free address of every region
+    let mut regions: HashMap<&str, u32> = program
+        .regions
+        .iter()
+        .map(|region| (region.name.as_str(), region.address as u32))
+        .collect();
+
+    let mut address = text_org as u32;
+
+    // Named sections follow the text section, unless they have their own address
+    let code_sections = program
+        .text
+        .iter()
+        .map(|text| (Section::Text, None, text))
+        .chain(program.sections.iter().map(|section| {
+            (
+                Section::Named(section.name.clone()),
+                section.origin,
+                &section.text,
+            )
+        }));
+
+    for (section, origin, text) in code_sections {
+        // Next free address of a section with an org, which leaves the address of the sections after it alone
+        let mut origin_address = origin.unwrap_or(0) as u32;
+
+        let section_address = match origin {
+            Some(_) => &mut origin_address,
+            None => &mut address,
+        };
+
+        for label in &text.labels {
+            let cursor = label_cursor(
+                path,
+                lines,
+                &mut regions,
+                section_address,
+                &label.attributes.placement,
+                &label.origin,
+            )?;
+            let start = *cursor;
+
+            symbols.push(place_symbol(
+                path,
+                lines,
+                &label.name,
+                start,
+                section.clone(),
+                label.span,
+            )?);
+
+            let size = place_contents(
+                path,
+                lines,
+                cursor,
+                label.contents.iter().map(|item| item.node.size()),
+                &label.continuations,
+                label.span,
+                symbols.len() - 1,
+                &mut ranges,
+            )?;
+
+            symbols.last_mut().unwrap().size = size;
+
+            check_size_budget(path, lines, &label.name, &label.attributes.size, size)?;
+        }
+    }
+
+    // The data section directly follows the text section unless it has its own address
+    if let Some(data_org) = data_org {
+        address = data_org as u32;
+    }
+
+    // .bss directly follows .data, both only have constant labels
+    for (section, constants) in [(Section::Data, &program.data), (Section::Bss, &program.bss)] {
+        let Some(constants) = constants else {
+            continue;
+        };
+
+        for label in &constants.labels {
+            let cursor = label_cursor(
+                path,
+                lines,
+                &mut regions,
+                &mut address,
+                &label.attributes.placement,
+                &label.origin,
+            )?;
+            let start = *cursor;
+
+            symbols.push(place_symbol(
+                path,
+                lines,
+                &label.name,
+                start,
+                section.clone(),
+                label.span,
+            )?);
+
+            let size = place_contents(
+                path,
+                lines,
+                cursor,
+                label.constants.iter().map(|constant| constant.node.size()),
+                &label.continuations,
+                label.span,
+                symbols.len() - 1,
+                &mut ranges,
+            )?;
+
+            symbols.last_mut().unwrap().size = size;
+
+            check_size_budget(path, lines, &label.name, &label.attributes.size, size)?;
+        }
+    }
+
+    check_overlaps(path, lines, &symbols, ranges)?;
+
+    let externs = resolve_visibility(path, lines, program, &mut symbols)?;
+
+    Ok(Layout {
+        symbols,
+        entry: None,
+        externs,
+    })
+}
+
+/**
+ * Lay out the program, choosing the short or long form of every conditional branch.
+ *
+ * Branches start out short. Making one long moves every label after it, which can put other
+ * branches out of range, so the layout is repeated until no branch has to grow. With `relax` off
+ * branches are always short, and one that cannot reach its target is an error.
+ */
+pub fn relax_branches(
+    path: &Path,
+    lines: &[String],
+    program: &mut Program,
+    text_org: u16,
+    data_org: Option<u16>,
+    relax: bool,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<Layout> {
+    loop {
+        let mut layout = resolve_labels(path, lines, program, text_org, data_org)?;
+        let mut changed = false;
+
+        // Symbols are in the same order as the labels, text labels first and then those of named sections
+        let code = program
+            .text
+            .iter_mut()
+            .chain(program.sections.iter_mut().map(|section| &mut section.text))
+            .flat_map(|text| &mut text.labels);
+
+        for (label, symbol) in code.zip(&layout.symbols) {
+            let mut address = symbol.address;
+
+            for (index, item) in label.contents.iter_mut().enumerate() {
+                if let Some(origin) = parse::continuation_at(&label.continuations, index) {
+                    address = origin.node;
+                }
+
+                let size = item.node.size();
+
+                if let SubroutineItem::Instruction { instruction, .. } = &mut item.node {
+                    if let Some(branch) = instruction.branch_mut().filter(|branch| !branch.long) {
+                        // Unknown labels are reported when the branch target is evaluated
+                        if let Some(target) = layout.find_symbol(&branch.label) {
+                            let displacement =
+                                target.address as i32 - (address as i32 + size as i32);
+
+                            if !(-128..=127).contains(&displacement) {
+                                if !relax {
+                                    return report_error_with_note(
+                                        Code::BranchOutOfRange,
+                                        format!("Branch target `{}` is {displacement} bytes away, out of range for a short branch!", branch.label).as_str(),
+                                        Some("Short branches reach -128 to 127 bytes from the end of the branch, assemble without `--no-relax` to use the long form"),
+                                        path,
+                                        lines,
+                                        item.span.line_number,
+                                        item.span.column_start,
+                                        item.span.column_end,
+                                    );
+                                }
+
+                                branch.long = true;
+                                changed = true;
+                            }
+                        }
+                    }
+                }
+
+                address = address.wrapping_add(size);
+            }
+        }
+
+        if !changed {
+            // The entry point is only looked up once every label has its final address
+            if let Some(entry) = &program.entry {
+                layout.entry = diagnostics.recover(resolve_entry(path, lines, &layout, entry))?;
+            }
+
+            return Ok(layout);
+        }
+    }
+}
+
+/**
+ * What `.assert` conditions can refer to: the address and size of every label, and variables defined on the command line
+ */
+struct LayoutContext<'a> {
+    program: &'a Program,
+    layout: &'a Layout,
+    defines: &'a HashMap<String, Option<u16>>,
+}
+
+impl Context for LayoutContext<'_> {
+    fn is_defined(&self, name: &str) -> bool {
+        self.defines.contains_key(name)
+    }
+
+    fn value_of(&self, name: &str) -> Option<u16> {
+        match self.layout.find_symbol(name) {
+            Some(symbol) => Some(symbol.address),
+            None => self.defines.get(name).copied().flatten(),
+        }
+    }
+
+    fn size_of(&self, label: &str, _span: Span) -> diagnostic::Result<Option<u32>> {
+        Ok(self.layout.find_symbol(label).map(|symbol| symbol.size))
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.layout
+            .names()
+            .chain(self.defines.keys().map(String::as_str))
+            .collect()
+    }
+
+    fn length_of(&self, label: &str) -> diagnostic::Result<Option<u32>> {
+        let program = self.program;
+
+        let code = program
+            .text
+            .iter()
+            .chain(program.sections.iter().map(|section| &section.text))
+            .flat_map(|text| &text.labels)
+            .find(|subroutine| subroutine.name == label)
+            .map(|subroutine| {
+                // Like sizeof, only what comes before a .org in the middle of the label is counted
+                subroutine
+                    .contents
+                    .iter()
+                    .take(leading_items(&subroutine.continuations))
+                    .filter_map(|item| match &item.node {
+                        SubroutineItem::Constant(constant) => Some(constant.length() as u32),
+                        SubroutineItem::Instruction { .. } => None,
+                    })
+                    .sum()
+            });
+
+        Ok(code.or_else(|| {
+            program
+                .data
+                .iter()
+                .chain(&program.bss)
+                .flat_map(|data| &data.labels)
+                .find(|constant_label| constant_label.name == label)
+                .map(|constant_label| {
+                    constant_label
+                        .constants
+                        .iter()
+                        .take(leading_items(&constant_label.continuations))
+                        .map(|constant| constant.node.length() as u32)
+                        .sum()
+                })
+        }))
+    }
+
+    fn section(&self) -> Option<Section> {
+        None
+    }
+}
+
+/**
+ * Report every label declared with a name that was already taken, in any section, along with its first declaration
+ */
+pub fn check_duplicate_labels(
+    path: &Path,
+    lines: &[String],
+    program: &Program,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<()> {
+    let code = program
+        .text
+        .iter()
+        .chain(program.sections.iter().map(|section| &section.text))
+        .flat_map(|text| &text.labels)
+        .map(|label| (label.name.as_str(), label.span));
+
+    let data = program
+        .data
+        .iter()
+        .chain(&program.bss)
+        .flat_map(|data| &data.labels)
+        .map(|label| (label.name.as_str(), label.span));
+
+    let mut declared: Vec<(&str, Span)> = code.chain(data).collect();
+
+    // Report duplicates in the order they appear in the source, no matter which section they are in
+    declared.sort_by_key(|(_, span)| (span.line_number, span.column_start));
+
+    let mut first: HashMap<&str, Span> = HashMap::new();
+
+    for (name, span) in declared {
+        let Some(first_span) = first.get(name) else {
+            first.insert(name, span);
+            continue;
+        };
+
+        diagnostics.recover(report_span_error_with_label::<()>(
+            Code::DuplicateLabel,
+            format!(
+                "Label {} is already declared!",
+                preprocess::source_name(name)
+            )
+            .as_str(),
+            path,
+            lines,
+            span,
+            "first declared here",
+            *first_span,
+        ))?;
+    }
+
+    Ok(())
+}
+
+/**
+ * Report every `.assert` whose condition is false once every label has an address, with its message
+ */
+pub fn check_assertions(
+    path: &Path,
+    lines: &[String],
+    program: &Program,
+    layout: &Layout,
+    defines: &HashMap<String, Option<u16>>,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<()> {
+    let context = LayoutContext {
+        program,
+        layout,
+        defines,
+    };
+
+    // Every failed assertion and unknown name is reported, not only the first
+    for assertion in &program.assertions {
+        let checked = (|| {
+            if assertion.condition.evaluate_number(path, lines, &context)? != 0 {
+                return Ok(());
+            }
+
+            let message = match &assertion.message {
+                Some(message) => format!("Assertion failed: {}", message.node),
+                None => "Assertion failed!".to_owned(),
+            };
+
+            let note = describe_condition(path, lines, &assertion.condition, &context)?;
+            let span = assertion.condition.span();
+
+            report_error_with_note(
+                Code::AssertionFailed,
+                message.as_str(),
+                note.as_deref(),
+                path,
+                lines,
+                span.line_number,
+                span.column_start,
+                span.column_end,
+            )
+        })();
+
+        diagnostics.recover(checked)?;
+    }
+
+    Ok(())
+}
+
+/**
+ * The text of every `.print` and `.info` directive, in the order they appear in the source
+ */
+pub fn evaluate_messages(
+    path: &Path,
+    lines: &[String],
+    program: &Program,
+    layout: &Layout,
+    defines: &HashMap<String, Option<u16>>,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<Vec<Spanned<String>>> {
+    let context = LayoutContext {
+        program,
+        layout,
+        defines,
+    };
+
+    let mut messages = Vec::new();
+
+    for message in &program.messages {
+        let values: diagnostic::Result<Vec<_>> = message
+            .arguments
+            .iter()
+            .map(|argument| {
+                Ok(match argument.evaluate(path, lines, &context)? {
+                    Value::String(string) => string,
+                    Value::Number(number @ 0..=0xFFFF) => format!("{number} (${number:04X})"),
+                    Value::Number(number) => number.to_string(),
+                })
+            })
+            .collect();
+
+        if let Some(values) = diagnostics.recover(values)? {
+            messages.push(Spanned {
+                span: message.span,
+                node: values.join(" "),
+            });
+        }
+    }
+
+    Ok(messages)
+}
+
+/**
+ * Compute every value that depends on where labels were placed, in the order `compile_program` encodes them.
+ *
+ * These are `.word` and `.byte` values and immediate operands that are expressions rather than a single number.
+ */
+pub fn evaluate_values(
+    path: &Path,
+    lines: &[String],
+    program: &Program,
+    layout: &Layout,
+    defines: &HashMap<String, Option<u16>>,
+    diagnostics: &mut Diagnostics,
+) -> diagnostic::Result<Vec<u16>> {
+    let context = LayoutContext {
+        program,
+        layout,
+        defines,
+    };
+
+    let code = program
+        .text
+        .iter()
+        .chain(program.sections.iter().map(|section| &section.text))
+        .flat_map(|text| &text.labels)
+        .flat_map(|label| &label.contents)
+        .flat_map(|item| match &item.node {
+            SubroutineItem::Instruction {
+                instruction,
+                deferred,
+            } => deferred
+                .iter()
+                .map(|operand| (&operand.value, instruction.immediate_size()))
+                .collect(),
+            SubroutineItem::Constant(constant) => {
+                deferred_constant(constant).into_iter().collect::<Vec<_>>()
+            }
+        });
+
+    let data = program
+        .data
+        .iter()
+        .flat_map(|data| &data.labels)
+        .flat_map(|label| &label.constants)
+        .filter_map(|constant| deferred_constant(&constant.node));
+
+    let mut values = Vec::new();
+
+    // A value that cannot be computed is recorded and left as 0, so every unknown label is reported at once
+    for (expression, size) in code.chain(data) {
+        // Negative values are stored in two's complement
+        let (range, kind) = match size {
+            1 => (-0x80..=0xFF, "an 8-bit byte"),
+            _ => (-0x8000..=0xFFFF, "a 16-bit word"),
+        };
+
+        let value = (|| {
+            check_extern_references(path, lines, program, expression)?;
+
+            let value = expression.evaluate_number(path, lines, &context)?;
+
+            if !range.contains(&value) {
+                return report_span_error(
+                    Code::ValueOutOfRange,
+                    format!(
+                        "Value {value} does not fit in {kind}, expected {} to {}!",
+                        range.start(),
+                        range.end()
+                    )
+                    .as_str(),
+                    path,
+                    lines,
+                    expression.span(),
+                );
+            }
+
+            Ok(value)
+        })();
+
+        let Some(value) = diagnostics.recover(value)? else {
+            values.push(0);
+            continue;
+        };
+
+        let stored = match size {
+            1 => value as u8 as u16,
+            _ => value as u16,
+        };
+
+        // The bits are the same, but anything reading them as unsigned sees a large value instead
+        if value < 0 {
+            let digits = size as usize * 2;
+
+            diagnostics.warn(
+                Warning::Truncation,
+                format!("Value {value} is stored in {kind} as ${stored:0digits$X} ({stored})!")
+                    .as_str(),
+                path,
+                lines,
+                expression.span(),
+            )?;
+        }
+
+        values.push(stored);
+    }
+
+    Ok(values)
+}
+
+/**
+ * The expression of a `.word` or `.byte` value computed once labels are placed, along with its size in bytes
+ */
+fn deferred_constant(constant: &ConstantLabelType) -> Option<(&Expression, u16)> {
+    match constant {
+        ConstantLabelType::DeferredWord(expression)
+        | ConstantLabelType::DeferredByte(expression) => Some((expression, constant.size())),
+        _ => None,
+    }
+}
+
+/**
+ * Labels declared .extern have no address in this file, so no value can be computed from them
+ */
+fn check_extern_references(
+    path: &Path,
+    lines: &[String],
+    program: &Program,
+    expression: &Expression,
+) -> diagnostic::Result<()> {
+    match expression {
+        Expression::Identifier(name, span)
+            if program
+                .externs
+                .iter()
+                .any(|external| &external.node == name) =>
+        {
+            return report_span_error(
+                Code::UnknownLabel,
+                format!(
+                    "Label `{name}` is declared .extern, so its address is not known in this file!"
+                )
+                .as_str(),
+                path,
+                lines,
+                *span,
+            )
+        }
+        Expression::Call { arguments, .. } => {
+            for argument in arguments {
+                check_extern_references(path, lines, program, argument)?;
+            }
+        }
+        Expression::Unary { operand, .. } => {
+            check_extern_references(path, lines, program, operand)?
+        }
+        Expression::Binary { left, right, .. } => {
+            check_extern_references(path, lines, program, left)?;
+            check_extern_references(path, lines, program, right)?;
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+/**
+ * Describe the values that made a failed condition false, e.g. `handler` is $0180 and `sizeof(handler)` is 160
+ */
+fn describe_condition(
+    path: &Path,
+    lines: &[String],
+    condition: &Expression,
+    context: &LayoutContext,
+) -> diagnostic::Result<Option<String>> {
+    let mut values: Vec<String> = Vec::new();
+    let mut pending = vec![condition];
+
+    while let Some(expression) = pending.pop() {
+        let value = match expression {
+            Expression::Identifier(name, _) => {
+                let value = expression.evaluate_number(path, lines, context)?;
+
+                // Labels are addresses, defines are plain numbers
+                match context.layout.find_symbol(name) {
+                    Some(_) => format!("`{name}` is ${value:04X}"),
+                    None => format!("`{name}` is {value}"),
+                }
+            }
+            Expression::Call {
+                name, arguments, ..
+            } if name == "sizeof" || name == "lengthof" => {
+                // The function already checked that its argument is a label name when the condition was evaluated
+                let Expression::Identifier(label, _) = &arguments[0] else {
+                    unreachable!()
+                };
+
+                let size = expression.evaluate_number(path, lines, context)?;
+                format!("`{name}({label})` is {size}")
+            }
+            Expression::Unary { operand, .. } => {
+                pending.push(operand);
+                continue;
+            }
+            Expression::Binary { left, right, .. } => {
+                pending.push(right);
+                pending.push(left);
+                continue;
+            }
+            _ => continue,
+        };
+
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+
+    if values.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(values.join(", ")))
+}
+
+/**
+ * Mark the .global labels and make sure no .extern label is also defined here, returning the .extern names
+ */
+fn resolve_visibility(
+    path: &Path,
+    lines: &[String],
+    program: &Program,
+    symbols: &mut [Symbol],
+) -> diagnostic::Result<Vec<String>> {
+    for global in &program.globals {
+        let Some(symbol) = symbols.iter_mut().find(|symbol| symbol.name == global.node) else {
+            return report_span_error(
+                Code::SymbolConflict,
+                format!(
+                    "Label `{}` is marked .global but is never declared!",
+                    global.node
+                )
+                .as_str(),
+                path,
+                lines,
+                global.span,
+            );
+        };
+
+        symbol.global = true;
+    }
+
+    for external in &program.externs {
+        if let Some(symbol) = symbols.iter().find(|symbol| symbol.name == external.node) {
+            return report_span_error_with_label(
+                Code::SymbolConflict,
+                format!(
+                    "Label `{}` is declared .extern but is also defined in this file!",
+                    external.node
+                )
+                .as_str(),
+                path,
+                lines,
+                external.span,
+                "defined here",
+                symbol.span,
+            );
+        }
+    }
+
+    Ok(program
+        .externs
+        .iter()
+        .map(|external| external.node.clone())
+        .collect())
+}
+
+/**
+ * The entry point has to be a subroutine label in the text section or a named section
+ */
+fn resolve_entry(
+    path: &Path,
+    lines: &[String],
+    layout: &Layout,
+    entry: &Spanned<String>,
+) -> diagnostic::Result<u16> {
+    match layout.find_symbol(&entry.node) {
+        Some(symbol) if symbol.section.is_code() => Ok(symbol.address),
+        Some(symbol) => report_span_error(
+            Code::InvalidEntryPoint,
+            format!(
+                "Entry point `{}` must be a label in the .text section or a named section, but it is in .{}!",
+                symbol.name,
+                symbol.section.name()
+            )
+            .as_str(),
+            path,
+            lines,
+            entry.span,
+        ),
+        None => report_span_error_with_help(
+            Code::UnknownLabel,
+            format!("Unknown label {} used as the entry point!", preprocess::source_name(&entry.node)).as_str(),
+            suggest::did_you_mean_label(&entry.node, layout.names()).as_deref(),
+            path,
+            lines,
+            entry.span,
+        ),
+    }
+}
+
+/**
+ * The address counter a label is placed with, either its region's or the one of its section.
+ *
+ * A .org before the label moves the counter forward to its address.
+ */
+fn label_cursor<'a>(
+    path: &Path,
+    lines: &[String],
+    regions: &'a mut HashMap<&str, u32>,
+    section_address: &'a mut u32,
+    placement: &Option<Spanned<String>>,
+    origin: &Option<Spanned<u16>>,
+) -> diagnostic::Result<&'a mut u32> {
+    let cursor = match placement {
+        Some(placement) => region_cursor(path, lines, regions, placement)?,
+        None => section_address,
+    };
+
+    if let Some(origin) = origin {
+        move_to_origin(path, lines, cursor, origin)?;
+    }
+
+    Ok(cursor)
+}
+
+/**
+ * Move an address counter forward to the address of a .org
+ */
+fn move_to_origin(
+    path: &Path,
+    lines: &[String],
+    cursor: &mut u32,
+    origin: &Spanned<u16>,
+) -> diagnostic::Result<()> {
+    let address = origin.node as u32;
+
+    if address < *cursor {
+        return report_span_error(
+            Code::OverlappingCode,
+            format!(
+                "Cannot move backwards with .org to ${address:04X}, everything up to ${:04X} has already been placed!",
+                *cursor - 1
+            )
+            .as_str(),
+            path,
+            lines,
+            origin.span,
+        );
+    }
+
+    *cursor = address;
+
+    Ok(())
+}
+
+/**
+ * Move `cursor` past the items of a label, given their sizes, and return the size of the label.
+ *
+ * A .org in the middle of the label moves the cursor to its address, and only the bytes before it count
+ * towards the size of the label. The address range of every part of the label is added to `ranges`.
+ */
+#[allow(clippy::too_many_arguments)]
+fn place_contents(
+    path: &Path,
+    lines: &[String],
+    cursor: &mut u32,
+    sizes: impl Iterator<Item = u16>,
+    continuations: &[Continuation],
+    span: Span,
+    symbol: usize,
+    ranges: &mut Vec<(u32, u32, usize)>,
+) -> diagnostic::Result<u32> {
+    let mut start = *cursor;
+    let mut size = None;
+
+    for (index, item_size) in sizes.enumerate() {
+        if let Some(origin) = parse::continuation_at(continuations, index) {
+            check_section_end(path, lines, *cursor, span)?;
+            ranges.push((start, *cursor, symbol));
+            size.get_or_insert(*cursor - start);
+
+            move_to_origin(path, lines, cursor, origin)?;
+            start = *cursor;
+        }
+
+        *cursor += item_size as u32;
+    }
+
+    check_section_end(path, lines, *cursor, span)?;
+    ranges.push((start, *cursor, symbol));
+
+    Ok(size.unwrap_or(*cursor - start))
+}
+
+/**
+ * Number of items of a label before a .org in the middle of it
+ */
+fn leading_items(continuations: &[Continuation]) -> usize {
+    continuations
+        .first()
+        .map_or(usize::MAX, |continuation| continuation.start)
+}
+
+fn region_cursor<'a>(
+    path: &Path,
+    lines: &[String],
+    regions: &'a mut HashMap<&str, u32>,
+    placement: &Spanned<String>,
+) -> diagnostic::Result<&'a mut u32> {
+    match regions.get_mut(placement.node.as_str()) {
+        Some(cursor) => Ok(cursor),
+        None => report_span_error(
+            Code::UnknownIdentifier,
+            format!(
+                "Unknown region `{}`! Regions are declared with `.region <name>, <address>`",
+                placement.node
+            )
+            .as_str(),
+            path,
+            lines,
+            placement.span,
+        ),
+    }
+}
+
+/**
+ * Make sure a label with a `.size` attribute fits in its budget
+ */
+fn check_size_budget(
+    path: &Path,
+    lines: &[String],
+    name: &str,
+    budget: &Option<Spanned<u16>>,
+    size: u32,
+) -> diagnostic::Result<()> {
+    let Some(budget) = budget else {
+        return Ok(());
+    };
+
+    if size > budget.node as u32 {
+        return report_span_error(
+            Code::ProgramTooLarge,
+            format!(
+                "Label `{name}` is {size} bytes, which is {} bytes over its .size budget of {} bytes!",
+                size - budget.node as u32,
+                budget.node
+            )
+            .as_str(),
+            path,
+            lines,
+            budget.span,
+        );
+    }
+
+    Ok(())
+}
+
+/**
+ * Make sure no two labels were placed on top of each other
+ */
+fn check_overlaps(
+    path: &Path,
+    lines: &[String],
+    symbols: &[Symbol],
+    mut ranges: Vec<(u32, u32, usize)>,
+) -> diagnostic::Result<()> {
+    // Empty labels never overlap anything
+    ranges.retain(|(start, end, _)| start != end);
+    ranges.sort();
+
+    for pair in ranges.windows(2) {
+        let (_, previous_end, previous) = pair[0];
+        let (start, _, current) = pair[1];
+
+        if start < previous_end {
+            let previous = &symbols[previous];
+            let current = &symbols[current];
+
+            return report_span_error_with_label(
+                Code::OverlappingCode,
+                format!(
+                    "Label `{}` at ${start:04X} overlaps label `{}`! (It ends at ${:04X})",
+                    current.name,
+                    previous.name,
+                    previous_end - 1
+                )
+                .as_str(),
+                path,
+                lines,
+                current.span,
+                format!("`{}` declared here", previous.name).as_str(),
+                previous.span,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn place_symbol(
+    path: &Path,
+    lines: &[String],
+    name: &str,
+    address: u32,
+    section: Section,
+    span: Span,
+) -> diagnostic::Result<Symbol> {
+    if address > u16::MAX as u32 {
+        return report_overflow(path, lines, address, span);
+    }
+
+    Ok(Symbol {
+        name: name.to_owned(),
+        address: address as u16,
+        section,
+        span,
+        size: 0,
+        global: false,
+    })
+}
+
+/**
+ * Make sure the contents of a label still fit in the 16-bit address space
+ */
+fn check_section_end(
+    path: &Path,
+    lines: &[String],
+    end_address: u32,
+    span: Span,
+) -> diagnostic::Result<()> {
+    if end_address > u16::MAX as u32 + 1 {
+        return report_overflow(path, lines, end_address - 1, span);
+    }
+
+    Ok(())
+}
+
+fn report_overflow<T>(
+    path: &Path,
+    lines: &[String],
+    address: u32,
+    span: Span,
+) -> diagnostic::Result<T> {
+    report_span_error(
+        Code::ProgramTooLarge,
+        format!("Program does not fit in memory! (Address ${address:X} is past $FFFF)").as_str(),
+        path,
+        lines,
+        span,
+    )
+}
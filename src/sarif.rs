@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use crate::{
+    code::Code,
+    diagnostic::{Diagnostic, Fix, Location, Severity},
+    json,
+};
+
+/**
+ * Render diagnostics as a SARIF 2.1.0 log, which code scanning dashboards can import
+ */
+pub fn sarif_log(diagnostics: &[Diagnostic]) -> String {
+    let rules: Vec<_> = Code::ALL
+        .iter()
+        .map(|code| {
+            format!(
+                "            {{ \"id\": {}, \"shortDescription\": {{ \"text\": {} }} }}",
+                json::string(code.id()),
+                json::string(code.title()),
+            )
+        })
+        .collect();
+
+    let results: Vec<_> = diagnostics.iter().map(sarif_result).collect();
+
+    format!(
+        "{{\n  \"$schema\": \"https://json.schemastore.org/sarif-2.1.0.json\",\n  \"version\": \"2.1.0\",\n  \"runs\": [\n    {{\n      \"tool\": {{\n        \"driver\": {{\n          \"name\": \"spasm\",\n          \"version\": {},\n          \"rules\": [\n{}\n          ]\n        }}\n      }},\n      \"columnKind\": \"unicodeCodePoints\",\n      \"results\": [\n{}\n      ]\n    }}\n  ]\n}}\n",
+        json::string(env!("CARGO_PKG_VERSION")),
+        rules.join(",\n"),
+        results.join(",\n"),
+    )
+}
+
+fn sarif_result(diagnostic: &Diagnostic) -> String {
+    let level = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+
+    // Notes and help explain how to fix the problem, so they are kept with the message
+    let text = std::iter::once(diagnostic.message.as_str())
+        .chain(diagnostic.notes.iter().map(String::as_str))
+        .chain(diagnostic.help.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let locations = match &diagnostic.location {
+        Some(location) => format!(", \"locations\": [{{ {} }}]", physical_location(location)),
+        None => String::new(),
+    };
+
+    let related: Vec<_> = diagnostic
+        .labels
+        .iter()
+        .enumerate()
+        .map(|(index, label)| {
+            format!(
+                "{{ \"id\": {index}, {}, \"message\": {{ \"text\": {} }} }}",
+                physical_location(&label.location),
+                json::string(&label.message),
+            )
+        })
+        .collect();
+
+    let related = if related.is_empty() {
+        String::new()
+    } else {
+        format!(", \"relatedLocations\": [{}]", related.join(", "))
+    };
+
+    let fixes: Vec<_> = diagnostic.fixes.iter().map(sarif_fix).collect();
+
+    let fixes = if fixes.is_empty() {
+        String::new()
+    } else {
+        format!(", \"fixes\": [{}]", fixes.join(", "))
+    };
+
+    format!(
+        "        {{ \"ruleId\": {}, \"level\": \"{level}\", \"message\": {{ \"text\": {} }}{locations}{related}{fixes} }}",
+        json::string(diagnostic.code.id()),
+        json::string(&text),
+    )
+}
+
+fn sarif_fix(fix: &Fix) -> String {
+    format!(
+        "{{ \"description\": {{ \"text\": {} }}, \"artifactChanges\": [{{ \"artifactLocation\": {{ \"uri\": {} }}, \"replacements\": [{{ \"deletedRegion\": {{ \"startLine\": {}, \"startColumn\": {}, \"endColumn\": {} }}, \"insertedContent\": {{ \"text\": {} }} }}] }}] }}",
+        json::string(&fix.message),
+        json::string(&uri(&fix.path)),
+        fix.span.line_number + 1,
+        fix.span.column_start + 1,
+        fix.span.column_end + 1,
+        json::string(&fix.replacement),
+    )
+}
+
+fn physical_location(location: &Location) -> String {
+    format!(
+        "\"physicalLocation\": {{ \"artifactLocation\": {{ \"uri\": {} }}, \"region\": {{ \"startLine\": {}, \"startColumn\": {}, \"endColumn\": {}, \"snippet\": {{ \"text\": {} }} }} }}",
+        json::string(&uri(&location.path)),
+        location.span.line_number + 1,
+        location.span.column_start + 1,
+        location.span.column_end.max(location.span.column_start + 1) + 1,
+        json::string(location.context.last().map(String::as_str).unwrap_or_default()),
+    )
+}
+
+/**
+ * A path as a URI reference, relative paths stay relative so dashboards resolve them against the repository
+ */
+fn uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+
+    let mut uri = String::new();
+
+    // Absolute paths are file URIs, with Windows drive letters after a slash
+    if path.starts_with('/') {
+        uri.push_str("file://");
+    } else if path.as_bytes().get(1) == Some(&b':') {
+        uri.push_str("file:///");
+    }
+
+    for (index, byte) in path.bytes().enumerate() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            b':' if index == 1 => uri.push(':'),
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    uri
+}
@@ -0,0 +1,91 @@
+/**
+ * Number of single character insertions, deletions, substitutions and swaps of neighbouring characters needed to
+ * turn `a` into `b`, ignoring case
+ */
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+
+    // distances[i][j] is the distance between the first i characters of a and the first j characters of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+
+            // Typing two characters the wrong way around is a single mistake
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/**
+ * The candidate closest to `name`, if it is close enough to be what was meant
+ *
+ * Short names allow a single mistake, longer ones one mistake for every three characters.
+ */
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/**
+ * A note suggesting the label closest to `name`. Local labels are renamed to `parent.local`, but can only be
+ * written as `.local` inside their parent, so only the ones in the same label as `name` are suggested.
+ */
+pub fn did_you_mean_label<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<String> {
+    let parent = name.split_once('.').map(|(parent, _)| parent);
+
+    let candidates = candidates.into_iter().filter(|candidate| {
+        candidate
+            .split_once('.')
+            .is_none_or(|(candidate_parent, _)| Some(candidate_parent) == parent)
+    });
+
+    let candidate = closest(name, candidates)?;
+
+    let candidate = match candidate.split_once('.') {
+        Some((_, local)) => format!(".{local}"),
+        None => candidate.to_owned(),
+    };
+
+    Some(format!("Did you mean `{candidate}`?"))
+}
+
+/**
+ * A note suggesting the closest candidate to `name`, written with `prefix` in front as in source, like `%` for registers
+ */
+pub fn did_you_mean<'a>(
+    name: &str,
+    prefix: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<String> {
+    closest(name, candidates).map(|candidate| format!("Did you mean `{prefix}{candidate}`?"))
+}
@@ -0,0 +1,235 @@
+use std::{fs, path::Path};
+
+use crate::{
+    code::Code,
+    diagnostic::{self, Diagnostic, Severity},
+    include, io_error_note,
+    parse::{ConstantLabelType, Spanned},
+    report_error_with_note, report_span_error,
+    token::{Span, Token},
+};
+
+/**
+ * Size of every element emitted from a table
+ */
+#[derive(Debug, Clone, Copy)]
+pub enum TableWidth {
+    Word,
+    Byte,
+}
+
+/**
+ * Read a table of numbers for the .inctable directive.
+ *
+ * Values are separated by commas and/or whitespace and may use the same `$`/`%` prefixes
+ * as literals in the source. Everything after a `;` or `#` on a row is a comment.
+ * Errors in the table are reported against the table file itself.
+ */
+pub fn read_table(
+    path: &Path,
+    lines: &[String],
+    file_name: &str,
+    file_token: &Token,
+    width: TableWidth,
+) -> diagnostic::Result<Vec<ConstantLabelType>> {
+    // Table paths are relative to the file that includes them
+    let table_path = include::source_path(path, lines, file_token.line_number)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(file_name);
+
+    let content = match fs::read_to_string(&table_path) {
+        Ok(content) => content,
+        Err(err) => {
+            return report_error_with_note(
+                Code::UnreadableFile,
+                format!("Could not read table file `{file_name}`: {err}").as_str(),
+                io_error_note(&err),
+                path,
+                lines,
+                file_token.line_number,
+                file_token.column_start,
+                file_token.column_end,
+            )
+        }
+    };
+
+    let table_lines: Vec<_> = content.lines().map(|line| line.to_owned()).collect();
+
+    let mut values = Vec::new();
+
+    for (row, line) in table_lines.iter().enumerate() {
+        let chars: Vec<_> = line.chars().collect();
+        let mut column = 0;
+
+        while column < chars.len() {
+            if matches!(chars[column], ';' | '#') {
+                break;
+            }
+
+            if matches!(chars[column], ',' | ' ' | '\t') {
+                column += 1;
+                continue;
+            }
+
+            // Read the whole cell
+            let cell_start = column;
+
+            while column < chars.len() && !matches!(chars[column], ',' | ' ' | '\t' | ';' | '#') {
+                column += 1;
+            }
+
+            let cell: String = chars[cell_start..column].iter().collect();
+
+            let cell_error = |error: &str| {
+                let span = Span {
+                    line_number: row as u32,
+                    column_start: cell_start as u32,
+                    column_end: column as u32,
+                };
+
+                Diagnostic::at(
+                    Severity::Error,
+                    Code::InvalidTable,
+                    error,
+                    None,
+                    &table_path,
+                    &table_lines,
+                    span,
+                )
+            };
+
+            let Some(value) = parse_table_value(&cell) else {
+                return cell_error(format!("Invalid number `{cell}` in table!").as_str()).raise();
+            };
+
+            values.push(match width {
+                TableWidth::Word => match u16::try_from(value) {
+                    Ok(value) => ConstantLabelType::Word(value),
+                    Err(_) => return cell_error(
+                        format!("Table value `{cell}` is larger than expected 16-bit word! (Max is $FFFF)").as_str(),
+                    )
+                    .raise(),
+                },
+                TableWidth::Byte => match u8::try_from(value) {
+                    Ok(value) => ConstantLabelType::Byte(value),
+                    Err(_) => return cell_error(
+                        format!("Table value `{cell}` is larger than expected 8-bit byte! (Max is $FF)").as_str(),
+                    )
+                    .raise(),
+                },
+            });
+        }
+    }
+
+    Ok(values)
+}
+
+/**
+ * Read the bytes of a binary file for the .incbin directive, starting at `offset` and
+ * reading `length` bytes or up to the end of the file.
+ * The file is found relative to the file that includes it.
+ */
+pub fn read_binary(
+    path: &Path,
+    lines: &[String],
+    file_name: &str,
+    file_token: &Token,
+    offset: Option<Spanned<u16>>,
+    length: Option<Spanned<u16>>,
+) -> diagnostic::Result<Vec<u8>> {
+    let binary_path = include::source_path(path, lines, file_token.line_number)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(file_name);
+
+    let bytes = match fs::read(&binary_path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            return report_error_with_note(
+                Code::UnreadableFile,
+                format!("Could not read binary file `{file_name}`: {err}").as_str(),
+                io_error_note(&err),
+                path,
+                lines,
+                file_token.line_number,
+                file_token.column_start,
+                file_token.column_end,
+            )
+        }
+    };
+
+    let start = offset.as_ref().map_or(0, |offset| offset.node as usize);
+
+    if let Some(offset) = offset {
+        if start > bytes.len() {
+            return report_span_error(
+                Code::FileRange,
+                format!(
+                    "Offset {start} is past the end of `{file_name}`, which is {} bytes long!",
+                    bytes.len()
+                )
+                .as_str(),
+                path,
+                lines,
+                offset.span,
+            );
+        }
+    }
+
+    let end = match length {
+        Some(length) => {
+            let end = start + length.node as usize;
+
+            if end > bytes.len() {
+                return report_span_error(
+                    Code::FileRange,
+                    format!(
+                        "Cannot read {} bytes from `{file_name}` at offset {start}, only {} bytes are left!",
+                        length.node,
+                        bytes.len() - start
+                    )
+                    .as_str(),
+                    path,
+                    lines,
+                    length.span,
+                );
+            }
+
+            end
+        }
+        None => bytes.len(),
+    };
+
+    // Everything is addressed with 16 bits
+    if end - start > u16::MAX as usize {
+        return report_span_error(
+            Code::ValueOutOfRange,
+            format!("Binary file `{file_name}` is {} bytes, which does not fit in the 64K address space!", end - start).as_str(),
+            path,
+            lines,
+            file_token.span(),
+        );
+    }
+
+    Ok(bytes[start..end].to_vec())
+}
+
+fn parse_table_value(cell: &str) -> Option<u32> {
+    let (digits, radix) =
+        if let Some(hex) = cell.strip_prefix('$').or_else(|| cell.strip_prefix("0x")) {
+            (hex, 16)
+        } else if let Some(binary) = cell.strip_prefix('%').or_else(|| cell.strip_prefix("0b")) {
+            (binary, 2)
+        } else {
+            (cell, 10)
+        };
+
+    // from_str_radix accepts a leading `+` which is not valid here
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+
+    // Anything too large for a u32 is definitely too large for the table
+    Some(u32::from_str_radix(digits, radix).unwrap_or(u32::MAX))
+}
@@ -1,11 +1,21 @@
-use std::{collections::VecDeque, num::IntErrorKind, path::PathBuf};
+use std::{collections::VecDeque, num::IntErrorKind, path::Path};
 
 use regex::Regex;
 
-use crate::report_error;
+use crate::{
+    code::Code,
+    diagnostic::{self, Diagnostics},
+    expr, report_error, report_error_with_note,
+};
+
+/**
+ * Shown with errors about invalid label names
+ */
+const LABEL_SYNTAX_NOTE: &str = "Labels start with a letter or `_` followed by letters, digits or `_` and end with `:` (e.g. `loop1:`). \
+Labels of only digits (e.g. `1:`) are numeric labels, referenced with `1f` or `1b`.";
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token {
     pub line_number: u32,
     pub column_start: u32,
@@ -14,36 +24,79 @@ pub struct Token {
     pub token_type: TokenType,
 }
 
+/**
+ * Location of a token (or anything built from tokens) in the source file
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line_number: u32,
+    pub column_start: u32,
+    pub column_end: u32,
+}
+
+impl Span {
+    /**
+     * Builds a span covering everything from the start of `self` to the end of `other`
+     */
+    pub fn to(&self, other: &Span) -> Span {
+        Span {
+            line_number: self.line_number,
+            column_start: self.column_start,
+            column_end: other.column_end,
+        }
+    }
+
+    /**
+     * Checks if two spans on the same line share at least one column
+     */
+    pub fn overlaps(&self, other: &Span) -> bool {
+        self.line_number == other.line_number
+            && self.column_start <= other.column_end
+            && other.column_start <= self.column_end
+    }
+}
+
 #[rustfmt::skip]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
-    Label(String),       // Any valid identifier followed by ':' and whitespace to end of line
+    Label(String),       // Any valid identifier, or '.' and an identifier for a local label, followed by ':', or a lone ':' or '@@'
     Directive(String),   // '.' followed by a valid identifier
     Instruction(String), // Any valid identifier at the beginning of a line
     Comma,               // ','
     Register(String),    // '%' followed by any identifier
     Immediate,           // '#'
     Decimal(String),     // any decimal value without a prefix
-    Binary(String),      // '%' followed by a binary value
-    Hex(String),         // '$' followed by a hex value
+    Binary(String),      // '%' or '0b' followed by a binary value
+    Hex(String),         // '$' or '0x' followed by a hex value
     AsciiString(String), // Any valid ascii string enclosed by '"' including valid escape characters
+    Char(String),        // A single character enclosed by '\'' including valid escape characters
     Identifier(String),  // Any alphanumeric value on its own
     OpenBracket,         // '['
     CloseBracket,        // ']'
     OpenParenthesis,     // '('
     CloseParenthesis,    // ')'
+    Operator(String),    // Any expression operator such as '==', '&&', '!' or '+'
 }
 
 impl Token {
-    pub fn parse_u16(&self, path: &PathBuf, lines: &Vec<String>) -> u16 {
+    pub fn span(&self) -> Span {
+        Span {
+            line_number: self.line_number,
+            column_start: self.column_start,
+            column_end: self.column_end,
+        }
+    }
+
+    pub fn parse_u16(&self, path: &Path, lines: &[String]) -> diagnostic::Result<u16> {
         match &self.token_type {
             TokenType::Binary(value) => {
                 // Parse from string value
                 match u16::from_str_radix(value, 2) {
-                    Ok(v) => v,
+                    Ok(v) => Ok(v),
                     Err(err) => match err.kind() {
                         // Greater than a 16 bit word
                         IntErrorKind::PosOverflow => report_error(
+                            Code::NumberTooLarge,
                             "Binary literal is larger than expected 16-bit word! (Max is %1111111111111111)",
                             path,
                             lines,
@@ -51,17 +104,18 @@ impl Token {
                             self.column_start,
                             self.column_end,
                         ),
-                        kind => panic!("Unexpected IntErrorKind: {kind:?}"),
+                        _ => self.report_invalid_number(path, lines),
                     },
                 }
             }
             TokenType::Decimal(value) => {
                 // Parse from string value
-                match u16::from_str_radix(value, 10) {
-                    Ok(v) => v,
+                match value.parse::<u16>() {
+                    Ok(v) => Ok(v),
                     Err(err) => match err.kind() {
                         // Greater than a 16 bit word
                         IntErrorKind::PosOverflow => report_error(
+                            Code::NumberTooLarge,
                             "Decimal literal is larger than expected 16-bit word! (Max is 65535)",
                             path,
                             lines,
@@ -69,17 +123,18 @@ impl Token {
                             self.column_start,
                             self.column_end,
                         ),
-                        kind => panic!("Unexpected IntErrorKind: {kind:?}"),
+                        _ => self.report_invalid_number(path, lines),
                     },
                 }
             }
             TokenType::Hex(value) => {
                 // Parse from string value
                 match u16::from_str_radix(value, 16) {
-                    Ok(v) => v,
+                    Ok(v) => Ok(v),
                     Err(err) => match err.kind() {
                         // Greater than a 16 bit word
                         IntErrorKind::PosOverflow => report_error(
+                            Code::NumberTooLarge,
                             "Hexadecimal literal is larger than expected 16-bit word! (Max is $FFFF)",
                             path,
                             lines,
@@ -87,249 +142,236 @@ impl Token {
                             self.column_start,
                             self.column_end,
                         ),
-                        kind => panic!("Unexpected IntErrorKind: {kind:?}"),
+                        _ => self.report_invalid_number(path, lines),
                     },
                 }
             }
-            _ => panic!("Cannot parse u16 from non number type!"),
+            _ => self.report_invalid_number(path, lines),
         }
     }
+
+    /**
+     * Report a token that should be a number but has no valid digits, which the lexer rejects before it gets here
+     */
+    fn report_invalid_number<T>(&self, path: &Path, lines: &[String]) -> diagnostic::Result<T> {
+        report_error(
+            Code::InvalidNumber,
+            format!("`{}` is not a valid number!", self.value).as_str(),
+            path,
+            lines,
+            self.line_number,
+            self.column_start,
+            self.column_end,
+        )
+    }
 }
 
-pub fn tokenize_lines(path: &PathBuf, lines: &Vec<String>) -> VecDeque<Token> {
+pub(crate) fn tokenize_lines(path: &Path, lines: &[String], diagnostics: &mut Diagnostics) -> diagnostic::Result<VecDeque<Token>> {
     let mut tokens: VecDeque<Token> = VecDeque::new();
 
-    for (line_number, line) in lines.iter().enumerate() {
-        let line_number = line_number as u32;
+    let code = mask_block_comments(path, lines)?;
 
-        let mut chars: VecDeque<_> = line.chars().collect();
+    for (line_number, line) in code.iter().enumerate() {
+        let start = tokens.len();
 
-        let mut col_number: u32 = 0;
-        let mut found_instruction = false;
-        let mut found_directive = false;
+        // A line with an error is left out, so the lines after it are still checked
+        let lexed = tokenize_line(path, lines, line_number as u32, line, &mut tokens);
 
-        // Check if line is empty
-        if chars.is_empty() {
-            continue;
+        if diagnostics.recover(lexed)?.is_none() {
+            tokens.truncate(start);
         }
+    }
 
-        // Loop through characters in the line building tokens
-        while !chars.is_empty() {
-            let token_col_start = col_number;
-
-            let first_char = chars.pop_front().unwrap();
-            col_number += 1;
-
-            match (
-                first_char,
-                first_char.is_alphabetic() || first_char == '_',
-                first_char.is_numeric(),
-            ) {
-                // Keep going until we find something more interesting
-                (' ', _, _) => continue,
-                // If we found a comment, there are no more tokens so just jump to the next line
-                (';', _, _) => break,
-                // Directive
-                ('.', _, _) => {
-                    let identifier =  read_to_chars(vec![' ', ']', ')', '[', '(', ','], &mut col_number, &mut chars);
-
-                    let Some(value) = identifier else {
-                        report_error(
-                            "Unexpected end of directive token",
-                            path,
-                            lines,
-                            line_number,
-                            token_col_start,
-                            col_number,
-                        );
-                    };
+    Ok(tokens)
+}
 
-                    if !value.is_alphanumeric() {
-                        report_error(
-                            "Directive names must be alphanumeric!",
-                            path,
-                            lines,
-                            line_number,
-                            token_col_start,
-                            col_number,
-                        );
-                    }
+/**
+ * Lex a line of code with block comments masked out, adding its tokens to `tokens`
+ */
+fn tokenize_line(path: &Path, lines: &[String], line_number: u32, line: &str, tokens: &mut VecDeque<Token>) -> diagnostic::Result<()> {
+    let mut chars: VecDeque<_> = line.chars().collect();
+
+    let mut col_number: u32 = 0;
+    let mut found_instruction = false;
+    let mut found_directive = false;
+
+    // Check if line is empty
+    if chars.is_empty() {
+        return Ok(());
+    }
 
-                    let full_value = format!("{first_char}{value}");
+    // Loop through characters in the line building tokens
+    while !chars.is_empty() {
+        let token_col_start = col_number;
+
+        let first_char = chars.pop_front().unwrap();
+        col_number += 1;
+
+        match (
+            first_char,
+            first_char.is_alphabetic() || first_char == '_',
+            first_char.is_numeric(),
+        ) {
+            // Keep going until we find something more interesting
+            (' ' | '\t', _, _) => continue,
+            // If we found a comment, there are no more tokens so just jump to the next line
+            (';', _, _) => break,
+            // Directive
+            ('.', _, _) => {
+                let identifier =  read_to_chars(vec![' ', '\t', ']', ')', '[', '(', ',', '=', '!', '<', '>', '&', '|', '+', '-', '*', '/'], &mut col_number, &mut chars);
+
+                let Some(value) = identifier else {
+                    return report_error(
+                        Code::InvalidToken,
+                        "Unexpected end of directive token",
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
+                };
+
+                let full_value = format!("{first_char}{value}");
 
+                // `.name:` declares a local label, which is scoped to the label before it
+                if let Some(name) = value.strip_suffix(':').filter(|name| !name.is_empty() && name.is_alphanumeric()) {
                     tokens.push_back(Token {
-                        line_number: line_number as u32,
+                        line_number,
                         column_start: token_col_start,
                         column_end: col_number,
-                        value: full_value,
-                        token_type: TokenType::Directive(value),
+                        value: full_value.clone(),
+                        token_type: TokenType::Label(format!(".{name}")),
                     });
 
-                    found_directive = true;
+                    continue;
                 }
-                // First character is alphanumeric
-                // Could be a label, an instruction, or an identifier
-                (_, true, _) => {
-                    let proceeding =
-                        read_to_chars(vec![' ', ']', ')', '[', '(', ','], &mut col_number, &mut chars);
-
-                    let value = match proceeding {
-                        Some(val) => val,
-                        None => "".to_owned(),
-                    };
-
-                    let full_value = format!("{first_char}{value}");
-
-                    // Found a label
-                    if full_value.ends_with(":") {
-                        // Check if name without the ':' is valid
-                        if !(&full_value[..full_value.len() - 1]).is_alphanumeric() {
-                            report_error(
-                                "Label name must be alphanumeric!",
-                                path,
-                                lines,
-                                line_number,
-                                token_col_start,
-                                col_number,
-                            );
-                        }
-
-                        let label_name = (&full_value[..full_value.len() - 1]).to_owned();
 
-                        tokens.push_back(Token {
-                            line_number: line_number as u32,
-                            column_start: token_col_start,
-                            column_end: col_number,
-                            value: full_value,
-                            token_type: TokenType::Label(label_name),
-                        });
+                if !value.is_alphanumeric() {
+                    return report_error(
+                        Code::InvalidToken,
+                        "Directive names must be alphanumeric!",
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
+                }
 
-                        continue;
-                    }
+                // In an operand or value `.name` refers to a local label, but after a label it is an attribute such as `.size`
+                let line_tokens: Vec<_> = tokens.iter().rev().take_while(|token| token.line_number == line_number).collect();
 
-                    // If we found a naked identifier on a line where we have not yet
-                    // found an instruction or directive, this must be an instruction
-                    if !found_instruction && !found_directive {
-                        // Found an instruction
-                        found_instruction = true;
-
-                        if !full_value.is_alphanumeric() {
-                            report_error(
-                                "Instruction name must be alphanumeric!",
-                                path,
-                                lines,
-                                line_number,
-                                token_col_start,
-                                col_number,
-                            );
-                        }
+                let is_local_reference = line_tokens.last().is_some_and(|first| !matches!(first.token_type, TokenType::Label(_)))
+                    && line_tokens.first().is_some_and(|previous| matches!(
+                        previous.token_type,
+                        TokenType::Instruction(_) | TokenType::Directive(_) | TokenType::Comma | TokenType::Immediate
+                            | TokenType::Operator(_) | TokenType::OpenParenthesis | TokenType::OpenBracket
+                    ));
 
-                        tokens.push_back(Token {
-                            line_number: line_number as u32,
-                            column_start: token_col_start,
-                            column_end: col_number,
-                            value: full_value.clone(),
-                            token_type: TokenType::Instruction(full_value),
-                        });
-                    }
-                    // If we already found an instruction on this line,
-                    // it must be another identifier
-                    else {
-                        if !full_value.is_alphanumeric() {
-                            report_error(
-                                "Identifier name must be alphanumeric!",
-                                path,
-                                lines,
-                                line_number,
-                                token_col_start,
-                                col_number,
-                            );
-                        }
+                if is_local_reference {
+                    tokens.push_back(Token {
+                        line_number,
+                        column_start: token_col_start,
+                        column_end: col_number,
+                        value: full_value.clone(),
+                        token_type: TokenType::Identifier(full_value),
+                    });
 
-                        tokens.push_back(Token {
-                            line_number: line_number as u32,
-                            column_start: token_col_start,
-                            column_end: col_number,
-                            value: full_value.clone(),
-                            token_type: TokenType::Identifier(full_value),
-                        });
-                    }
+                    continue;
                 }
-                // Ascii String Literal
-                ('"', _, _) => {
-                    let proceeding = read_to_char_inclusive('"', &mut col_number, &mut chars);
 
-                    let Some(value) = proceeding else {
-                        report_error(
-                            "Expected closing '\"' for string literal",
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: full_value,
+                    token_type: TokenType::Directive(value),
+                });
+
+                found_directive = true;
+            }
+            // First character is alphanumeric
+            // Could be a label, an instruction, or an identifier
+            (_, true, _) => {
+                let proceeding =
+                    read_to_chars(vec![' ', '\t', ']', ')', '[', '(', ',', '=', '!', '<', '>', '&', '|', '+', '-', '*', '/'], &mut col_number, &mut chars);
+
+                let value = match proceeding {
+                    Some(val) => val,
+                    None => "".to_owned(),
+                };
+
+                let full_value = format!("{first_char}{value}");
+
+                // Found a label
+                if full_value.ends_with(":") {
+                    // Check if name without the ':' is valid
+                    if !(&full_value[..full_value.len() - 1]).is_alphanumeric() {
+                        return report_error_with_note(
+                            Code::InvalidToken,
+                            "Label name must be alphanumeric!",
+                            Some(LABEL_SYNTAX_NOTE),
                             path,
                             lines,
                             line_number,
                             token_col_start,
                             col_number,
                         );
-                    };
-
-                    let full_value = format!("{first_char}{value}");
+                    }
 
-                    let string_contents = (&full_value[1..full_value.len() - 1]).to_owned();
+                    let label_name = full_value[..full_value.len() - 1].to_owned();
 
                     tokens.push_back(Token {
-                        line_number: line_number as u32,
+                        line_number,
                         column_start: token_col_start,
                         column_end: col_number,
                         value: full_value,
-                        token_type: TokenType::AsciiString(string_contents),
+                        token_type: TokenType::Label(label_name),
                     });
+
+                    continue;
                 }
-                // Register name or binary value
-                ('%', _, _) => {
-                    let value = read_to_chars(vec![' ', ',', ';', '(', ')', '[', ']'], &mut col_number, &mut chars);
 
-                    let Some(value) = value else {
-                        report_error(
-                            "Unexpected end of token",
+                // If we found a naked identifier on a line where we have not yet
+                // found an instruction or directive, this must be an instruction
+                if !found_instruction && !found_directive {
+                    // Found an instruction
+                    found_instruction = true;
+
+                    // A `.b` or `.w` suffix gives the size of the operands (e.g. `mov.b`)
+                    let name = match full_value.rsplit_once('.') {
+                        Some((name, "b" | "w" | "B" | "W")) => name,
+                        _ => full_value.as_str(),
+                    };
+
+                    if !name.is_alphanumeric() {
+                        return report_error(
+                            Code::InvalidToken,
+                            "Instruction name must be alphanumeric!",
                             path,
                             lines,
                             line_number,
                             token_col_start,
                             col_number,
                         );
-                    };
-
-                    let full_value = format!("{first_char}{value}");
-
-                    // Value is binary literal
-                    if value.is_numeric() {
-                        if !value.is_binary() {
-                            report_error(
-                                "'%' Can only be used for binary literals!",
-                                path,
-                                lines,
-                                line_number,
-                                token_col_start,
-                                col_number,
-                            );
-                        }
-
-                        // Push binary token
-                        tokens.push_back(Token {
-                            line_number: line_number as u32,
-                            column_start: token_col_start,
-                            column_end: col_number,
-                            value: full_value,
-                            token_type: TokenType::Binary(value),
-                        });
-
-                        continue;
                     }
 
-                    /* Otherwise must be a register name */
-
-                    // Make sure register name is valie
-                    if !value.is_alphanumeric() {
-                        report_error(
-                            "Register names must be alphanumeric!",
+                    tokens.push_back(Token {
+                        line_number,
+                        column_start: token_col_start,
+                        column_end: col_number,
+                        value: full_value.clone(),
+                        token_type: TokenType::Instruction(full_value),
+                    });
+                }
+                // If we already found an instruction on this line,
+                // it must be another identifier
+                else {
+                    if !full_value.is_alphanumeric() {
+                        return report_error(
+                            Code::InvalidToken,
+                            "Identifier name must be alphanumeric!",
                             path,
                             lines,
                             line_number,
@@ -338,55 +380,278 @@ pub fn tokenize_lines(path: &PathBuf, lines: &Vec<String>) -> VecDeque<Token> {
                         );
                     }
 
-                    // Push register token
                     tokens.push_back(Token {
-                        line_number: line_number as u32,
+                        line_number,
                         column_start: token_col_start,
                         column_end: col_number,
-                        value: full_value,
-                        token_type: TokenType::Register(value),
+                        value: full_value.clone(),
+                        token_type: TokenType::Identifier(full_value),
                     });
                 }
-                // Comma
-                (',', _, _) => {
+            }
+            // Ascii String Literal
+            ('"', _, _) => {
+                let proceeding = read_to_char_inclusive('"', &mut col_number, &mut chars);
+
+                let Some(value) = proceeding else {
+                    return report_error(
+                        Code::UnterminatedLiteral,
+                        "Expected closing '\"' for string literal",
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
+                };
+
+                let full_value = format!("{first_char}{value}");
+
+                let string_contents = full_value[1..full_value.len() - 1].to_owned();
+
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: full_value,
+                    token_type: TokenType::AsciiString(string_contents),
+                });
+            }
+            // Ascii Character Literal
+            ('\'', _, _) => {
+                let proceeding = read_to_char_inclusive('\'', &mut col_number, &mut chars);
+
+                let Some(value) = proceeding else {
+                    return report_error(
+                        Code::UnterminatedLiteral,
+                        "Expected closing '\'' for character literal",
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
+                };
+
+                let full_value = format!("{first_char}{value}");
+
+                let char_contents = full_value[1..full_value.len() - 1].to_owned();
+
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: full_value,
+                    token_type: TokenType::Char(char_contents),
+                });
+            }
+            // Register name or binary value
+            ('%', _, _) => {
+                let value = read_to_chars(vec![' ', '\t', ',', ';', '(', ')', '[', ']', '=', '!', '<', '>', '&', '|', '+', '-', '*', '/'], &mut col_number, &mut chars);
+
+                let Some(value) = value else {
+                    return report_error(
+                        Code::InvalidToken,
+                        "Unexpected end of token",
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
+                };
+
+                let full_value = format!("{first_char}{value}");
+
+                // Value is binary literal
+                if value.is_numeric() {
+                    if !value.is_binary() {
+                        return report_error(
+                            Code::InvalidNumber,
+                            "'%' Can only be used for binary literals!",
+                            path,
+                            lines,
+                            line_number,
+                            token_col_start,
+                            col_number,
+                        );
+                    }
+
+                    // Push binary token
                     tokens.push_back(Token {
-                        line_number: line_number as u32,
+                        line_number,
                         column_start: token_col_start,
                         column_end: col_number,
-                        value: first_char.to_string(),
-                        token_type: TokenType::Comma,
+                        value: full_value,
+                        token_type: TokenType::Binary(value),
                     });
+
+                    continue;
+                }
+
+                /* Otherwise must be a register name */
+
+                // Make sure register name is valie
+                if !value.is_alphanumeric() {
+                    return report_error(
+                        Code::InvalidToken,
+                        "Register names must be alphanumeric!",
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
+                }
+
+                // Push register token
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: full_value,
+                    token_type: TokenType::Register(value),
+                });
+            }
+            // Comma
+            (',', _, _) => {
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: first_char.to_string(),
+                    token_type: TokenType::Comma,
+                });
+            }
+            // Immediate Value
+            ('#', _, _) => {
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: first_char.to_string(),
+                    token_type: TokenType::Immediate,
+                });
+            }
+            // Hex Value
+            ('$', _, _) => {
+                let value = read_to_chars(vec![' ', '\t', ',', ';', '(', ')', '[', ']', '=', '!', '<', '>', '&', '|', '+', '-', '*', '/'], &mut col_number, &mut chars);
+
+                let Some(value) = value else {
+                    return report_error(
+                        Code::InvalidToken,
+                        "Unexpected end of hex literal token",
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
+                };
+
+                let full_value = format!("{first_char}{value}");
+
+                if !value.is_alphanumeric() {
+                    return report_error(
+                        Code::InvalidNumber,
+                        "Unexpected non-alphanumeric characters in hex literal!",
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
+                }
+
+                // Make sure the value is value hex
+                if !value.is_hex() {
+                    return report_error(
+                        Code::InvalidNumber,
+                        "'$' Can only be used for hex literals!",
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
                 }
-                // Immediate Value
-                ('#', _, _) => {
+
+                // Push hex token
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: full_value,
+                    token_type: TokenType::Hex(value),
+                });
+            }
+            (_, _, true) => {
+                let literal = read_to_chars(vec![' ', '\t', ',', ';', '(', ')', '[', ']', '=', '!', '<', '>', '&', '|', '+', '-', '*', '/'], &mut col_number, &mut chars);
+
+                let value = match literal {
+                    Some(val) => val,
+                    None => "".to_owned(),
+                };
+
+                let full_value = format!("{first_char}{value}");
+
+                // Labels that start with a digit are read as a decimal literal
+                if let Some(label_name) = full_value.strip_suffix(':') {
+                    // Numeric labels can be declared more than once
+                    if label_name.is_numeric() {
+                        tokens.push_back(Token {
+                            line_number,
+                            column_start: token_col_start,
+                            column_end: col_number,
+                            value: full_value.clone(),
+                            token_type: TokenType::Label(label_name.to_owned()),
+                        });
+
+                        continue;
+                    }
+
+                    return report_error_with_note(
+                        Code::InvalidLabelName,
+                        format!("Label `{label_name}` cannot start with a digit!").as_str(),
+                        Some(LABEL_SYNTAX_NOTE),
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
+                }
+
+                // `1f` and `1b` refer to the next and previous numeric label `1:`
+                if full_value.strip_suffix(['f', 'b']).is_some_and(|digits| !digits.is_empty() && digits.is_numeric()) {
                     tokens.push_back(Token {
-                        line_number: line_number as u32,
+                        line_number,
                         column_start: token_col_start,
                         column_end: col_number,
-                        value: first_char.to_string(),
-                        token_type: TokenType::Immediate,
+                        value: full_value.clone(),
+                        token_type: TokenType::Identifier(full_value),
                     });
+
+                    continue;
                 }
-                // Hex Value
-                ('$', _, _) => {
-                    let value = read_to_chars(vec![' ', ',', ';', '(', ')', '[', ']'], &mut col_number, &mut chars);
 
-                    let Some(value) = value else {
-                        report_error(
-                            "Unexpected end of hex literal token",
-                            path,
-                            lines,
-                            line_number,
-                            token_col_start,
-                            col_number,
-                        );
+                // C style `0x` and `0b` prefixes are accepted alongside `$` and `%`
+                let prefixed = match (first_char, value.get(..1)) {
+                    ('0', Some("x" | "X")) => Some(("hex", value[1..].to_owned())),
+                    ('0', Some("b" | "B")) => Some(("binary", value[1..].to_owned())),
+                    _ => None,
+                };
+
+                if let Some((kind, digits)) = prefixed {
+                    let is_valid = match kind {
+                        "hex" => digits.is_hex(),
+                        _ => digits.is_binary(),
                     };
 
-                    let full_value = format!("{first_char}{value}");
-
-                    if !value.is_alphanumeric() {
-                        report_error(
-                            "Unexpected non-alphanumeric characters in hex literal!",
+                    if digits.is_empty() || !is_valid {
+                        return report_error(
+                            Code::InvalidNumber,
+                            format!("Expected {kind} digits after `{}`!", &full_value[..2]).as_str(),
                             path,
                             lines,
                             line_number,
@@ -395,40 +660,122 @@ pub fn tokenize_lines(path: &PathBuf, lines: &Vec<String>) -> VecDeque<Token> {
                         );
                     }
 
-                    // Make sure the value is value hex
-                    if !value.is_hex() {
-                        report_error(
-                            "'$' Can only be used for hex literals!",
-                            path,
-                            lines,
-                            line_number,
-                            token_col_start,
-                            col_number,
-                        );
-                    }
-
-                    // Push hex token
                     tokens.push_back(Token {
-                        line_number: line_number as u32,
+                        line_number,
                         column_start: token_col_start,
                         column_end: col_number,
                         value: full_value,
-                        token_type: TokenType::Hex(value),
+                        token_type: match kind {
+                            "hex" => TokenType::Hex(digits),
+                            _ => TokenType::Binary(digits),
+                        },
                     });
+
+                    continue;
                 }
-                (_, _, true) => {
-                    let literal = read_to_chars(vec![' ', ',', ';', '(', ')', '[', ']'], &mut col_number, &mut chars);
 
-                    let value = match literal {
-                        Some(val) => val,
-                        None => "".to_owned(),
-                    };
+                // The first character can be a digit from another script, which is not valid either
+                if !full_value.is_numeric() {
+                    return report_error(
+                        Code::InvalidNumber,
+                        "Unexpected non-numeric characters in decimal literal!",
+                        path,
+                        lines,
+                        line_number,
+                        token_col_start,
+                        col_number,
+                    );
+                }
 
-                    let full_value = format!("{first_char}{value}");
+                // Push decimal token
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: full_value.clone(),
+                    token_type: TokenType::Decimal(full_value),
+                });
+            }
+            // Open Bracket
+            ('[', _, _) => {
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: first_char.to_string(),
+                    token_type: TokenType::OpenBracket,
+                });
+            } // Close Bracket
+            (']', _, _) => {
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: first_char.to_string(),
+                    token_type: TokenType::CloseBracket,
+                });
+            } // Open Parenthesis
+            ('(', _, _) => {
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: first_char.to_string(),
+                    token_type: TokenType::OpenParenthesis,
+                });
+            } // Close Parenthesis
+            (')', _, _) => {
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: first_char.to_string(),
+                    token_type: TokenType::CloseParenthesis,
+                });
+            }
+            // Expression operator
+            ('=' | '!' | '<' | '>' | '&' | '|' | '+' | '-' | '*' | '/', _, _) => {
+                let mut operator = first_char.to_string();
+
+                // Check for a two character operator
+                if let Some(&next_char) = chars.front() {
+                    if matches!(
+                        (first_char, next_char),
+                        ('=', '=') | ('!', '=') | ('<', '=') | ('>', '=') | ('&', '&') | ('|', '|')
+                    ) {
+                        operator.push(chars.pop_front().unwrap());
+                        col_number += 1;
+                    }
+                }
 
-                    if !value.is_numeric() {
-                        report_error(
-                            "Unexpected non-numeric characters in decimal literal!",
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: operator.clone(),
+                    token_type: TokenType::Operator(operator),
+                });
+            }
+            // `:+` and `:-` refer to the next and previous anonymous label
+            (':', _, _) if matches!(chars.front(), Some('+' | '-')) => {
+                let value = format!("{first_char}{}", chars.pop_front().unwrap());
+                col_number += 1;
+
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: value.clone(),
+                    token_type: TokenType::Identifier(value),
+                });
+            }
+            // Anonymous label, `:` or `@@` at the start of a line
+            (':' | '@', _, _) if tokens.back().is_none_or(|token| token.line_number != line_number) => {
+                if first_char == '@' {
+                    if chars.front() != Some(&'@') {
+                        return report_error(
+                            Code::InvalidLabelName,
+                            "Expected `@@` for an anonymous label!",
                             path,
                             lines,
                             line_number,
@@ -437,67 +784,140 @@ pub fn tokenize_lines(path: &PathBuf, lines: &Vec<String>) -> VecDeque<Token> {
                         );
                     }
 
-                    // Push decimal token
-                    tokens.push_back(Token {
-                        line_number: line_number as u32,
-                        column_start: token_col_start,
-                        column_end: col_number,
-                        value: full_value.clone(),
-                        token_type: TokenType::Decimal(full_value),
-                    });
+                    chars.pop_front();
+                    col_number += 1;
                 }
-                // Open Bracket
-                ('[', _, _) => {
-                    tokens.push_back(Token {
-                        line_number: line_number as u32,
-                        column_start: token_col_start,
-                        column_end: col_number,
-                        value: first_char.to_string(),
-                        token_type: TokenType::OpenBracket,
-                    });
-                } // Close Bracket
-                (']', _, _) => {
-                    tokens.push_back(Token {
-                        line_number: line_number as u32,
-                        column_start: token_col_start,
-                        column_end: col_number,
-                        value: first_char.to_string(),
-                        token_type: TokenType::CloseBracket,
-                    });
-                } // Open Parenthesis
-                ('(', _, _) => {
-                    tokens.push_back(Token {
-                        line_number: line_number as u32,
-                        column_start: token_col_start,
-                        column_end: col_number,
-                        value: first_char.to_string(),
-                        token_type: TokenType::OpenParenthesis,
-                    });
-                } // Close Parenthesis
-                (')', _, _) => {
-                    tokens.push_back(Token {
-                        line_number: line_number as u32,
-                        column_start: token_col_start,
-                        column_end: col_number,
-                        value: first_char.to_string(),
-                        token_type: TokenType::CloseParenthesis,
-                    });
-                }
-                _ => {
-                    report_error(
-                        format!("Unexpected value '{first_char}' at start of token").as_str(),
+
+                if !matches!(chars.front(), None | Some(' ' | '\t' | ';')) {
+                    return report_error_with_note(
+                        Code::InvalidLabelName,
+                        "Expected whitespace after anonymous label!",
+                        Some("Anonymous labels are a `:` or `@@` on their own, referenced with `:+` for the next one or `:-` for the previous one"),
                         path,
                         lines,
                         line_number,
                         token_col_start,
-                        col_number,
+                        col_number + 1,
                     );
                 }
+
+                tokens.push_back(Token {
+                    line_number,
+                    column_start: token_col_start,
+                    column_end: col_number,
+                    value: if first_char == '@' { "@@" } else { ":" }.to_owned(),
+                    token_type: TokenType::Label(":".to_owned()),
+                });
+            }
+            _ => {
+                return report_error(
+                    Code::InvalidToken,
+                    format!("Unexpected value '{first_char}' at start of token").as_str(),
+                    path,
+                    lines,
+                    line_number,
+                    token_col_start,
+                    col_number,
+                );
             }
         }
     }
 
-    tokens
+    Ok(())
+}
+
+/**
+ * Lowercase the names of instructions, directives and builtin functions, so they are matched regardless of case.
+ *
+ * Labels and other identifiers keep their case, except the instruction after `times <count>`.
+ */
+pub fn fold_case(tokens: &mut VecDeque<Token>) {
+    for index in 0..tokens.len() {
+        let is_repeated_instruction = index >= 2
+            && tokens[index - 2].line_number == tokens[index].line_number
+            && matches!(&tokens[index - 2].token_type, TokenType::Instruction(name) if name == "times");
+
+        let is_call = tokens
+            .get(index + 1)
+            .is_some_and(|next| next.token_type == TokenType::OpenParenthesis);
+
+        match &mut tokens[index].token_type {
+            TokenType::Instruction(name) | TokenType::Directive(name) => name.make_ascii_lowercase(),
+            TokenType::Identifier(name) if is_repeated_instruction => name.make_ascii_lowercase(),
+            // A label can have the name of a function, but only a function is called
+            TokenType::Identifier(name) if is_call && expr::FUNCTIONS.iter().any(|function| name.eq_ignore_ascii_case(function)) => {
+                name.make_ascii_lowercase()
+            }
+            _ => (),
+        }
+    }
+}
+
+/**
+ * Replace every character of a `/* ... */` block comment with a space, so the lines can be read
+ * as code without moving any tokens to a different column.
+ *
+ * Block comments can span lines, and are not started inside of strings or `;` line comments.
+ */
+pub fn mask_block_comments(path: &Path, lines: &[String]) -> diagnostic::Result<Vec<String>> {
+    let mut masked = Vec::with_capacity(lines.len());
+
+    // Line and column of the `/*` that opened the comment being read
+    let mut comment_start: Option<(u32, u32)> = None;
+
+    for (line_number, line) in lines.iter().enumerate() {
+        let mut result = String::with_capacity(line.len());
+        let mut chars = line.chars().enumerate().peekable();
+        let mut quote = None;
+
+        while let Some((column, c)) = chars.next() {
+            if comment_start.is_some() {
+                if c == '*' && chars.next_if(|&(_, next)| next == '/').is_some() {
+                    result.push(' ');
+                    comment_start = None;
+                }
+
+                result.push(' ');
+                continue;
+            }
+
+            match (quote, c) {
+                (Some(open), _) if c == open => quote = None,
+                (Some(_), _) => (),
+                (None, '"' | '\'') => quote = Some(c),
+                (None, ';') => {
+                    result.push(c);
+                    result.extend(chars.by_ref().map(|(_, c)| c));
+                    break;
+                }
+                (None, '/') if chars.next_if(|&(_, next)| next == '*').is_some() => {
+                    comment_start = Some((line_number as u32, column as u32));
+                    result.push_str("  ");
+                    continue;
+                }
+                _ => (),
+            }
+
+            result.push(c);
+        }
+
+        masked.push(result);
+    }
+
+    if let Some((line_number, column)) = comment_start {
+        return report_error_with_note(
+            Code::UnclosedComment,
+            "Block comment is never closed!",
+            Some("Block comments end with `*/`, everything after the `/*` was read as part of the comment"),
+            path,
+            lines,
+            line_number,
+            column,
+            column + 2,
+        );
+    }
+
+    Ok(masked)
 }
 
 fn read_to_char_inclusive(
@@ -514,8 +934,9 @@ fn read_to_char_inclusive(
     while !chars.is_empty() {
         if *chars.front().unwrap() == character {
             string.push_str(chars.pop_front().unwrap().to_string().as_str());
+            *col_number += 1;
 
-            return if string.len() > 0 { Some(string) } else { None };
+            return if !string.is_empty() { Some(string) } else { None };
         }
 
         let character = chars.pop_front().unwrap();
@@ -525,7 +946,8 @@ fn read_to_char_inclusive(
         string.push_str(character.to_string().as_str());
     }
 
-    Some(string)
+    // The line ended before the closing character
+    None
 }
 
 fn read_to_chars(
@@ -541,7 +963,7 @@ fn read_to_chars(
 
     while !chars.is_empty() {
         if characters.contains(chars.front().unwrap()) {
-            return if string.len() > 0 { Some(string) } else { None };
+            return if !string.is_empty() { Some(string) } else { None };
         }
 
         let character = chars.pop_front().unwrap();
@@ -554,18 +976,6 @@ fn read_to_chars(
     Some(string)
 }
 
-trait Extractable {
-    fn extract_range(&self, start: u32, end: u32) -> Self;
-}
-
-impl Extractable for String {
-    fn extract_range(&self, start: u32, end: u32) -> String {
-        let string = &*self;
-        let slice = &string[start as usize..end as usize];
-        slice.to_owned()
-    }
-}
-
 trait Alphabetic {
     fn is_alphanumeric(&self) -> bool;
     fn is_numeric(&self) -> bool;